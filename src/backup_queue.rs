@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+
+/// How many distinct repos can wait for a backup before the watcher blocks.
+pub const BACKUP_QUEUE_CAPACITY: usize = 64;
+
+/// Sending half of the pipeline between the watcher, which notices changes, and the worker,
+/// which makes the snapshots.
+///
+/// The channel is bounded so a slow worker applies backpressure instead of letting a queue grow
+/// without limit. On top of that, requests are coalesced: while a repo is already waiting for a
+/// backup, more requests for it are dropped since the one pending backup will pick up all of
+/// its changes anyway.
+pub struct BackupQueue {
+    sender: SyncSender<PathBuf>,
+    pending: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+/// Receiving half of the pipeline, see `BackupQueue`.
+pub struct BackupReceiver {
+    receiver: Receiver<PathBuf>,
+    pending: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+pub fn backup_queue(capacity: usize) -> (BackupQueue, BackupReceiver) {
+    let (sender, receiver) = sync_channel(capacity);
+    let pending = Arc::new(Mutex::new(HashSet::new()));
+    (
+        BackupQueue {
+            sender,
+            pending: Arc::clone(&pending),
+        },
+        BackupReceiver { receiver, pending },
+    )
+}
+
+impl BackupQueue {
+    /// Asks for a backup of `path`, blocking while the queue is full. Returns false when the
+    /// request was coalesced into one already pending, or when the worker has gone away.
+    pub fn request(&self, path: PathBuf) -> bool {
+        // The lock must be released before sending, the worker takes it on every receive.
+        if !self.pending.lock().unwrap().insert(path.clone()) {
+            return false;
+        }
+        if self.sender.send(path.clone()).is_err() {
+            self.pending.lock().unwrap().remove(&path);
+            return false;
+        }
+        true
+    }
+}
+
+impl BackupReceiver {
+    /// Waits for the next backup request. Returns None once every `BackupQueue` is dropped.
+    pub fn recv(&self) -> Option<PathBuf> {
+        let path = self.receiver.recv().ok()?;
+        self.received(path)
+    }
+
+    /// Takes the next backup request, if there is one, without waiting.
+    pub fn try_recv(&self) -> Option<PathBuf> {
+        let path = self.receiver.try_recv().ok()?;
+        self.received(path)
+    }
+
+    /// Once a request leaves the channel, changes that happen after this point need a new
+    /// backup, so new requests for the same repo are accepted again.
+    fn received(&self, path: PathBuf) -> Option<PathBuf> {
+        self.pending.lock().unwrap().remove(&path);
+        Some(path)
+    }
+}
@@ -0,0 +1,526 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Local, LocalResult, TimeZone, Timelike};
+use git2::{BranchType, Commit, Oid, Repository, Sort};
+use serde::{Deserialize, Serialize};
+
+use crate::snapshots::{BackupNote, BACKUP_MESSAGE, NOTES_REF, STASH_REF};
+
+/// A single dura auto-backup commit found while walking a repository's history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    pub oid: Oid,
+    /// Commit time, in seconds since the unix epoch
+    pub time: i64,
+    /// The ref this backup is attributed to. See `find_backups` for how it's chosen.
+    pub ref_name: String,
+}
+
+/// How far in the future a backup can be dated before it's considered implausible, to allow for
+/// small differences between machines' clocks.
+pub const FUTURE_TOLERANCE_SECS: i64 = 5 * 60;
+
+/// Aggregate of a repo's backups, see `summarize`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackupSummary {
+    pub count: usize,
+    pub latest_id: Option<String>,
+    /// Time of the newest backup, or 0 when there are none
+    pub latest_time: i64,
+    /// Backups dated more than `FUTURE_TOLERANCE_SECS` after `now`
+    pub future_dated: usize,
+    /// Backups made since local midnight
+    pub today: usize,
+}
+
+impl BackupSummary {
+    /// The newest backup and its time, if there's any.
+    pub fn latest(&self) -> Option<(Oid, i64)> {
+        let oid = Oid::from_str(self.latest_id.as_deref()?).ok()?;
+        Some((oid, self.latest_time))
+    }
+}
+
+/// Start of `now`'s day in local time, in seconds since the unix epoch.
+pub fn local_midnight(now: DateTime<Local>) -> i64 {
+    let midnight = now.naive_local().date().and_hms(0, 0, 0);
+    // Midnight can be skipped or repeated by a DST change
+    match Local.from_local_datetime(&midnight) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => time.timestamp(),
+        LocalResult::None => now.timestamp() - i64::from(now.num_seconds_from_midnight()),
+    }
+}
+
+/// Summarizes `backups` as of `now`.
+///
+/// A backup is only future-dated if the clock was skewed when it was made. Since the newest
+/// backup wins, one future-dated backup would otherwise mask every real backup made after it.
+/// With `clamp` set, backup times are clamped to `now` before picking the newest one. The
+/// tradeoff is that clamped times no longer show how far off the clock was, and clamped
+/// backups compare as equally new.
+pub fn summarize(backups: &[Backup], now: DateTime<Local>, clamp: bool) -> BackupSummary {
+    let midnight = local_midnight(now);
+    let now = now.timestamp();
+    let mut summary = BackupSummary {
+        count: backups.len(),
+        ..Default::default()
+    };
+    for backup in backups {
+        if backup.time >= midnight {
+            summary.today += 1;
+        }
+        if backup.time > now + FUTURE_TOLERANCE_SECS {
+            summary.future_dated += 1;
+        }
+        let time = if clamp {
+            backup.time.min(now)
+        } else {
+            backup.time
+        };
+        if time > summary.latest_time {
+            summary.latest_time = time;
+            summary.latest_id = Some(backup.oid.to_string());
+        }
+    }
+    summary
+}
+
+/// Backups bucketed by age, see `age_histogram`. Each backup lands in exactly one bucket: the
+/// first of these it fits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AgeHistogram {
+    /// Made less than an hour ago. Future-dated backups count here too.
+    pub last_hour: usize,
+    /// Made since local midnight
+    pub today: usize,
+    /// Made since local midnight six days ago, i.e. over the last seven calendar days
+    pub this_week: usize,
+    pub older: usize,
+}
+
+impl AgeHistogram {
+    /// `(label, count)` for each bucket, newest first.
+    pub fn buckets(&self) -> [(&'static str, usize); 4] {
+        [
+            ("last hour", self.last_hour),
+            ("today", self.today),
+            ("this week", self.this_week),
+            ("older", self.older),
+        ]
+    }
+}
+
+/// How many of `backups` each ref has, keyed by the ref's short name.
+pub fn count_by_branch(backups: &[Backup]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for backup in backups {
+        *counts.entry(backup.ref_name.clone()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Buckets `backups` by how long before `now` they were made. Day boundaries are local time.
+pub fn age_histogram(backups: &[Backup], now: DateTime<Local>) -> AgeHistogram {
+    let hour_ago = now.timestamp() - 60 * 60;
+    let midnight = local_midnight(now);
+    let week_start = local_midnight(now - chrono::Duration::days(6));
+    let mut histogram = AgeHistogram::default();
+    for backup in backups {
+        if backup.time >= hour_ago {
+            histogram.last_hour += 1;
+        } else if backup.time >= midnight {
+            histogram.today += 1;
+        } else if backup.time >= week_start {
+            histogram.this_week += 1;
+        } else {
+            histogram.older += 1;
+        }
+    }
+    histogram
+}
+
+/// Which backups to keep when thinning out old ones, see `PrunePolicy::partition`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrunePolicy {
+    /// Keeps every backup from the last `keep_recent` calendar days, today included, and only
+    /// the first backup of each day before that.
+    OnePerDay { keep_recent: usize },
+    /// Keeps the newest `keep` backups
+    KeepLatest { keep: usize },
+}
+
+impl PrunePolicy {
+    /// Splits `backups` into the ones to keep and the ones to prune as of `now`, both in their
+    /// original order. Days are local dates of the commit times.
+    pub fn partition(
+        &self,
+        backups: &[Backup],
+        now: DateTime<Local>,
+    ) -> (Vec<Backup>, Vec<Backup>) {
+        match *self {
+            PrunePolicy::OnePerDay { keep_recent } => {
+                let recent_start = match keep_recent {
+                    0 => i64::MAX,
+                    days => local_midnight(now - chrono::Duration::days(days as i64 - 1)),
+                };
+                // the first backup of each older day
+                let mut firsts: HashMap<chrono::NaiveDate, &Backup> = HashMap::new();
+                for backup in backups.iter().filter(|backup| backup.time < recent_start) {
+                    let Some(date) = local_date(backup.time) else {
+                        continue;
+                    };
+                    let first = firsts.entry(date).or_insert(backup);
+                    if backup.time < first.time {
+                        *first = backup;
+                    }
+                }
+                let firsts: HashSet<Oid> = firsts.values().map(|backup| backup.oid).collect();
+                backups
+                    .iter()
+                    .cloned()
+                    .partition(|backup| backup.time >= recent_start || firsts.contains(&backup.oid))
+            }
+            PrunePolicy::KeepLatest { keep } => {
+                // newest first, and for equal times the one that comes first, which is the
+                // newer one in find_backups' topological order
+                let mut by_age: Vec<&Backup> = backups.iter().collect();
+                by_age.sort_by_key(|backup| std::cmp::Reverse(backup.time));
+                let kept: HashSet<Oid> = by_age.iter().take(keep).map(|b| b.oid).collect();
+                backups
+                    .iter()
+                    .cloned()
+                    .partition(|backup| kept.contains(&backup.oid))
+            }
+        }
+    }
+}
+
+/// Removes the backups on `dura/` branches that `policy` doesn't keep, and returns how many
+/// were removed. Backups only reachable from other refs are left alone, dura doesn't own those.
+///
+/// Each backup on a dura branch has the previous one as its parent, so the backups after a
+/// removed one are recreated on top of what's left, with the same trees, signatures, messages
+/// and notes. They get new ids then, the branch's newest backup included. A branch left with
+/// no backups is deleted.
+///
+/// `newest` is a backup that was just made. Commit times only have seconds, so it goes first
+/// among backups as old as it is, rather than being pruned for another made the same second.
+pub fn prune_backups(
+    repo: &Repository,
+    policy: PrunePolicy,
+    now: DateTime<Local>,
+    newest: Option<Oid>,
+) -> Result<usize, git2::Error> {
+    let mut on_dura_branches: Vec<Backup> = find_backups(repo)?
+        .into_iter()
+        .filter(|backup| backup.ref_name.starts_with("dura/"))
+        .collect();
+    // a stable sort, so the rest keeps find_backups' order
+    on_dura_branches.sort_by_key(|backup| Some(backup.oid) != newest);
+    let (_, pruned) = policy.partition(&on_dura_branches, now);
+    let pruned: HashSet<Oid> = pruned.iter().map(|backup| backup.oid).collect();
+    let branches: HashSet<&str> = on_dura_branches
+        .iter()
+        .filter(|backup| pruned.contains(&backup.oid))
+        .map(|backup| backup.ref_name.as_str())
+        .collect();
+
+    let noted = noted_commits(repo);
+    let mut removed = 0;
+    for name in branches {
+        let mut branch = repo.find_branch(name, BranchType::Local)?;
+        let Some(tip) = branch.get().target() else {
+            continue;
+        };
+        // the branch's backups, oldest first, and the commit they're based on
+        let mut chain = Vec::new();
+        let mut base = None;
+        let mut next = Some(repo.find_commit(tip)?);
+        while let Some(commit) = next.take() {
+            if !(noted.contains(&commit.id()) || is_backup(&commit)) {
+                base = Some(commit);
+                break;
+            }
+            next = match commit.parent_count() {
+                0 => None,
+                _ => Some(commit.parent(0)?),
+            };
+            chain.push(commit);
+        }
+        chain.reverse();
+
+        let mut parent = base;
+        let mut rewriting = false;
+        for commit in &chain {
+            if pruned.contains(&commit.id()) {
+                rewriting = true;
+                removed += 1;
+                drop_note(repo, commit);
+                continue;
+            }
+            if !rewriting {
+                parent = Some(commit.clone());
+                continue;
+            }
+            let parents: Vec<&Commit> = parent.iter().collect();
+            let oid = repo.commit(
+                None,
+                &commit.author(),
+                &commit.committer(),
+                commit.message_raw().unwrap_or(BACKUP_MESSAGE),
+                &commit.tree()?,
+                &parents,
+            )?;
+            if let Ok(note) = repo.find_note(Some(NOTES_REF), commit.id()) {
+                if let Some(message) = note.message() {
+                    let sig = commit.committer();
+                    repo.note(&sig, &sig, Some(NOTES_REF), oid, message, true)?;
+                }
+                drop_note(repo, commit);
+            }
+            parent = Some(repo.find_commit(oid)?);
+        }
+
+        let kept_any = chain.iter().any(|commit| !pruned.contains(&commit.id()));
+        match parent {
+            Some(new_tip) if rewriting && kept_any => {
+                branch
+                    .get_mut()
+                    .set_target(new_tip.id(), "dura: prune backups")?;
+            }
+            _ if rewriting => branch.delete()?,
+            _ => (),
+        }
+    }
+    Ok(removed)
+}
+
+/// Removes the note of `commit` in `NOTES_REF`, if it has one.
+fn drop_note(repo: &Repository, commit: &Commit) {
+    let sig = commit.committer();
+    let _ = repo.note_delete(commit.id(), Some(NOTES_REF), &sig, &sig);
+}
+
+fn local_date(time: i64) -> Option<chrono::NaiveDate> {
+    match Local.timestamp_opt(time, 0) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => {
+            Some(time.naive_local().date())
+        }
+        LocalResult::None => None,
+    }
+}
+
+/// The tip of the dura branch that was committed to last, and its commit time. Unlike
+/// `find_backups`, this only reads the branches, not the history behind them.
+pub fn newest_dura_tip(repo: &Repository) -> Option<(Oid, i64)> {
+    repo.branches(Some(BranchType::Local))
+        .ok()?
+        .flatten()
+        .filter(|(branch, _)| {
+            branch
+                .name()
+                .ok()
+                .flatten()
+                .is_some_and(|name| name.starts_with("dura/"))
+        })
+        .filter_map(|(branch, _)| branch.get().peel_to_commit().ok())
+        .map(|commit| (commit.id(), commit.time().seconds()))
+        .max_by_key(|(_, time)| *time)
+}
+
+/// The note dura attached to the backup `oid`, if it has one that parses.
+pub fn backup_note(repo: &Repository, oid: Oid) -> Option<BackupNote> {
+    let note = repo.find_note(Some(NOTES_REF), oid).ok()?;
+    serde_json::from_str(note.message()?).ok()
+}
+
+/// Commits that have a note in `NOTES_REF`.
+fn noted_commits(repo: &Repository) -> HashSet<Oid> {
+    match repo.notes(Some(NOTES_REF)) {
+        Ok(notes) => notes.flatten().map(|(_, annotated)| annotated).collect(),
+        // no notes ref yet
+        Err(_) => HashSet::new(),
+    }
+}
+
+/// Whether `commit`'s summary has dura's backup message. See `find_backups` for backups
+/// recognized by their note instead.
+pub fn is_backup(commit: &Commit) -> bool {
+    commit.summary().is_some_and(is_backup_summary)
+}
+
+/// Whether a commit summary ends in dura's backup message. Trailing whitespace is ignored,
+/// since a commit template or a tool rewriting the message may add some.
+pub fn is_backup_summary(summary: &str) -> bool {
+    summary.trim_end().ends_with(BACKUP_MESSAGE)
+}
+
+/// Where `count_since` left off: the tip of each ref it walked, by the ref's shorthand, and how
+/// many backups they reach. Kept in the runtime lock, see `Config::backup_count`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountCursor {
+    #[serde(default)]
+    pub refs: BTreeMap<String, String>,
+    pub count: usize,
+}
+
+/// How `count_since` got to its count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountWalk {
+    /// Only commits that the cursor's tips don't reach were walked
+    Incremental,
+    /// There was no cursor, or history was rewritten since, so everything was walked
+    Full,
+}
+
+/// Counts the backups reachable from any ref, like `find_backups` does, but only walks what's
+/// new since `cursor` was made. Each ref in the cursor has to still point to its old tip, or
+/// to a descendant of it. Otherwise, e.g. because a branch was deleted or backups were
+/// pruned, backups may have gone away with it, so everything is counted again.
+pub fn count_since(
+    repo: &Repository,
+    cursor: Option<&CountCursor>,
+) -> Result<(CountCursor, CountWalk), git2::Error> {
+    let tips: BTreeMap<String, Oid> = ref_tips(repo)?.into_iter().collect();
+    // a cursor without refs is from before they were kept, and can't be checked
+    let known: Option<Vec<Oid>> = match cursor {
+        Some(cursor) if !cursor.refs.is_empty() => cursor
+            .refs
+            .iter()
+            .map(|(name, old)| kept_tip(repo, &tips, name, old))
+            .collect(),
+        _ => None,
+    };
+
+    let mut walk = repo.revwalk()?;
+    for tip in tips.values() {
+        walk.push(*tip)?;
+    }
+    let (mut count, how) = match (&known, cursor) {
+        (Some(known), Some(cursor)) => {
+            for old in known {
+                walk.hide(*old)?;
+            }
+            (cursor.count, CountWalk::Incremental)
+        }
+        _ => (0, CountWalk::Full),
+    };
+    let noted = noted_commits(repo);
+    for oid in walk {
+        let oid = oid?;
+        if noted.contains(&oid) || is_backup(&repo.find_commit(oid)?) {
+            count += 1;
+        }
+    }
+
+    let refs = tips
+        .into_iter()
+        .map(|(name, tip)| (name, tip.to_string()))
+        .collect();
+    Ok((CountCursor { refs, count }, how))
+}
+
+/// `old`, if the ref `name` still points to it or to a descendant of it.
+fn kept_tip(repo: &Repository, tips: &BTreeMap<String, Oid>, name: &str, old: &str) -> Option<Oid> {
+    let old = Oid::from_str(old).ok()?;
+    let tip = *tips.get(name)?;
+    (tip == old || repo.graph_descendant_of(tip, old).unwrap_or(false)).then_some(old)
+}
+
+/// Every ref's shorthand and the commit it points to, plus HEAD when it's detached. Notes refs
+/// are left out, they don't point to commits of the repo's history, and so is the stash.
+fn ref_tips(repo: &Repository) -> Result<Vec<(String, Oid)>, git2::Error> {
+    let mut tips: Vec<(String, Oid)> = Vec::new();
+    for reference in repo.references()?.flatten() {
+        // stash entries are found through the reflog instead, see `stash_backups`
+        if reference.is_note() || reference.name() == Some(STASH_REF) {
+            continue;
+        }
+        if let (Some(name), Ok(commit)) = (reference.shorthand(), reference.peel_to_commit()) {
+            tips.push((name.to_string(), commit.id()));
+        }
+    }
+    if repo.head_detached().unwrap_or(false) {
+        if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+            tips.push(("HEAD".to_string(), commit.id()));
+        }
+    }
+    Ok(tips)
+}
+
+/// Dura's entries in the repo's stash, see `BackupStrategy::Stash`, attributed to e.g.
+/// `stash@{0}`. Entries the user stashed are left out.
+pub fn stash_backups(repo: &Repository) -> Result<Vec<Backup>, git2::Error> {
+    let reflog = match repo.reflog(STASH_REF) {
+        Ok(reflog) => reflog,
+        // nothing stashed yet
+        Err(_) => return Ok(Vec::new()),
+    };
+    let mut backups = Vec::new();
+    for (index, entry) in reflog.iter().enumerate() {
+        if !entry.message().is_some_and(is_backup_summary) {
+            continue;
+        }
+        if let Ok(commit) = repo.find_commit(entry.id_new()) {
+            backups.push(Backup {
+                oid: commit.id(),
+                time: commit.time().seconds(),
+                ref_name: format!("stash@{{{index}}}"),
+            });
+        }
+    }
+    Ok(backups)
+}
+
+/// Finds every dura backup reachable from any ref (like `git log --all`) in a single revwalk.
+///
+/// A commit is a backup when it has a note in `NOTES_REF`, or else when `is_backup` says so by
+/// its summary. Notes refs themselves aren't walked.
+///
+/// A commit can be reachable from more than one ref, e.g. when a dura branch was merged into a
+/// regular branch. Every backup is counted exactly once and attributed to a single ref:
+///  1. `dura/` branches win over every other ref
+///  2. ties are broken by ref name, in lexical order
+pub fn find_backups(repo: &Repository) -> Result<Vec<Backup>, git2::Error> {
+    let mut tips = ref_tips(repo)?;
+    // After sorting, a lower index is a better owner
+    tips.sort_by(|(a, _), (b, _)| {
+        (!a.starts_with("dura/"), a.as_str()).cmp(&(!b.starts_with("dura/"), b.as_str()))
+    });
+
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL)?;
+
+    // Best owner seen so far for each commit waiting to be visited
+    let mut owners: HashMap<Oid, usize> = HashMap::new();
+    fn claim(owners: &mut HashMap<Oid, usize>, oid: Oid, owner: usize) {
+        let entry = owners.entry(oid).or_insert(owner);
+        *entry = owner.min(*entry);
+    }
+
+    for (index, (_, oid)) in tips.iter().enumerate() {
+        walk.push(*oid)?;
+        claim(&mut owners, *oid, index);
+    }
+
+    // Topological order visits every child before its parents, so a commit's owner is final by
+    // the time the walk reaches it.
+    let noted = noted_commits(repo);
+    let mut backups = Vec::new();
+    for oid in walk {
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+        let owner = owners.remove(&oid).unwrap_or_default();
+        for parent in commit.parent_ids() {
+            claim(&mut owners, parent, owner);
+        }
+        if noted.contains(&oid) || is_backup(&commit) {
+            backups.push(Backup {
+                oid,
+                time: commit.time().seconds(),
+                ref_name: tips[owner].0.clone(),
+            });
+        }
+    }
+    Ok(backups)
+}
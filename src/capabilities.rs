@@ -0,0 +1,126 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+/// What this dura build can do, for front-ends that have to work with older and newer
+/// versions. Printed as JSON by `dura capabilities`; keys are only ever added.
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct Capabilities {
+    pub dura_version: String,
+    pub libgit2_version: String,
+    pub features: Features,
+}
+
+/// Features compiled into this build.
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct Features {
+    /// `info --format json` and `doctor --json`
+    pub json_output: bool,
+    /// `info --format porcelain`
+    pub porcelain_output: bool,
+    /// Backup metadata under `snapshots::NOTES_REF`
+    pub backup_notes: bool,
+    /// Backups kept in a separate bare repo, see `WatchConfig::mirror_path`
+    pub mirror_repos: bool,
+    /// Signed backup commits
+    pub signing: bool,
+    /// libgit2 was built with HTTPS support
+    pub https: bool,
+    /// libgit2 was built with SSH support
+    pub ssh: bool,
+}
+
+/// How git reaches a remote, see `Transport::for_url`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// A path, or a file:// URL, which every libgit2 build can reach
+    Local,
+    Https,
+    Ssh,
+}
+
+impl Transport {
+    /// The transport git would use for `url`, which may also be scp-like, e.g.
+    /// `git@github.com:tkellogg/dura.git`.
+    pub fn for_url(url: &str) -> Transport {
+        let lower = url.to_ascii_lowercase();
+        if lower.starts_with("https://") || lower.starts_with("http://") {
+            Transport::Https
+        } else if lower.starts_with("ssh://") || lower.starts_with("git+ssh://") {
+            Transport::Ssh
+        } else if lower.contains("://") {
+            Transport::Local
+        } else {
+            // scp-like syntax has a colon before any slash, unlike e.g. C:\ paths
+            match url.split_once(':') {
+                Some((host, _)) if host.len() > 1 && !host.contains('/') => Transport::Ssh,
+                _ => Transport::Local,
+            }
+        }
+    }
+}
+
+/// A feature an operation needs and this build's libgit2 lacks, reported before the operation
+/// is attempted, instead of whatever libgit2 fails with halfway through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingFeature {
+    Https,
+    Ssh,
+    Signing,
+}
+
+impl fmt::Display for MissingFeature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MissingFeature::Https => f.write_str("this dura build's libgit2 lacks HTTPS support"),
+            MissingFeature::Ssh => f.write_str("this dura build's libgit2 lacks SSH support"),
+            MissingFeature::Signing => f.write_str("this dura build can't sign commits"),
+        }
+    }
+}
+
+impl std::error::Error for MissingFeature {}
+
+impl Features {
+    /// Whether `url` can be reached with this build, to check before e.g. pushing to it.
+    pub fn require_transport(&self, url: &str) -> Result<(), MissingFeature> {
+        match Transport::for_url(url) {
+            Transport::Https if !self.https => Err(MissingFeature::Https),
+            Transport::Ssh if !self.ssh => Err(MissingFeature::Ssh),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn require_signing(&self) -> Result<(), MissingFeature> {
+        if self.signing {
+            Ok(())
+        } else {
+            Err(MissingFeature::Signing)
+        }
+    }
+}
+
+/// The features of this build. libgit2 is only asked once, they can't change while dura runs.
+pub fn features() -> &'static Features {
+    static FEATURES: OnceLock<Features> = OnceLock::new();
+    FEATURES.get_or_init(|| capabilities().features)
+}
+
+pub fn capabilities() -> Capabilities {
+    let version = git2::Version::get();
+    let (major, minor, rev) = version.libgit2_version();
+    Capabilities {
+        dura_version: env!("CARGO_PKG_VERSION").to_string(),
+        libgit2_version: format!("{major}.{minor}.{rev}"),
+        features: Features {
+            json_output: true,
+            porcelain_output: true,
+            backup_notes: true,
+            mirror_repos: true,
+            signing: false,
+            https: version.https(),
+            ssh: version.ssh(),
+        },
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::SystemTime;
+
+/// Where dura gets the time its backup commits are signed with. Backups use `SystemClock`;
+/// a `FixedClock` makes them reproducible, e.g. in tests.
+pub trait Clock {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system's wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that's always at the time it was made with.
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Component, Path};
+use std::rc::Rc;
+
+use glob::Pattern;
+
+use crate::config::WatchConfig;
+
+/// The include and exclude patterns of a `WatchConfig`, compiled for matching directories
+/// while scanning a watch. Each pattern is matched a path component at a time, so `*` never
+/// spans a `/`, and a pattern without wildcards means what it always has: that directory and
+/// everything under it. Patterns are always relative to the watched directory: `watch` and
+/// `Config::add_exclude` reject absolute ones, and a leading `/` in the config file is ignored.
+#[derive(Debug)]
+pub struct CompiledWatch {
+    include: Vec<Vec<Pattern>>,
+    exclude: Vec<Vec<Pattern>>,
+}
+
+type PatternSources = (Vec<String>, Vec<String>);
+
+thread_local! {
+    /// Compiled patterns by their source, since the config is reloaded on every poll but
+    /// rarely changes.
+    static CACHE: RefCell<HashMap<PatternSources, Rc<CompiledWatch>>> =
+        RefCell::new(HashMap::new());
+}
+
+impl CompiledWatch {
+    pub fn new(watch_config: &WatchConfig) -> Self {
+        Self {
+            include: watch_config.include.iter().map(|p| compile(p)).collect(),
+            exclude: watch_config.exclude.iter().map(|p| compile(p)).collect(),
+        }
+    }
+
+    /// The patterns of `watch_config`, compiled only if no config with the same patterns was
+    /// compiled before on this thread.
+    pub fn cached(watch_config: &WatchConfig) -> Rc<Self> {
+        let sources = (watch_config.include.clone(), watch_config.exclude.clone());
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            Rc::clone(
+                cache
+                    .entry(sources)
+                    .or_insert_with(|| Rc::new(Self::new(watch_config))),
+            )
+        })
+    }
+
+    /// Whether the directory at `relative`, relative to the watched dir, is in or under an
+    /// excluded one.
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        let components = normal_components(relative);
+        self.exclude.iter().any(|pattern| {
+            pattern.len() <= components.len()
+                && pattern.iter().zip(&components).all(|(p, c)| p.matches(c))
+        })
+    }
+
+    /// Whether the directory at `relative` is included, or has to be scanned to get to an
+    /// included one.
+    pub fn leads_to_include(&self, relative: &Path) -> bool {
+        let components = normal_components(relative);
+        self.include.iter().any(|pattern| {
+            components.len() <= pattern.len()
+                && pattern.iter().zip(&components).all(|(p, c)| p.matches(c))
+        })
+    }
+
+    /// Whether a scan of the watch gets to the directory at `relative`: it isn't excluded, or an
+    /// include leads to it anyway.
+    pub fn admits(&self, relative: &Path) -> bool {
+        !(self.has_excludes() && self.is_excluded(relative))
+            || (self.has_includes() && self.leads_to_include(relative))
+    }
+
+    /// Whether the file at `relative` is left out of backups: it's under an excluded directory
+    /// and no include covers it.
+    pub fn excludes_file(&self, relative: &Path) -> bool {
+        let components = normal_components(relative);
+        self.is_excluded(relative)
+            && !self.include.iter().any(|pattern| {
+                pattern.len() <= components.len()
+                    && pattern.iter().zip(&components).all(|(p, c)| p.matches(c))
+            })
+    }
+
+    pub fn has_excludes(&self) -> bool {
+        !self.exclude.is_empty()
+    }
+
+    pub fn has_includes(&self) -> bool {
+        !self.include.is_empty()
+    }
+}
+
+/// One pattern per component of `pattern`. A trailing `/**`, as used by presets, means the same
+/// as the bare directory. Components that aren't valid globs are matched literally.
+fn compile(pattern: &str) -> Vec<Pattern> {
+    let pattern = pattern.strip_suffix("/**").unwrap_or(pattern);
+    normal_components(Path::new(pattern))
+        .into_iter()
+        .map(|component| {
+            Pattern::new(&component)
+                .or_else(|_| Pattern::new(&Pattern::escape(&component)))
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn normal_components(path: &Path) -> Vec<String> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect()
+}
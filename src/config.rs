@@ -1,26 +1,136 @@
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::fs::{create_dir_all, File};
-use std::io::{BufReader, Read};
+use std::io::{self, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::{env, fs};
+use std::{env, fmt, fs};
 use std::time::{SystemTime, Duration};
 use chrono::{DateTime, Local};
-use git2::Repository;
-use std::io::IsTerminal;
+use git2::{Oid, Repository};
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
+use crate::backups::{self, AgeHistogram, BackupSummary};
+use crate::clock::SystemClock;
+use crate::compiled_watch::CompiledWatch;
 use crate::git_repo_iter::GitRepoIter;
-use crate::database::RuntimeLock;
+use crate::human_duration;
+use crate::output::{self, OutputFormat};
+use crate::database::{self, RuntimeLock, StatusCache};
+use crate::doctor::{DoctorReport, RepoCheck, ResolvedPaths, ResolvedRepoPaths, RuntimeLockCheck};
+use crate::preflight::{self, PreflightResult, PreflightTarget};
+use crate::poller;
+use crate::presets::{self, ProjectKind};
+use crate::read_only;
+use crate::repo_size::RepoSizes;
+use crate::repo_status::{self, RepoState, RepoStatus, Upstream};
+use crate::snapshots::{self, CaptureOutcome, HeadState};
+use crate::term::{self, Color};
 
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
-
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+/// Every field is written out when saved, so a config file shows exactly what's in effect. Each
+/// one also has a default, so configs from older versions that lack a field still load.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone)]
 pub struct WatchConfig {
+    #[serde(default)]
     pub include: Vec<String>,
+    #[serde(default)]
     pub exclude: Vec<String>,
+    /// How many directory levels below the watched path are searched for repos. 0 only backs up
+    /// the watched path itself, if it's a repo, 1 also its immediate children, and so on. 255
+    /// has no limit.
+    #[serde(default = "default_max_depth")]
+    #[schemars(range(min = 0, max = 255))]
     pub max_depth: u8,
+    #[serde(default)]
+    pub submodule_mode: SubmoduleMode,
+    /// Files bigger than this are left out of backups, so a stray multi-GB file doesn't bloat
+    /// the repo. There's no way to recover such a file from a backup.
+    #[serde(default)]
+    pub max_file_size_mb: Option<u64>,
+    /// When false, untracked files neither make the repo dirty nor get backed up, only changes
+    /// to files git already tracks do.
+    #[serde(default = "default_backup_untracked")]
+    pub backup_untracked: bool,
+    /// When true, files under `include` are backed up even if they're gitignored, e.g. a
+    /// `.env`. Backups are ordinary commits in the repo, so whatever secrets such files hold
+    /// end up in its object database, and go wherever the dura branches are pushed or copied.
+    #[serde(default)]
+    pub include_overrides_gitignore: bool,
+    /// Backups of a repo are at least this far apart, changes in between wait for the next
+    /// one. None backs up every change as it's seen.
+    #[serde(default, alias = "min_backup_interval_secs", with = "human_duration::option")]
+    #[schemars(schema_with = "human_duration::option::schema")]
+    pub min_backup_interval: Option<Duration>,
+    /// Bare repo to keep this watch's backups in, instead of branches in the repos themselves.
+    /// Created on the first backup if it doesn't exist. The repos only get the backups' objects.
+    #[serde(default)]
+    pub mirror_path: Option<PathBuf>,
+    /// Shell command to run after each successful backup. It runs through the user's shell
+    /// with DURA_REPO, DURA_COMMIT and DURA_FILES set, and whatever it does is the user's
+    /// responsibility.
+    #[serde(default)]
+    pub post_backup_hook: Option<String>,
+    /// Repos keep at most this many backups on their dura branches. After each backup, the
+    /// oldest ones beyond it are pruned, see `backups::prune_backups`. None, or 0, keeps them
+    /// all.
+    #[serde(default)]
+    pub max_backups: Option<usize>,
+    /// Repos keep every backup from this many calendar days, today included, and only the
+    /// first backup of each day before that. Thinned after each backup, like `max_backups`,
+    /// see `backups::PrunePolicy::OnePerDay`. 0 is taken as 1, so today's backups are always
+    /// kept. None keeps them all.
+    #[serde(default)]
+    pub thin_to_daily_after_days: Option<usize>,
+    /// Name to show the watch by instead of its path, e.g. "dotfiles". Also accepted by
+    /// `info --repo`. Unique among watches, see `Config::set_watch`.
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Branches dura backs up from, as globs, e.g. `feature/*`. While HEAD is on any other
+    /// branch, or detached, the repo isn't backed up. Empty backs up from every branch.
+    #[serde(default)]
+    pub only_branches: Vec<String>,
+    /// Remotes each new backup's branch is pushed to, by name or URL, e.g. a self-hosted mirror
+    /// next to GitHub. Authenticates like git does. A remote that fails doesn't keep the
+    /// backup from being pushed to the others, see `CaptureStatus::pushes`.
+    #[serde(default)]
+    pub push_remotes: Vec<String>,
+}
+
+fn default_max_depth() -> u8 {
+    255
+}
+
+fn default_backup_untracked() -> bool {
+    true
+}
+
+/// What a moved submodule pointer means to dura.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmoduleMode {
+    /// Submodules are left out entirely: a moved pointer doesn't make the repo dirty, and
+    /// backups keep the pointer of the commit they're based on
+    #[default]
+    Ignore,
+    /// A moved pointer is a change like any other, and backups record the submodule's current
+    /// commit
+    RecordPointer,
+}
+
+/// Where dura puts backups.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupStrategy {
+    /// Commits on a `dura/<commit at HEAD>` branch per base commit
+    #[default]
+    CommitToRef,
+    /// Entries in the repo's stash, which `git stash list` shows and `git stash apply` restores.
+    /// The working tree is left alone, unlike with `git stash`. Watches' `mirror_path`,
+    /// `max_backups` and `push_remotes`, and backup notes, don't apply to stashes.
+    Stash,
 }
 
 impl WatchConfig {
@@ -28,277 +138,2084 @@ impl WatchConfig {
         Self {
             include: vec![],
             exclude: vec![],
-            max_depth: 255,
+            max_depth: default_max_depth(),
+            submodule_mode: SubmoduleMode::default(),
+            max_file_size_mb: None,
+            backup_untracked: default_backup_untracked(),
+            include_overrides_gitignore: false,
+            min_backup_interval: None,
+            mirror_path: None,
+            post_backup_hook: None,
+            max_backups: None,
+            thin_to_daily_after_days: None,
+            label: None,
+            only_branches: vec![],
+            push_remotes: vec![],
+        }
+    }
+
+    /// A watch that excludes the usual build output and dependency directories of `kind`.
+    pub fn preset(kind: ProjectKind) -> Self {
+        Self {
+            exclude: kind.excludes().iter().map(|e| e.to_string()).collect(),
+            ..Self::new()
+        }
+    }
+
+    /// Whether repos of this watch are backed up while HEAD is on `branch`, see
+    /// `only_branches`. None is a detached HEAD.
+    pub fn backs_up_branch(&self, branch: Option<&str>) -> bool {
+        if self.only_branches.is_empty() {
+            return true;
+        }
+        let Some(branch) = branch else {
+            return false;
+        };
+        self.only_branches.iter().any(|pattern| match glob::Pattern::new(pattern) {
+            Ok(pattern) => pattern.matches_with(branch, PATTERN_MATCH_OPTIONS),
+            Err(_) => pattern == branch,
+        })
+    }
+
+    /// How git's status is read to tell whether a repo of this watch is dirty, see
+    /// `Config::dirty_file_count`.
+    pub(crate) fn dirty_status_options(&self) -> git2::StatusOptions {
+        let mut options = git2::StatusOptions::new();
+        options
+            .include_untracked(self.backup_untracked)
+            .include_ignored(false)
+            .include_unmodified(false)
+            .exclude_submodules(self.submodule_mode == SubmoduleMode::Ignore);
+        options
+    }
+
+    /// Fluent alternative to building a `WatchConfig` by hand, starting from `WatchConfig::new`.
+    pub fn builder() -> WatchConfigBuilder {
+        WatchConfigBuilder::from(Self::new())
+    }
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig::new()
+    }
+}
+
+/// Builds a `WatchConfig`, see `WatchConfig::builder`. Use `WatchConfigBuilder::from` to start
+/// from another config instead, e.g. a preset.
+#[derive(Debug, Clone)]
+pub struct WatchConfigBuilder {
+    config: WatchConfig,
+}
+
+impl WatchConfigBuilder {
+    /// Appends to the include patterns
+    pub fn include<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.include.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    /// Appends to the exclude patterns
+    pub fn exclude<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.exclude.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: u8) -> Self {
+        self.config.max_depth = max_depth;
+        self
+    }
+
+    pub fn submodule_mode(mut self, submodule_mode: SubmoduleMode) -> Self {
+        self.config.submodule_mode = submodule_mode;
+        self
+    }
+
+    pub fn max_file_size_mb(mut self, max_file_size_mb: u64) -> Self {
+        self.config.max_file_size_mb = Some(max_file_size_mb);
+        self
+    }
+
+    pub fn backup_untracked(mut self, backup_untracked: bool) -> Self {
+        self.config.backup_untracked = backup_untracked;
+        self
+    }
+
+    pub fn include_overrides_gitignore(mut self, include_overrides_gitignore: bool) -> Self {
+        self.config.include_overrides_gitignore = include_overrides_gitignore;
+        self
+    }
+
+    pub fn min_backup_interval(mut self, min_backup_interval: Duration) -> Self {
+        self.config.min_backup_interval = Some(min_backup_interval);
+        self
+    }
+
+    pub fn mirror_path(mut self, mirror_path: impl Into<PathBuf>) -> Self {
+        self.config.mirror_path = Some(mirror_path.into());
+        self
+    }
+
+    pub fn max_backups(mut self, max_backups: usize) -> Self {
+        self.config.max_backups = Some(max_backups);
+        self
+    }
+
+    pub fn thin_to_daily_after_days(mut self, days: usize) -> Self {
+        self.config.thin_to_daily_after_days = Some(days);
+        self
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.config.label = Some(label.into());
+        self
+    }
+
+    /// Appends to the branches backed up from
+    pub fn only_branches<I, S>(mut self, patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.only_branches.extend(patterns.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn push_remote(mut self, remote: impl Into<String>) -> Self {
+        self.config.push_remotes.push(remote.into());
+        self
+    }
+
+    pub fn build(self) -> WatchConfig {
+        self.config
+    }
+}
+
+impl From<WatchConfig> for WatchConfigBuilder {
+    fn from(config: WatchConfig) -> Self {
+        Self { config }
+    }
+}
+
+/// Where `Config::load_verbose` got its config from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoadSource {
+    Loaded(PathBuf),
+    /// There's no config file, empty defaults were used
+    Missing(PathBuf),
+    /// The config file couldn't be read or parsed, empty defaults were used
+    ParseError(PathBuf, String),
+}
+
+impl fmt::Display for LoadSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadSource::Loaded(path) => write!(f, "Config: {}", path.display()),
+            LoadSource::Missing(path) => {
+                write!(f, "Config: {} not found, using defaults", path.display())
+            }
+            LoadSource::ParseError(path, e) => write!(
+                f,
+                "Config: unable to load {} ({}), using defaults",
+                path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// So that `*` in a repo pattern stops at path separators, like it does in a shell.
+const PATTERN_MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// `pattern` with a leading `~` replaced by the home dir.
+fn expand_home(pattern: &str) -> String {
+    match (pattern.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{}", home.display(), rest)
+        }
+        _ => pattern.to_string(),
+    }
+}
+
+/// Length of `pattern` up to its first wildcard, how specific a pattern is.
+fn literal_prefix_len(pattern: &str) -> usize {
+    pattern.find(['*', '?', '[']).unwrap_or(pattern.len())
+}
+
+/// Why a path can't be watched, see `Config::set_watch`.
+#[derive(Debug)]
+pub enum WatchPathError {
+    NotFound(PathBuf),
+    NotADirectory(PathBuf),
+    Io(PathBuf, io::Error),
+    /// Creating a git repo there failed, see `Config::watch`
+    Init(PathBuf, git2::Error),
+    /// The label is taken by the watch under this key already
+    DuplicateLabel(String, String),
+}
+
+impl fmt::Display for WatchPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WatchPathError::NotFound(path) => write!(f, "{} does not exist", path.display()),
+            WatchPathError::NotADirectory(path) => {
+                write!(f, "{} is a file, not a directory", path.display())
+            }
+            WatchPathError::Io(path, e) => write!(f, "unable to read {}: {}", path.display(), e),
+            WatchPathError::Init(path, e) => {
+                write!(f, "unable to create a git repo at {}: {}", path.display(), e)
+            }
+            WatchPathError::DuplicateLabel(label, key) => {
+                write!(f, "the label '{}' is already used for {}", label, key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WatchPathError {}
+
+/// What `Config::set_watch_many` did with one of its paths.
+#[derive(Debug)]
+pub enum WatchOutcome {
+    Added,
+    /// The path was watched already, under this key. It differs from the path's own key when
+    /// only their case differs, see `Config::case_variant_of`.
+    AlreadyWatched(String),
+    /// The path was watched already, under this key, and was given the new label
+    Relabeled(String),
+    Error(WatchPathError),
+}
+
+/// How `Config::plan_watch` or `Config::plan_unwatch` would change the watched paths, for
+/// showing with `--dry-run`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigChange {
+    /// This key would be added to `repos`
+    Add(String),
+    /// This key would be removed from `repos`
+    Remove(String),
+    /// Nothing would change, the path is watched under this key already
+    Unchanged(String),
+}
+
+impl fmt::Display for ConfigChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigChange::Add(key) => write!(f, "Would start watching {}", key),
+            ConfigChange::Remove(key) => write!(f, "Would stop watching {}", key),
+            ConfigChange::Unchanged(key) => write!(f, "{} is already being watched", key),
+        }
+    }
+}
+
+/// Why `Config::clean_candidates` suggests removing a watch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleanReason {
+    /// Nothing is at the path anymore
+    Missing,
+    /// The path is neither in a git repo, nor has any under it, so there's nothing to back up
+    NotARepo,
+}
+
+impl fmt::Display for CleanReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CleanReason::Missing => write!(f, "does not exist"),
+            CleanReason::NotARepo => write!(f, "has no git repositories"),
+        }
+    }
+}
+
+/// Why loading, saving or changing the config failed.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Reading or writing the config file at the path failed
+    Io(PathBuf, io::Error),
+    /// The config file at the path isn't valid TOML, or doesn't match the config format
+    Parse(PathBuf, toml::de::Error),
+    Serialize(toml::ser::Error),
+    /// The path isn't watched, so there's nothing to unwatch
+    NotWatched(String),
+    /// The exclude isn't a valid glob, see `Config::add_exclude`
+    InvalidPattern(String, glob::PatternError),
+    /// The include or exclude starts at the root, but patterns are relative to the watch
+    AbsolutePattern(String),
+    /// The watch at the key already has the exclude
+    DuplicateExclude(String, String),
+    /// The watch at the key doesn't have the exclude, so there's nothing to remove
+    NoSuchExclude(String, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) if e.kind() == io::ErrorKind::StorageFull => write!(
+                f,
+                "unable to write {}: the disk is full, free up some space and try again",
+                path.display()
+            ),
+            ConfigError::Io(path, e) if e.kind() == io::ErrorKind::PermissionDenied => write!(
+                f,
+                "unable to access {}: permission denied, check who owns it, or set \
+                 DURA_CONFIG_HOME to a directory you can write to",
+                path.display()
+            ),
+            ConfigError::Io(path, e) => write!(f, "unable to access {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "unable to parse {}: {}", path.display(), e),
+            ConfigError::Serialize(e) => write!(f, "unable to serialize the config: {}", e),
+            ConfigError::NotWatched(path) => write!(f, "{} is not being watched", path),
+            ConfigError::InvalidPattern(pattern, e) => {
+                write!(f, "'{}' is not a valid pattern: {}", pattern, e)
+            }
+            ConfigError::AbsolutePattern(pattern) => write!(
+                f,
+                "'{}' is an absolute path, patterns are relative to the watched directory",
+                pattern
+            ),
+            ConfigError::DuplicateExclude(key, pattern) => {
+                write!(f, "{} already excludes '{}'", key, pattern)
+            }
+            ConfigError::NoSuchExclude(key, pattern) => {
+                write!(f, "{} doesn't exclude '{}'", key, pattern)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(_, e) => Some(e),
+            ConfigError::Parse(_, e) => Some(e),
+            ConfigError::Serialize(e) => Some(e),
+            ConfigError::InvalidPattern(_, e) => Some(e),
+            ConfigError::NotWatched(_)
+            | ConfigError::AbsolutePattern(_)
+            | ConfigError::DuplicateExclude(..)
+            | ConfigError::NoSuchExclude(..) => None,
+        }
+    }
+}
+
+/// What `Config::backup_now` did.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BackupOutcome {
+    /// A backup was made
+    Committed(snapshots::CaptureStatus),
+    /// Nothing changed since the last backup
+    NoChanges,
+    /// The repo couldn't be backed up right now, e.g. because git holds its index lock
+    Skipped(snapshots::SkipReason),
+}
+
+/// Why `Config::backup_now` couldn't back a path up.
+#[derive(Debug)]
+pub enum BackupNowError {
+    /// No git repo contains the path
+    NotARepo(PathBuf, git2::Error),
+    /// The repo at the path isn't under any watch
+    NotWatched(PathBuf),
+    /// The repo at the path is under a watch, whose include and exclude patterns leave it out
+    Excluded(PathBuf),
+    Backup(git2::Error),
+}
+
+impl fmt::Display for BackupNowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BackupNowError::NotARepo(path, e) => {
+                write!(f, "{} is not in a git repo: {}", path.display(), e.message())
+            }
+            BackupNowError::NotWatched(path) => {
+                write!(f, "{} is not being watched", path.display())
+            }
+            BackupNowError::Excluded(path) => {
+                write!(f, "{} is excluded by its watch's patterns", path.display())
+            }
+            BackupNowError::Backup(e) => write!(f, "backup failed: {}", e.message()),
+        }
+    }
+}
+
+impl std::error::Error for BackupNowError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BackupNowError::NotARepo(_, e) | BackupNowError::Backup(e) => Some(e),
+            BackupNowError::NotWatched(_) | BackupNowError::Excluded(_) => None,
+        }
+    }
+}
+
+/// The key `path` is stored under in `Config::repos`.
+///
+/// TOML strings can only hold UTF-8, so a path that isn't valid unicode is stored lossily, with
+/// the offending bytes replaced by U+FFFD. Such a key no longer names the directory on disk, so
+/// dura can't back it up (it's reported as not found), but it can still be unwatched, and repos
+/// under a watched parent are found regardless of their names.
+fn repo_key(path: &Path) -> String {
+    let key = path.to_string_lossy();
+    if let Cow::Owned(_) = key {
+        warn!("{} is not valid unicode, it's saved as {}", path.display(), key);
+    }
+    key.into_owned()
+}
+
+/// Whether paths that only differ in case name the same file, as on the default filesystems
+/// of macOS and Windows.
+const CASE_INSENSITIVE_PATHS: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+
+/// Fails if `pattern` isn't a glob that can be matched relative to a watched directory.
+pub fn check_pattern(pattern: &str) -> std::result::Result<(), ConfigError> {
+    if Path::new(pattern).has_root() {
+        return Err(ConfigError::AbsolutePattern(pattern.to_string()));
+    }
+    glob::Pattern::new(pattern)
+        .map(|_| ())
+        .map_err(|e| ConfigError::InvalidPattern(pattern.to_string(), e))
+}
+
+/// Makes `path` absolute against the current directory, and canonical, if it's a directory.
+fn resolve_watch_dir(path: &Path) -> std::result::Result<PathBuf, WatchPathError> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir()
+            .map_err(|e| WatchPathError::Io(path.to_path_buf(), e))?
+            .join(path)
+    };
+    match fs::metadata(&path) {
+        Ok(metadata) if metadata.is_dir() => {
+            fs::canonicalize(&path).map_err(|e| WatchPathError::Io(path, e))
+        }
+        Ok(_) => Err(WatchPathError::NotADirectory(path)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err(WatchPathError::NotFound(path)),
+        Err(e) => Err(WatchPathError::Io(path, e)),
+    }
+}
+
+/// FNV-1a hash of `bytes`, as hex. Only for telling contents apart, not for security.
+fn hash_bytes(bytes: &[u8]) -> String {
+    let hash = bytes.iter().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// Where dura's own files are, resolved once, so that the files a backup would cover can be
+/// told apart from them without resolving each. For watches that cover dura's own directories,
+/// e.g. a dotfiles repo.
+#[derive(Debug, Clone)]
+pub struct DuraFiles {
+    cache_home: PathBuf,
+    config: PathBuf,
+}
+
+impl DuraFiles {
+    pub fn current() -> Self {
+        let resolve = |path: PathBuf| fs::canonicalize(&path).unwrap_or(path);
+        Self {
+            cache_home: resolve(RuntimeLock::get_dura_cache_home()),
+            config: resolve(Config::default_path()),
+        }
+    }
+
+    /// Where `Config::save_to_path` keeps the hash of the config file as dura last wrote it.
+    fn config_written_path() -> PathBuf {
+        RuntimeLock::get_dura_cache_home().join("config_written")
+    }
+
+    /// Whether the canonical `path` is in dura's cache directory, e.g. the runtime lock. Those
+    /// files change all the time and only mean something to dura, so they're never backed up.
+    pub fn is_cache_file(&self, path: &Path) -> bool {
+        path.starts_with(&self.cache_home)
+    }
+
+    /// Whether the canonical `path` is a file only dura writes to, or the config file exactly
+    /// as dura last saved it, so a change to it alone isn't worth a backup. An edit by anyone
+    /// else to the config is backed up.
+    pub fn is_dura_write(&self, path: &Path) -> bool {
+        if self.is_cache_file(path) {
+            return true;
+        }
+        if path != self.config {
+            return false;
+        }
+        let written = fs::read_to_string(Self::config_written_path()).ok();
+        written.is_some() && fs::read(path).ok().map(|bytes| hash_bytes(&bytes)) == written
+    }
+}
+
+/// Whether `a` and `b` name the same file, also through symlinks. A path that can't be resolved
+/// is compared as it is.
+fn same_file(a: &Path, b: &Path) -> bool {
+    let resolve = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    resolve(a) == resolve(b)
+}
+
+/// Writes `contents` to a temp file next to `path`, then renames it to `path`, so that a
+/// failed write, e.g. on a full disk, leaves the old file as it was. The temp file is removed
+/// again when anything fails. A symlinked `path` stays a symlink, the file it points to is
+/// replaced, and an existing file keeps its permissions.
+fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let permissions = fs::metadata(&target).ok().map(|metadata| metadata.permissions());
+    let mut tmp_name = target.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = target.with_file_name(tmp_name);
+    let result = File::create(&tmp)
+        .and_then(|mut file| file.write_all(contents).and_then(|()| file.sync_all()))
+        .and_then(|()| match permissions {
+            Some(permissions) => fs::set_permissions(&tmp, permissions),
+            None => Ok(()),
+        })
+        .and_then(|()| fs::rename(&tmp, &target));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// The key `path` is unwatched by. It's taken as is when it can't be canonicalized, so that
+/// paths which are gone can still be unwatched.
+fn unwatch_key(path: &Path) -> String {
+    repo_key(&fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+/// Aggregate of the watched repos sharing a parent directory, see `Config::group_by_parent`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RepoGroup {
+    pub repos: Vec<PathBuf>,
+    /// How many of `repos` have uncommitted changes
+    pub dirty: usize,
+}
+
+/// Version of the config format written by this build, see `Config::migrate`.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct Config {
+    /// Format version of this config, so that it can be migrated when the format changes.
+    /// Configs from before versioning don't have it, they're version 0.
+    #[serde(default)]
+    pub version: u32,
+    /// When commit_exclude_git_config is true,
+    /// never use any git configuration to sign dura's commits.
+    /// Defaults to false
+    #[serde(default)]
+    pub commit_exclude_git_config: bool,
+    /// Author name of backup commits. Defaults to git's user.name, or "dura"
+    pub commit_author: Option<String>,
+    /// Author email of backup commits. Defaults to git's user.email, or "dura@github.io"
+    pub commit_email: Option<String>,
+    /// When clamp_commit_time is true, backups dated in the future (because the clock was
+    /// skewed when they were made) are treated as if they were made now, so they can't mask
+    /// newer backups. See backups::summarize for the tradeoff.
+    /// Defaults to false
+    #[serde(default)]
+    pub clamp_commit_time: bool,
+    /// When stats_csv is set, `dura serve` periodically appends a row of summary stats to this
+    /// CSV file, for charting dura's activity over time.
+    pub stats_csv: Option<PathBuf>,
+    /// Repos whose git dir is bigger than max_repo_size_mb aren't backed up, so that dura
+    /// doesn't spend its time on e.g. huge binary histories.
+    /// Defaults to no limit
+    pub max_repo_size_mb: Option<u64>,
+    // Durations below are written like "30s", "5m" or "1h 30m", or as a number of seconds.
+    /// Results of collect_status are reused for status_cache_ttl, unless a repo changed in
+    /// the meantime, so that scripts calling it in a loop don't rescan every repo each time.
+    /// Defaults to 0, i.e. no caching
+    #[serde(default, alias = "status_cache_ttl_secs", with = "human_duration")]
+    #[schemars(schema_with = "human_duration::schema")]
+    pub status_cache_ttl: Duration,
+    /// When backup_unborn_repos is true, repos without any commits yet are backed up too, to
+    /// the dura/unborn branch. Otherwise they're skipped until their first commit.
+    /// Defaults to false
+    #[serde(default)]
+    pub backup_unborn_repos: bool,
+    /// When backup_broken_head_repos is true, repos whose HEAD points to a branch that doesn't
+    /// exist, see snapshots::broken_head, are backed up to the dura/unborn branch with a warning
+    /// in the log. Otherwise they're skipped until HEAD is fixed.
+    /// Defaults to false
+    #[serde(default)]
+    pub backup_broken_head_repos: bool,
+    /// When max_poll_interval is set, `dura serve` polls less and less often while nothing
+    /// changes, up to this long between polls, and goes back to polling every few seconds as
+    /// soon as something does. See poller::IdleBackoff.
+    /// Defaults to always polling at the base interval
+    #[serde(default, alias = "max_poll_interval_secs", with = "human_duration::option")]
+    #[schemars(schema_with = "human_duration::option::schema")]
+    pub max_poll_interval: Option<Duration>,
+    /// A repo whose files changed less than debounce_ms milliseconds ago isn't backed up yet,
+    /// so that a burst of writes, e.g. a save touching several files, lands in one backup.
+    /// Unlike min_backup_interval it doesn't space backups out, it waits for quiet. 0 or
+    /// None backs up as soon as a change is seen. See poller::Debouncer.
+    /// Defaults to None, since each change it's on for costs a full walk of the repo.
+    #[serde(default)]
+    pub debounce_ms: Option<u64>,
+    /// When write_backup_notes is true, each backup also gets a git note under refs/notes/dura
+    /// with metadata about it, and is recognized as a backup by that note even when its commit
+    /// message no longer says "dura auto-backup", e.g. after a rebase.
+    /// Defaults to false
+    #[serde(default)]
+    pub write_backup_notes: bool,
+    /// Whether backups are commits on dura branches, or stash entries, see BackupStrategy.
+    /// Defaults to commit_to_ref
+    #[serde(default)]
+    pub backup_strategy: BackupStrategy,
+    /// When notify_on_dirty is true, the daemon logs once when a watched repo that was clean
+    /// gets uncommitted changes, as a sign that dura is watching the work that just started.
+    /// Later polls of the same dirty repo stay quiet, until it's clean again.
+    /// Defaults to false
+    #[serde(default)]
+    pub notify_on_dirty: bool,
+    /// When force_plain_symbols is set, it decides between ASCII (true) and emoji (false)
+    /// symbols in dura's output, over the DURA_PLAIN_TEXT and DURA_FANCY environment variables
+    /// and terminal detection. For terminals that claim to handle emoji but don't.
+    /// Defaults to detecting it
+    pub force_plain_symbols: Option<bool>,
+    /// Repos with uncommitted changes whose newest backup is older than stale_after, or that
+    /// have no backup at all, are flagged "(stale)" in `dura info`, in red with color on.
+    /// Defaults to never flagging repos
+    #[serde(default, alias = "stale_after_secs", with = "human_duration::option")]
+    #[schemars(schema_with = "human_duration::option::schema")]
+    pub stale_after: Option<Duration>,
+    /// Watched paths and how each is watched
+    pub repos: BTreeMap<String, Rc<WatchConfig>>,
+    /// Watch configs for repos whose path matches a glob, e.g. "~/work/*", so that many similar
+    /// repos under a watched dir can share one config. They don't add anything to watch. A repo
+    /// with its own entry in repos uses that, otherwise the most specific matching pattern wins:
+    /// the one with the longest literal prefix before its first wildcard, then the longest. See
+    /// watch_config_for.
+    /// Defaults to no patterns
+    #[serde(default)]
+    pub repo_patterns: BTreeMap<String, Rc<WatchConfig>>,
+}
+
+/// Totals across all watched repos, see `Config::summary_stats` and `SummaryStats::of`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SummaryStats {
+    pub total_repos: usize,
+    /// Repos with uncommitted changes
+    pub dirty_repos: usize,
+    /// Files with uncommitted changes, summed over all accessible repos
+    pub dirty_files: usize,
+    pub total_backups: usize,
+    /// Repos that are missing, aren't git repos, or whose status can't be read. They're
+    /// counted in `total_repos`, and in `total_backups` if their backups can be counted.
+    pub inaccessible_repos: usize,
+}
+
+impl SummaryStats {
+    /// The totals of `statuses`, as collected by `Config::collect_status`.
+    pub fn of(statuses: &[RepoStatus]) -> Self {
+        let mut stats = SummaryStats {
+            total_repos: statuses.len(),
+            ..SummaryStats::default()
+        };
+        for status in statuses {
+            if !status.is_git_repo || status.status_error.is_some() {
+                stats.inaccessible_repos += 1;
+            }
+            if status.uncommitted_changes {
+                stats.dirty_repos += 1;
+            }
+            stats.dirty_files += status.dirty_files;
+            stats.total_backups += status.backups;
+        }
+        stats
+    }
+}
+
+/// How `Config::import_from_directory_list` went.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub added: usize,
+    /// Lines that weren't repos, or were watched already
+    pub skipped: usize,
+}
+
+/// Uncommitted work across every watched repo, see `Config::global_uncommitted_summary`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GlobalSummary {
+    pub total_repos: usize,
+    pub dirty_repos: usize,
+    /// Files with uncommitted changes, summed over the dirty repos
+    pub dirty_files: usize,
+    /// How long ago the oldest change that isn't in a backup yet was made, going by file
+    /// modification times. None when every change is backed up.
+    pub oldest_unbacked_change: Option<Duration>,
+}
+
+impl Config {
+    const SYMBOLS_FANCY: [&'static str; 8] = ["✓", "📝", "❌", "⚠️", "ℹ️", "🕒", "📊", "📁"];
+    const SYMBOLS_PLAIN: [&'static str; 8] = ["[OK]", "[M]", "[X]", "!", "i", "@", "#", "*"];
+
+    fn get_symbols(&self) -> &'static [&'static str; 8] {
+        if self.fancy_symbols(&term::TermEnv::detect()) {
+            &Self::SYMBOLS_FANCY
+        } else {
+            &Self::SYMBOLS_PLAIN
+        }
+    }
+
+    /// Whether to print emoji symbols in `env`. `force_plain_symbols` wins over everything in
+    /// the environment, `DURA_PLAIN_TEXT` and `DURA_FANCY` included.
+    pub fn fancy_symbols(&self, env: &term::TermEnv) -> bool {
+        match self.force_plain_symbols {
+            Some(plain) => !plain,
+            None => env.fancy_symbols(),
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            commit_exclude_git_config: false,
+            commit_author: None,
+            commit_email: None,
+            clamp_commit_time: false,
+            stats_csv: None,
+            max_repo_size_mb: None,
+            status_cache_ttl: Duration::ZERO,
+            backup_unborn_repos: false,
+            backup_broken_head_repos: false,
+            max_poll_interval: None,
+            debounce_ms: None,
+            write_backup_notes: false,
+            backup_strategy: BackupStrategy::default(),
+            notify_on_dirty: false,
+            force_plain_symbols: None,
+            stale_after: None,
+            repos: BTreeMap::new(),
+            repo_patterns: BTreeMap::new(),
+        }
+    }
+
+    /// JSON Schema of config.toml, with each field's type, default and docs, for editors to
+    /// complete and validate the file against.
+    pub fn export_json_schema() -> serde_json::Value {
+        let schema = schemars::schema_for!(Config);
+        serde_json::to_value(schema).expect("schemas always serialize")
+    }
+
+    /// Hash of the config as it's serialized, to tell whether two configs differ, see
+    /// `RuntimeLock::config_drifted`. FNV-1a, so it's the same across builds and platforms.
+    pub fn content_hash(&self) -> String {
+        let serialized = toml::to_string(self).unwrap_or_else(|_| format!("{:?}", self));
+        hash_bytes(serialized.as_bytes())
+    }
+
+    pub fn default_path() -> PathBuf {
+        Self::get_dura_config_home().join("config.toml")
+    }
+
+    /// Location of all config. By default
+    ///
+    /// Linux   :   $XDG_CONFIG_HOME/dura or $HOME/.config/dura
+    /// macOS   :   $HOME/Library/Application Support
+    /// Windows :   %AppData%\Roaming\dura
+    ///
+    /// This can be overridden by setting DURA_CONFIG_HOME environment variable.
+    fn get_dura_config_home() -> PathBuf {
+        // The environment variable lets us run tests independently, but I'm sure someone will come
+        // up with another reason to use it.
+        if let Ok(env_var) = env::var("DURA_CONFIG_HOME") {
+            if !env_var.is_empty() {
+                return env_var.into();
+            }
+        }
+
+        dirs::config_dir()
+            .expect("Could not find your config directory. The default is ~/.config/dura but it can also \
+                be controlled by setting the DURA_CONFIG_HOME environment variable.")
+            .join("dura")
+    }
+
+    /// Load Config from default path
+    pub fn load() -> Self {
+        Self::load_from(Self::default_path().as_path())
+    }
+
+    /// Load Config from `path`, or an empty one if that can't be read
+    pub fn load_from(path: &Path) -> Self {
+        Self::load_file(path).unwrap_or_else(|_| Self::empty())
+    }
+
+    /// Like `load`, but also reports where the config came from, since falling back to an empty
+    /// config can otherwise look like dura forgot every watch.
+    pub fn load_verbose() -> (Self, LoadSource) {
+        Self::load_verbose_from(Self::default_path())
+    }
+
+    pub fn load_verbose_from(path: PathBuf) -> (Self, LoadSource) {
+        match Self::load_file(path.as_path()) {
+            Ok(config) => (config, LoadSource::Loaded(path)),
+            Err(ConfigError::Io(_, e)) if e.kind() == io::ErrorKind::NotFound => {
+                (Self::empty(), LoadSource::Missing(path))
+            }
+            // the path is in the LoadSource already, only what went wrong with it is kept
+            Err(e) => {
+                let cause = std::error::Error::source(&e)
+                    .map_or_else(|| e.to_string(), ToString::to_string);
+                (Self::empty(), LoadSource::ParseError(path, cause))
+            }
+        }
+    }
+
+    pub fn load_file(path: &Path) -> std::result::Result<Self, ConfigError> {
+        let io_error = |e| ConfigError::Io(path.to_path_buf(), e);
+        let mut reader = BufReader::new(File::open(path).map_err(io_error)?);
+
+        let mut buffer = Vec::new();
+        reader.read_to_end(&mut buffer).map_err(io_error)?;
+
+        let mut res: Self = toml::from_slice(buffer.as_slice())
+            .map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        res.migrate();
+        Ok(res)
+    }
+
+    /// Upgrades a config written by an older dura to `CONFIG_VERSION`, which is saved with it
+    /// from then on. A config from a newer dura is left alone, with a warning, since it may
+    /// hold settings this build doesn't know about.
+    pub fn migrate(&mut self) {
+        if self.version > CONFIG_VERSION {
+            warn!(
+                "Config version {} is newer than this dura understands ({}), some settings may \
+                 be ignored",
+                self.version, CONFIG_VERSION
+            );
+            return;
+        }
+        if self.version < 1 {
+            // Version 0 configs could hold keys that aren't canonical paths, e.g. from editing
+            // the file by hand, which then never match the paths dura looks up
+            let repos = std::mem::take(&mut self.repos);
+            for (key, watch_config) in repos {
+                let key = fs::canonicalize(&key).map_or(key, |path| repo_key(&path));
+                self.repos.entry(key).or_insert(watch_config);
+            }
+        }
+        self.version = CONFIG_VERSION;
+    }
+
+    /// Save config to disk in ~/.config/dura/config.toml
+    pub fn save(&self) {
+        if let Err(e) = self.save_to_path(Self::default_path().as_path()) {
+            println!("Unable to save the dura config: {e}");
+        }
+    }
+
+    /// Attempts to create parent dirs, serialize `self` as TOML and write to disk.
+    pub fn save_to_path(&self, path: &Path) -> std::result::Result<(), ConfigError> {
+        if read_only::is_enabled() {
+            println!("Saving config to {} skipped (read-only)", path.display());
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir).map_err(|e| ConfigError::Io(dir.to_path_buf(), e))?;
+        }
+
+        let config_string = toml::to_string(self).map_err(ConfigError::Serialize)?;
+        write_atomically(path, config_string.as_bytes())
+            .map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        if same_file(path, &Self::default_path()) {
+            // only a hint for DuraFiles::is_dura_write, so failing to write it is fine
+            let written = DuraFiles::config_written_path();
+            if let Some(dir) = written.parent() {
+                let _ = create_dir_all(dir);
+            }
+            let _ = fs::write(written, hash_bytes(config_string.as_bytes()));
+        }
+        Ok(())
+    }
+
+    /// Watches the directory at `path`, which may be relative to the current directory. Fails
+    /// without changing anything if there's no directory there.
+    pub fn set_watch(
+        &mut self,
+        path: impl AsRef<Path>,
+        cfg: WatchConfig,
+    ) -> std::result::Result<(), WatchPathError> {
+        let abs_path = repo_key(&resolve_watch_dir(path.as_ref())?);
+        let label = cfg.label.clone().unwrap_or_default();
+
+        match self.add_watch(&abs_path, cfg) {
+            WatchOutcome::Added => println!("Started watching {abs_path}"),
+            WatchOutcome::Relabeled(existing) => {
+                println!("{existing} is already being watched, it's now labeled '{label}'")
+            }
+            WatchOutcome::AlreadyWatched(existing) if existing == abs_path => {
+                println!("{abs_path} is already being watched")
+            }
+            WatchOutcome::AlreadyWatched(existing) => {
+                println!("{abs_path} is already being watched as {existing}")
+            }
+            WatchOutcome::Error(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Watches each of `entries` like `set_watch`, but without printing anything, and saves
+    /// the config to `config_path` once at the end if any were added. A path that can't be
+    /// watched doesn't stop the others, its outcome holds the error.
+    pub fn set_watch_many(
+        &mut self,
+        entries: Vec<(PathBuf, WatchConfig)>,
+        config_path: &Path,
+    ) -> Vec<(PathBuf, WatchOutcome)> {
+        let outcomes: Vec<(PathBuf, WatchOutcome)> = entries
+            .into_iter()
+            .map(|(path, cfg)| {
+                let outcome = match resolve_watch_dir(&path) {
+                    Ok(dir) => self.add_watch(&repo_key(&dir), cfg),
+                    Err(e) => WatchOutcome::Error(e),
+                };
+                (path, outcome)
+            })
+            .collect();
+        let changed = |outcome: &WatchOutcome| {
+            matches!(outcome, WatchOutcome::Added | WatchOutcome::Relabeled(_))
+        };
+        if outcomes.iter().any(|(_, outcome)| changed(outcome)) {
+            if let Err(e) = self.save_to_path(config_path) {
+                println!("Unable to save the dura config: {e}");
+            }
+        }
+        outcomes
+    }
+
+    /// What `set_watch` would do with `path`, without changing anything.
+    pub fn plan_watch(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> std::result::Result<ConfigChange, WatchPathError> {
+        let abs_path = repo_key(&resolve_watch_dir(path.as_ref())?);
+        Ok(match self.watched_as(&abs_path) {
+            Some(existing) => ConfigChange::Unchanged(existing),
+            None => ConfigChange::Add(abs_path),
+        })
+    }
+
+    /// Adds `abs_path`, a key from `repo_key`, unless it or a case variant of it is watched,
+    /// or another watch has the same label. A watched path only takes the label of `cfg`, if
+    /// it has one, and keeps the rest of its config.
+    fn add_watch(&mut self, abs_path: &str, cfg: WatchConfig) -> WatchOutcome {
+        let labeled = cfg
+            .label
+            .as_deref()
+            .and_then(|label| self.find_by_label(label))
+            .map(|(key, _)| key.clone());
+        match (self.watched_as(abs_path), labeled) {
+            (Some(existing), Some(key)) if key == existing => {
+                WatchOutcome::AlreadyWatched(existing)
+            }
+            (_, Some(key)) => WatchOutcome::Error(WatchPathError::DuplicateLabel(
+                cfg.label.clone().unwrap_or_default(),
+                key,
+            )),
+            (Some(existing), None) => match cfg.label {
+                Some(label) => {
+                    let watch_config = self.repos.get_mut(&existing).expect("watched");
+                    Rc::make_mut(watch_config).label = Some(label);
+                    WatchOutcome::Relabeled(existing)
+                }
+                None => WatchOutcome::AlreadyWatched(existing),
+            },
+            (None, None) => {
+                self.repos.insert(abs_path.to_string(), Rc::new(cfg));
+                WatchOutcome::Added
+            }
+        }
+    }
+
+    /// The watch labeled `label`, see `WatchConfig::label`.
+    pub fn find_by_label(&self, label: &str) -> Option<(&String, &Rc<WatchConfig>)> {
+        self.repos
+            .iter()
+            .find(|(_, watch_config)| watch_config.label.as_deref() == Some(label))
+    }
+
+    /// How summaries name the watch at `key`: `<label> (<path>)` if it has a label, otherwise
+    /// just the path.
+    pub fn display_name(&self, key: &str) -> String {
+        match self.repos.get(key).and_then(|watch_config| watch_config.label.as_ref()) {
+            Some(label) => format!("{} ({})", label, key),
+            None => key.to_string(),
+        }
+    }
+
+    /// The key `abs_path` is watched under, itself or a case variant of it.
+    fn watched_as(&self, abs_path: &str) -> Option<String> {
+        if self.repos.contains_key(abs_path) {
+            Some(abs_path.to_string())
+        } else {
+            self.case_variant_of(abs_path).cloned()
+        }
+    }
+
+    /// Like `set_watch`, but with `init_if_needed` a git repo is created at `path` first if
+    /// it isn't in one yet, so that a plain directory is backed up right away instead of
+    /// being reported as not a git repository.
+    pub fn watch(
+        &mut self,
+        path: impl AsRef<Path>,
+        cfg: WatchConfig,
+        init_if_needed: bool,
+    ) -> std::result::Result<(), WatchPathError> {
+        let dir = resolve_watch_dir(path.as_ref())?;
+        if init_if_needed && Repository::discover(&dir).is_err() {
+            if read_only::is_enabled() {
+                println!("Creating a git repo at {} skipped (read-only)", dir.display());
+            } else {
+                Repository::init(&dir).map_err(|e| WatchPathError::Init(dir.clone(), e))?;
+                println!("Created a git repo at {}", dir.display());
+            }
+        }
+        self.set_watch(dir, cfg)
+    }
+
+    /// The watched path that names the same directory as `key` when case is ignored, on
+    /// platforms whose filesystems usually do, e.g. `~/code` for `~/Code`. Canonical keys
+    /// don't settle on one case there, it depends on how the path was typed.
+    pub fn case_variant_of(&self, key: &str) -> Option<&String> {
+        if !CASE_INSENSITIVE_PATHS {
+            return None;
+        }
+        let key = key.to_lowercase();
+        self.repos.keys().find(|watched| watched.to_lowercase() == key)
+    }
+
+    /// Adds the preset excludes of each watched dir's detected project kind to its watch
+    /// config, keeping the excludes that are already there. Returns how many watches changed.
+    pub fn reapply_presets(&mut self) -> usize {
+        let mut updated = 0;
+        for (path, watch_config) in self.repos.iter_mut() {
+            let kind = presets::detect_project_kind(Path::new(path));
+            let missing: Vec<String> = kind
+                .excludes()
+                .iter()
+                .filter(|exclude| !watch_config.exclude.iter().any(|e| e == *exclude))
+                .map(|exclude| exclude.to_string())
+                .collect();
+            if !missing.is_empty() {
+                Rc::make_mut(watch_config).exclude.extend(missing);
+                updated += 1;
+            }
+        }
+        updated
+    }
+
+    /// Watches, with the default config, each git repo in the file at `list_path`, one path
+    /// per line like `ghq list --full-path` prints. Lines that aren't repos, or are already
+    /// watched, are skipped. Fails without changing anything if the list can't be read. The
+    /// config isn't saved.
+    pub fn import_from_directory_list(
+        &mut self,
+        list_path: &Path,
+    ) -> std::result::Result<ImportSummary, ConfigError> {
+        let list = fs::read_to_string(list_path)
+            .map_err(|e| ConfigError::Io(list_path.to_path_buf(), e))?;
+        let mut summary = ImportSummary::default();
+        for line in list.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let outcome = resolve_watch_dir(Path::new(line))
+                .ok()
+                .filter(|dir| snapshots::is_repo(dir))
+                .map(|dir| self.add_watch(&repo_key(&dir), WatchConfig::new()));
+            match outcome {
+                Some(WatchOutcome::Added) => summary.added += 1,
+                _ => summary.skipped += 1,
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Stops watching `path`. Fails with `ConfigError::NotWatched` if it wasn't watched.
+    pub fn set_unwatch(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> std::result::Result<(), ConfigError> {
+        let abs_path = unwatch_key(path.as_ref());
+
+        match self.repos.remove(&abs_path) {
+            Some(_) => {
+                println!("Stopped watching {abs_path}");
+                Ok(())
+            }
+            None => Err(ConfigError::NotWatched(abs_path)),
+        }
+    }
+
+    /// Adds `pattern` to the excludes of the watch at `path`, keeping the rest of its config,
+    /// and saves to `config_path`, where the config was loaded from. Fails without changing
+    /// anything if the path isn't watched, the watch already excludes `pattern`, or it isn't a
+    /// valid relative glob.
+    pub fn add_exclude(
+        &mut self,
+        path: &Path,
+        pattern: String,
+        config_path: &Path,
+    ) -> std::result::Result<(), ConfigError> {
+        check_pattern(&pattern)?;
+        let key = unwatch_key(path);
+        let watch_config = self
+            .repos
+            .get_mut(&key)
+            .ok_or_else(|| ConfigError::NotWatched(key.clone()))?;
+        if watch_config.exclude.contains(&pattern) {
+            return Err(ConfigError::DuplicateExclude(key, pattern));
+        }
+        Rc::make_mut(watch_config).exclude.push(pattern);
+        self.save_to_path(config_path)
+    }
+
+    /// Removes `pattern` from the excludes of the watch at `path`, and saves to `config_path`
+    /// like `add_exclude`. Fails without changing anything if the path isn't watched, or the
+    /// watch doesn't exclude `pattern`.
+    pub fn remove_exclude(
+        &mut self,
+        path: &Path,
+        pattern: &str,
+        config_path: &Path,
+    ) -> std::result::Result<(), ConfigError> {
+        let key = unwatch_key(path);
+        let watch_config = self
+            .repos
+            .get_mut(&key)
+            .ok_or_else(|| ConfigError::NotWatched(key.clone()))?;
+        if !watch_config.exclude.iter().any(|exclude| exclude == pattern) {
+            return Err(ConfigError::NoSuchExclude(key, pattern.to_string()));
+        }
+        Rc::make_mut(watch_config)
+            .exclude
+            .retain(|exclude| exclude != pattern);
+        self.save_to_path(config_path)
+    }
+
+    /// Watches that look stale, for `dura clean` to offer removing, in key order. Paths that are
+    /// `repo_status::is_unavailable` aren't among them, they may well come back.
+    pub fn clean_candidates(&self) -> Vec<(String, CleanReason)> {
+        self.repos
+            .iter()
+            .filter_map(|(key, watch_config)| {
+                let path = Path::new(key);
+                let reason = if repo_status::is_unavailable(path) {
+                    return None;
+                } else if !path.exists() {
+                    CleanReason::Missing
+                } else if Repository::discover(path).is_err()
+                    && GitRepoIter::under(path, watch_config).next().is_none()
+                {
+                    CleanReason::NotARepo
+                } else {
+                    return None;
+                };
+                Some((key.clone(), reason))
+            })
+            .collect()
+    }
+
+    /// What `set_unwatch` would do with `path`, without changing anything.
+    pub fn plan_unwatch(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> std::result::Result<ConfigChange, ConfigError> {
+        let abs_path = unwatch_key(path.as_ref());
+        if self.repos.contains_key(&abs_path) {
+            Ok(ConfigChange::Remove(abs_path))
+        } else {
+            Err(ConfigError::NotWatched(abs_path))
+        }
+    }
+
+    /// The top-level directories covering every watch, e.g. to register with a file-system
+    /// notifier. Watches nested under another watch are collapsed into it, so that each
+    /// directory only gets registered once.
+    pub fn watched_roots(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = self
+            .repos
+            .keys()
+            .map(|path| fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path)))
+            .collect();
+        // Parents sort before their children
+        paths.sort();
+        paths.dedup();
+
+        let mut roots: Vec<PathBuf> = Vec::new();
+        for path in paths {
+            if !roots.iter().any(|root| path.starts_with(root)) {
+                roots.push(path);
+            }
+        }
+        roots
+    }
+
+    /// Verifies dura can write to its config dir, its cache dir, and the git dir of every watched
+    /// repo, so problems show up before dura has to rely on them.
+    pub fn preflight(&self) -> Vec<PreflightResult> {
+        let mut results = vec![
+            preflight::check_writable(
+                PreflightTarget::ConfigHome,
+                &Self::get_dura_config_home(),
+            ),
+            preflight::check_writable(
+                PreflightTarget::CacheHome,
+                &RuntimeLock::get_dura_cache_home(),
+            ),
+        ];
+        for path in self.repos.keys() {
+            results.push(match Repository::open(path) {
+                Ok(repo) => preflight::check_writable(PreflightTarget::GitDir, repo.path()),
+                Err(e) => PreflightResult {
+                    target: PreflightTarget::GitDir,
+                    path: PathBuf::from(path),
+                    error: Some(format!("Not a git repository: {}", e.message())),
+                },
+            });
         }
+        results
     }
-}
 
-impl Default for WatchConfig {
-    fn default() -> Self {
-        WatchConfig::new()
+    /// The config and cache locations in effect, and where each watched repo and its git dir
+    /// resolve to. Includes the env overrides, so it's what a bug report needs.
+    pub fn debug_paths(&self) -> ResolvedPaths {
+        ResolvedPaths {
+            config_home: Self::get_dura_config_home(),
+            config_file: Self::default_path(),
+            cache_home: RuntimeLock::get_dura_cache_home(),
+            runtime_lock: RuntimeLock::default_path(),
+            repos: self
+                .repos
+                .keys()
+                .map(|key| ResolvedRepoPaths {
+                    key: key.clone(),
+                    canonical: fs::canonicalize(key).ok(),
+                    git_dir: Repository::open(key).ok().map(|repo| repo.path().to_path_buf()),
+                })
+                .collect(),
+        }
     }
-}
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Config {
-    // When commit_exclude_git_config is true,
-    // never use any git configuration to sign dura's commits.
-    // Defaults to false
-    #[serde(default)]
-    pub commit_exclude_git_config: bool,
-    pub commit_author: Option<String>,
-    pub commit_email: Option<String>,
-    pub repos: BTreeMap<String, Rc<WatchConfig>>,
-}
+    /// Diagnostics for a bug report, for a config loaded from the default path.
+    pub fn doctor(&self) -> DoctorReport {
+        self.doctor_at(Self::default_path())
+    }
 
-impl Config {
-    const SYMBOLS_FANCY: [&'static str; 8] = ["✓", "📝", "❌", "⚠️", "ℹ️", "🕒", "📊", "📁"];
-    const SYMBOLS_PLAIN: [&'static str; 8] = ["[OK]", "[M]", "[X]", "!", "i", "@", "#", "*"];
+    /// Like `doctor`, with `config_path` being where this config was loaded from.
+    pub fn doctor_at(&self, config_path: PathBuf) -> DoctorReport {
+        let (_, config) = Self::load_verbose_from(config_path);
+        let lock_path = RuntimeLock::default_path();
+        let (lock, lock_error) = match RuntimeLock::load_file(&lock_path) {
+            Ok(lock) => (lock, None),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (RuntimeLock::empty(), None),
+            Err(e) => (RuntimeLock::empty(), Some(e.to_string())),
+        };
+        let now = SystemTime::now();
+        let version = git2::Version::get();
+        let (major, minor, rev) = version.libgit2_version();
 
-    fn get_symbols() -> &'static [&'static str; 8] {
-        // Check environment variable first (explicit override)
-        if std::env::var("DURA_PLAIN_TEXT").is_ok() {
-            return &Self::SYMBOLS_PLAIN;
-        }
-        
-        // Check if DURA_FANCY is set (explicit override)
-        if std::env::var("DURA_FANCY").is_ok() {
-            return &Self::SYMBOLS_FANCY;
+        DoctorReport {
+            dura_version: env!("CARGO_PKG_VERSION").to_string(),
+            git2_version: version.crate_version().to_string(),
+            libgit2_version: format!("{major}.{minor}.{rev}"),
+            config,
+            runtime_lock: RuntimeLockCheck {
+                path: lock_path,
+                error: lock_error,
+                pid: lock.pid,
+                stale: lock.is_stale(now),
+                server_status: lock.server_status(now),
+            },
+            repos: self
+                .repos
+                .keys()
+                .map(|path| RepoCheck {
+                    path: PathBuf::from(path),
+                    error: Repository::open(path).err().map(|e| e.message().to_string()),
+                })
+                .collect(),
         }
+    }
 
-        // Auto-detect terminal capabilities
-        if !std::io::stdout().is_terminal() {
-            // Not a terminal (e.g., pipe or redirect)
-            return &Self::SYMBOLS_PLAIN;
+    /// How long until the repo at `repo_path` may be backed up again, going by its
+    /// `min_backup_interval` and the last backup in the runtime lock. Zero when it may be
+    /// backed up now, None when its watch has no interval.
+    pub fn next_backup_eta(&self, repo_path: &Path) -> Option<Duration> {
+        self.next_backup_eta_with(repo_path, &RuntimeLock::load())
+    }
+
+    /// `next_backup_eta`, with an already loaded runtime lock.
+    pub(crate) fn next_backup_eta_with(
+        &self,
+        repo_path: &Path,
+        runtime_lock: &RuntimeLock,
+    ) -> Option<Duration> {
+        let interval = self.watch_config_for(repo_path)?.min_backup_interval?;
+        let last_backup = runtime_lock
+            .repos
+            .get(repo_path.to_string_lossy().as_ref())
+            .and_then(|cache| cache.last_backup_time);
+        let Some(last_backup) = last_backup else {
+            return Some(Duration::ZERO);
+        };
+        let allowed_at = SystemTime::UNIX_EPOCH
+            + Duration::from_secs(last_backup.max(0) as u64)
+            + interval;
+        Some(
+            allowed_at
+                .duration_since(SystemTime::now())
+                .unwrap_or(Duration::ZERO),
+        )
+    }
+
+    /// Backs up the repo containing `path` right away, even if its `min_backup_interval` hasn't
+    /// passed since the last backup. The repo still has to be one the daemon would back up,
+    /// i.e. under a watch and not left out by its include and exclude patterns.
+    pub fn backup_now(&self, path: &Path) -> std::result::Result<BackupOutcome, BackupNowError> {
+        let repo = Repository::discover(path)
+            .map_err(|e| BackupNowError::NotARepo(path.to_path_buf(), e))?;
+        let repo_path = match repo.workdir() {
+            Some(workdir) => workdir.canonicalize().unwrap_or_else(|_| workdir.to_path_buf()),
+            None => return Err(BackupNowError::NotWatched(path.to_path_buf())),
+        };
+        let (root, watch_config) = self
+            .watch_entry_for(&repo_path)
+            .ok_or_else(|| BackupNowError::NotWatched(repo_path.clone()))?;
+        let relative = repo_path.strip_prefix(root).unwrap_or(Path::new(""));
+        if !CompiledWatch::cached(watch_config).admits(relative) {
+            return Err(BackupNowError::Excluded(repo_path));
         }
 
-        // Check for NO_COLOR (standard for disabling color/unicode)
-        if std::env::var("NO_COLOR").is_ok() {
-            return &Self::SYMBOLS_PLAIN;
+        let outcome = snapshots::capture_outcome(self, &repo_path, &SystemClock);
+        match outcome.map_err(BackupNowError::Backup)? {
+            CaptureOutcome::Backup(mut status) => {
+                // so the throttle counts from this backup
+                let mut runtime_lock = RuntimeLock::load();
+                runtime_lock.record_backup(&repo_path, &status);
+                runtime_lock.save();
+                status.pushes = snapshots::push_backup(self, &repo_path, &status);
+                Ok(BackupOutcome::Committed(status))
+            }
+            CaptureOutcome::Unchanged => Ok(BackupOutcome::NoChanges),
+            CaptureOutcome::Skipped(reason) => Ok(BackupOutcome::Skipped(reason)),
         }
+    }
 
-        // Check TERM environment variable
-        if let Ok(term) = std::env::var("TERM") {
-            let term = term.to_lowercase();
-            if term == "dumb" || term == "vt100" || term.contains("linux") {
-                return &Self::SYMBOLS_PLAIN;
+    /// The config of the watch covering `path`. When watches are nested, the innermost wins.
+    /// A repo's own entry in `repos` comes first, then the most specific of `repo_patterns`
+    /// matching it. Patterns only apply to paths some watch covers.
+    pub(crate) fn watch_config_for(&self, path: &Path) -> Option<Rc<WatchConfig>> {
+        let (root, watch_config) = self.watch_entry_for(path)?;
+        if Path::new(root) != path {
+            if let Some(watch_config) = self.pattern_config_for(path) {
+                return Some(watch_config);
             }
         }
+        Some(Rc::clone(watch_config))
+    }
 
-        // Default to fancy if we couldn't determine otherwise
-        // Most modern terminals support Unicode
-        &Self::SYMBOLS_FANCY
+    /// The config of the most specific pattern in `repo_patterns` matching `path`.
+    fn pattern_config_for(&self, path: &Path) -> Option<Rc<WatchConfig>> {
+        self.repo_patterns
+            .iter()
+            .filter_map(|(pattern, watch_config)| {
+                let expanded = expand_home(pattern);
+                match glob::Pattern::new(&expanded) {
+                    Ok(compiled) => Some((expanded, compiled, watch_config)),
+                    Err(e) => {
+                        warn!("Ignoring repo pattern {pattern}: {e}");
+                        None
+                    }
+                }
+            })
+            .filter(|(_, compiled, _)| compiled.matches_path_with(path, PATTERN_MATCH_OPTIONS))
+            .max_by_key(|(expanded, _, _)| (literal_prefix_len(expanded), expanded.len()))
+            .map(|(_, _, watch_config)| Rc::clone(watch_config))
     }
 
-    pub fn empty() -> Self {
-        Self {
-            commit_exclude_git_config: false,
-            commit_author: None,
-            commit_email: None,
-            repos: BTreeMap::new(),
-        }
+    /// The watch that owns `path`, which needn't exist or be canonical. With nested watches,
+    /// e.g. `~/work` and `~/work/app`, the longest watched prefix wins, so `~/work/app/src`
+    /// belongs to `~/work/app` and `~/work/lib` to `~/work`. Prefixes are matched by whole
+    /// path components, `~/work` doesn't own `~/workshop`.
+    pub fn find_watch_root(&self, path: &Path) -> Option<(&String, &Rc<WatchConfig>)> {
+        let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        self.watch_entry_for(&resolved)
     }
 
-    pub fn default_path() -> PathBuf {
-        Self::get_dura_config_home().join("config.toml")
+    /// Whether some watch owns `path`, see `find_watch_root`.
+    pub fn is_watched(&self, path: &Path) -> bool {
+        self.find_watch_root(path).is_some()
     }
 
-    /// Location of all config. By default
-    ///
-    /// Linux   :   $XDG_CONFIG_HOME/dura or $HOME/.config/dura
-    /// macOS   :   $HOME/Library/Application Support
-    /// Windows :   %AppData%\Roaming\dura
-    ///
-    /// This can be overridden by setting DURA_CONFIG_HOME environment variable.
-    fn get_dura_config_home() -> PathBuf {
-        // The environment variable lets us run tests independently, but I'm sure someone will come
-        // up with another reason to use it.
-        if let Ok(env_var) = env::var("DURA_CONFIG_HOME") {
-            if !env_var.is_empty() {
-                return env_var.into();
+    /// `find_watch_root` for a path that's canonical already, e.g. one found by scanning a
+    /// watch, so that hot paths don't resolve it again.
+    pub(crate) fn watch_entry_for(&self, path: &Path) -> Option<(&String, &Rc<WatchConfig>)> {
+        self.repos
+            .iter()
+            .filter(|(root, _)| path.starts_with(root))
+            .max_by_key(|(root, _)| root.len())
+    }
+
+    /// Plain-language explanation of what dura will do for `path`: which watch covers it, who
+    /// backups are signed as and why, what's scanned and where backups go.
+    pub fn describe(&self, path: &Path) -> String {
+        let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        // what the poller goes by, repo_patterns included
+        let (root, watch_config) = match self.watch_entry_for(&resolved) {
+            Some((root, _)) => (root, self.watch_config_for(&resolved).unwrap_or_default()),
+            None => return format!("{} is not watched, dura leaves it alone.\n", path.display()),
+        };
+
+        let mut lines = vec![format!("{} is watched as part of {}.", path.display(), root)];
+        if repo_status::is_unavailable(path) {
+            lines.push(format!(
+                "It's currently {}, dura skips it until it can be resolved again.",
+                repo_status::UNAVAILABLE
+            ));
+        }
+        let repo = Repository::open(path).ok();
+        lines.push(self.describe_identity("Author", &self.commit_author, "user.name", &repo));
+        lines.push(self.describe_identity("Email", &self.commit_email, "user.email", &repo));
+        match repo.as_ref().map(|repo| snapshots::resolve_identity(self, repo)) {
+            Some(Ok((name, email))) => {
+                lines.push(format!("Backups are signed {} <{}>.", name, email))
+            }
+            Some(Err(e)) => lines.push(format!("Backups fail: {}", e.message())),
+            None => (),
+        }
+        lines.push(format!(
+            "Checked for changes every {}s, changed repos are then backed up in the background.",
+            poller::POLL_INTERVAL.as_secs()
+        ));
+        if let Some(interval) = watch_config.min_backup_interval {
+            let interval = humantime::format_duration(interval);
+            lines.push(format!("Backups are at least {} apart.", interval));
+        }
+        if watch_config.include.is_empty() {
+            lines.push("Includes: everything".to_string());
+        } else {
+            lines.push(format!("Includes: {}", watch_config.include.join(", ")));
+            if watch_config.include_overrides_gitignore {
+                lines.push("Included files are backed up even if gitignored.".to_string());
             }
         }
+        if watch_config.exclude.is_empty() {
+            lines.push("Excludes: nothing".to_string());
+        } else {
+            lines.push(format!("Excludes: {}", watch_config.exclude.join(", ")));
+        }
+        lines.push(match watch_config.max_depth {
+            0 => "Only the watched directory itself is backed up.".to_string(),
+            u8::MAX => "Repos are looked for at any depth.".to_string(),
+            max_depth => format!("Repos are looked for up to {} directories deep.", max_depth),
+        });
+        match &watch_config.mirror_path {
+            _ if self.backup_strategy == BackupStrategy::Stash => lines.push(
+                "Backups are added to the repo's stash, HEAD and the working tree are left \
+                 untouched."
+                    .to_string(),
+            ),
+            None => lines.push(
+                "Backups are committed to dura/<commit at HEAD> branches, HEAD and the working \
+                 tree are left untouched."
+                    .to_string(),
+            ),
+            Some(mirror) => lines.push(format!(
+                "Backups are committed to dura/<commit at HEAD> branches in the bare repo {}, \
+                 the repo's own branches, HEAD and working tree are left untouched.",
+                mirror.display()
+            )),
+        }
+        if let Some(hook) = &watch_config.post_backup_hook {
+            lines.push(format!("After each backup, runs: {}", hook));
+        }
+        if let Some(max_backups) = watch_config.max_backups.filter(|&keep| keep > 0) {
+            lines.push(format!("Only the newest {} backups are kept.", max_backups));
+        }
+        if let Some(days) = watch_config.thin_to_daily_after_days {
+            lines.push(format!(
+                "Backups older than {} days are thinned to the first of each day.",
+                days.max(1)
+            ));
+        }
+        if !watch_config.only_branches.is_empty() {
+            lines.push(format!(
+                "Only backs up while on a branch matching {}.",
+                watch_config.only_branches.join(", ")
+            ));
+        }
+        if !watch_config.push_remotes.is_empty() {
+            lines.push(format!(
+                "Each backup is pushed to {}.",
+                watch_config.push_remotes.join(", ")
+            ));
+        }
+        if let Some(limit_mb) = self.max_repo_size_mb {
+            lines.push(format!("Repos whose git dir is over {} MB are skipped.", limit_mb));
+        }
+        if read_only::is_enabled() {
+            lines.push("Read-only mode is on, so nothing will actually be backed up.".to_string());
+        }
 
-        dirs::config_dir()
-            .expect("Could not find your config directory. The default is ~/.config/dura but it can also \
-                be controlled by setting the DURA_CONFIG_HOME environment variable.")
-            .join("dura")
+        let mut description = lines.join("\n");
+        description.push('\n');
+        description
     }
 
-    /// Load Config from default path
-    pub fn load() -> Self {
-        Self::load_file(Self::default_path().as_path()).unwrap_or_else(|_| Self::empty())
+    /// "Author: name (from ...)" for `describe`, following the same order as the resolvers.
+    fn describe_identity(
+        &self,
+        label: &str,
+        configured: &Option<String>,
+        git_key: &str,
+        repo: &Option<Repository>,
+    ) -> String {
+        if let Some(value) = configured {
+            return format!("{}: {} (from dura's config)", label, value);
+        }
+        let from_git = if self.commit_exclude_git_config {
+            None
+        } else {
+            repo.as_ref()
+                .and_then(|repo| repo.config().ok())
+                .and_then(|git_cfg| git_cfg.get_string(git_key).ok())
+        };
+        match (from_git, repo) {
+            (Some(value), _) => format!("{}: {} (from git's {})", label, value, git_key),
+            (None, Some(_)) => format!("{}: not set in dura's or git's config", label),
+            (None, None) => format!(
+                "{}: taken from each repo's git {}, if set, otherwise dura's default",
+                label, git_key
+            ),
+        }
     }
 
-    pub fn load_file(path: &Path) -> Result<Self> {
-        let mut reader = BufReader::new(File::open(path)?);
+    /// Whether the repo at `path` is over `max_repo_size_mb`, and so shouldn't be backed up.
+    pub fn exceeds_size_limit(&self, path: &Path, sizes: &mut RepoSizes) -> bool {
+        self.max_repo_size_mb
+            .is_some_and(|limit_mb| sizes.exceeds(path, limit_mb))
+    }
 
-        let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
+    pub fn git_repos(&self) -> GitRepoIter<'_> {
+        GitRepoIter::new(self)
+    }
 
-        let res = toml::from_slice(buffer.as_slice())?;
-        Ok(res)
+    pub fn count_backups(&self, repo: &Repository) -> BackupSummary {
+        let backups = self.backups_of(repo);
+        backups::summarize(&backups, Local::now(), self.clamp_commit_time)
     }
 
-    /// Save config to disk in ~/.config/dura/config.toml
-    pub fn save(&self) {
-        self.save_to_path(Self::default_path().as_path())
+    pub fn backup_age_histogram(&self, repo: &Repository) -> AgeHistogram {
+        let backups = self.backups_of(repo);
+        backups::age_histogram(&backups, Local::now())
     }
 
-    pub fn create_dir(path: &Path) {
-        if let Some(dir) = path.parent() {
-            create_dir_all(dir)
-                .unwrap_or_else(|_| panic!("Failed to create directory at `{}`.\
-                    Dura stores its configuration in `{}/config.toml`, \
-                    where you can instruct dura to watch patterns of Git repositories, among other things. \
-                    See https://github.com/tkellogg/dura for more information.", dir.display(), path.display()))
+    /// The repo's backups, with the ones in its stash when `backup_strategy` is stash.
+    fn backups_of(&self, repo: &Repository) -> Vec<backups::Backup> {
+        let mut found = backups::find_backups(repo).unwrap_or_default();
+        if self.backup_strategy == BackupStrategy::Stash {
+            found.extend(backups::stash_backups(repo).unwrap_or_default());
         }
+        found
     }
 
-    /// Attempts to create parent dirs, serialize `self` as TOML and write to disk.
-    pub fn save_to_path(&self, path: &Path) {
-        Self::create_dir(path);
+    /// The newest backup of the repo at `path`, and its commit time.
+    ///
+    /// This comes from the runtime cache when possible. The cache goes stale when e.g. dura's
+    /// branches are deleted with plain git, or a backup is made without the daemon, so a cached
+    /// backup that isn't the newest tip of the dura branches anymore is ignored and the history
+    /// walked instead. The cache itself is left to the daemon.
+    pub fn last_backup(
+        &self,
+        path: &str,
+        repo: &Repository,
+        runtime_lock: &RuntimeLock,
+    ) -> Option<(Oid, i64)> {
+        self.cached_last_backup(path, repo, runtime_lock)
+            .or_else(|| self.count_backups(repo).latest())
+    }
 
-        let config_string = match toml::to_string(self) {
-            Ok(v) => v,
-            Err(e) => {
-                println!("Unexpected error when deserializing config: {e}");
-                return;
+    /// How many backups the repo at `path` has. With a count in the runtime cache, only the
+    /// commits made since are walked, see `backups::count_since`, and the cache is updated.
+    /// Backups in the stash are counted too, when that's where they go.
+    pub fn backup_count(
+        &self,
+        path: &str,
+        repo: &Repository,
+        runtime_lock: &mut RuntimeLock,
+    ) -> usize {
+        let cache = runtime_lock.repos.entry(path.to_string()).or_default();
+        let on_refs = match backups::count_since(repo, cache.count_cursor.as_ref()) {
+            Ok((cursor, _)) => {
+                let count = cursor.count;
+                cache.count_cursor = Some(cursor);
+                cache.backups_since_count = 0;
+                count
             }
+            Err(_) => 0,
         };
+        // the stash's reflog is cheap to read, it isn't worth a cursor
+        let in_stash = match self.backup_strategy {
+            BackupStrategy::Stash => backups::stash_backups(repo).map_or(0, |found| found.len()),
+            BackupStrategy::CommitToRef => 0,
+        };
+        on_refs + in_stash
+    }
 
-        match fs::write(path, config_string) {
-            Ok(_) => (),
-            Err(e) => println!("Unable to initialize dura config file: {e}"),
+    /// How many backups the watched repos have, going by the counts `backup_count` left in the
+    /// runtime cache plus the backups recorded since, so it's quick but approximate, e.g. pruned
+    /// backups are still in it. Repos that were never counted are counted with `backup_count`,
+    /// which fills in their cache. Backups in the stash are read from it like `backup_count` does.
+    pub fn total_backups_cached(&self, runtime_lock: &mut RuntimeLock) -> usize {
+        let mut total = 0;
+        for key in self.repos.keys() {
+            if repo_status::is_unavailable(Path::new(key)) {
+                continue;
+            }
+            let Ok(repo) = Repository::open(key) else {
+                continue;
+            };
+            let cached = runtime_lock.repos.get(key).and_then(|cache| {
+                let count = cache.count_cursor.as_ref()?.count;
+                Some(match self.backup_strategy {
+                    BackupStrategy::Stash => {
+                        count + backups::stash_backups(&repo).map_or(0, |found| found.len())
+                    }
+                    BackupStrategy::CommitToRef => count + cache.backups_since_count,
+                })
+            });
+            total += cached.unwrap_or_else(|| self.backup_count(key, &repo, runtime_lock));
         }
+        total
     }
 
-    pub fn set_watch(&mut self, path: String, cfg: WatchConfig) {
-        let abs_path = fs::canonicalize(path).expect("The provided path is not a directory");
-        let abs_path = abs_path
-            .to_str()
-            .expect("The provided path is not valid unicode");
+    /// Like `last_backup`, but only from the runtime cache, or None if it has nothing valid, so
+    /// it never walks the history. Only the dura branches' tips are read to check it.
+    pub fn cached_last_backup(
+        &self,
+        path: &str,
+        repo: &Repository,
+        runtime_lock: &RuntimeLock,
+    ) -> Option<(Oid, i64)> {
+        let oid = runtime_lock
+            .repos
+            .get(path)
+            .and_then(|cache| cache.last_backup.as_deref())
+            .and_then(|id| Oid::from_str(id).ok())?;
+        backups::newest_dura_tip(repo).filter(|(newest, _)| *newest == oid)
+    }
 
-        if self.repos.contains_key(abs_path) {
-            println!("{abs_path} is already being watched")
-        } else {
-            self.repos.insert(abs_path.to_string(), Rc::new(cfg));
-            println!("Started watching {abs_path}")
+    /// Whether a repo went longer than `stale_after` without a backup while it has
+    /// uncommitted changes. `last_backup_time` is the commit time of its newest backup, if any.
+    pub fn backup_is_stale(
+        &self,
+        has_changes: bool,
+        last_backup_time: Option<i64>,
+        now: SystemTime,
+    ) -> bool {
+        let stale_after = match self.stale_after {
+            Some(stale_after) if has_changes => stale_after,
+            _ => return false,
+        };
+        let now = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_secs() as i64);
+        match last_backup_time {
+            Some(time) => now.saturating_sub(time) > stale_after.as_secs() as i64,
+            None => true,
         }
     }
 
-    pub fn set_unwatch(&mut self, path: String) {
-        let abs_path = fs::canonicalize(path).expect("The provided path is not a directory");
-        let abs_path = abs_path
-            .to_str()
-            .expect("The provided path is not valid unicode")
-            .to_string();
+    /// Number of dura backups per ref, keyed by the ref's short name (e.g. `dura/<oid>`).
+    ///
+    /// Each backup is counted once, even if several refs reach it. See
+    /// `backups::find_backups` for which ref it gets attributed to.
+    pub fn count_backups_by_branch(&self, repo: &Repository) -> BTreeMap<String, usize> {
+        backups::count_by_branch(&self.backups_of(repo))
+    }
 
-        match self.repos.remove(&abs_path) {
-            Some(_) => {
-                println!("Stopped watching {abs_path}");
+    pub(crate) fn has_changes(
+        &self,
+        path: &Path,
+        repo: &Repository,
+    ) -> std::result::Result<bool, git2::Error> {
+        Ok(self.dirty_file_count(path, repo)? > 0)
+    }
+
+    /// Whether the repo at `path` is backed up on the branch it's on now, see
+    /// `WatchConfig::only_branches`.
+    pub fn backs_up_current_branch(&self, path: &Path, repo: &Repository) -> bool {
+        self.watch_config_for(path)
+            .unwrap_or_default()
+            .backs_up_branch(snapshots::current_branch(repo).as_deref())
+    }
+
+    /// How many files in the repo have uncommitted changes. Untracked files are included unless
+    /// the watch has `backup_untracked` off. Fails when git can't read the status, e.g. because
+    /// the index is corrupt.
+    pub fn dirty_file_count(
+        &self,
+        path: &Path,
+        repo: &Repository,
+    ) -> std::result::Result<usize, git2::Error> {
+        self.dirty_statuses(path, repo).map(|statuses| statuses.len())
+    }
+
+    fn dirty_statuses<'r>(
+        &self,
+        path: &Path,
+        repo: &'r Repository,
+    ) -> std::result::Result<git2::Statuses<'r>, git2::Error> {
+        let watch_config = self.watch_config_for(path).unwrap_or_default();
+        repo.statuses(Some(&mut watch_config.dirty_status_options()))
+    }
+
+    /// When the oldest dirty file in the repo that was modified after the backup made at `since`
+    /// was modified, going by the files' mtimes. Deleted files have no mtime and are skipped.
+    fn oldest_change_after(
+        &self,
+        path: &Path,
+        repo: &Repository,
+        since: Option<SystemTime>,
+    ) -> std::result::Result<Option<SystemTime>, git2::Error> {
+        let statuses = self.dirty_statuses(path, repo)?;
+        Ok(statuses
+            .iter()
+            .filter_map(|entry| entry.path().map(|file| path.join(file)))
+            .filter_map(|file| file.metadata().and_then(|m| m.modified()).ok())
+            // backup times are whole seconds, so anything in the backup's second is in it
+            .filter(|modified| {
+                since.is_none_or(|since| *modified >= since + Duration::from_secs(1))
+            })
+            .min())
+    }
+
+    /// Status of every watched repo, in path order.
+    ///
+    /// With `status_cache_ttl` set, results are cached in the runtime lock and reused
+    /// while younger than the TTL, as long as the same repos are watched and none of their
+    /// directories were modified after the results were computed.
+    pub fn collect_status(&self) -> Vec<RepoStatus> {
+        if self.status_cache_ttl.is_zero() {
+            return self.collect_status_uncached();
+        }
+
+        let ttl = self.status_cache_ttl;
+        if let Some(cache) = StatusCache::load() {
+            let fresh = SystemTime::now()
+                .duration_since(cache.computed_at)
+                .is_ok_and(|age| age < ttl);
+            let same_repos = cache
+                .statuses
+                .iter()
+                .map(|status| status.path.as_path())
+                .eq(self.repos.keys().map(Path::new));
+            let unmodified = !cache
+                .statuses
+                .iter()
+                .any(|status| status.modified_since(cache.computed_at));
+            if fresh && same_repos && unmodified {
+                return cache.statuses;
             }
-            None => println!("{abs_path} is not being watched"),
         }
+
+        let computed_at = SystemTime::now();
+        let statuses = self.collect_status_uncached();
+        StatusCache {
+            computed_at,
+            statuses: statuses.clone(),
+        }
+        .save();
+        statuses
     }
 
-    pub fn git_repos(&self) -> GitRepoIter {
-        GitRepoIter::new(self)
+    fn collect_status_uncached(&self) -> Vec<RepoStatus> {
+        let runtime_lock = RuntimeLock::load();
+        let now = SystemTime::now();
+        self.repos
+            .keys()
+            .map(|path| {
+                let mut status = RepoStatus::collect(self, Path::new(path));
+                status.open_retry_at = runtime_lock.open_retry_at(Path::new(path), now);
+                status
+            })
+            .collect()
     }
 
-    fn count_backups(&self, repo: &Repository) -> (usize, Option<String>, i64) {
-        let mut backup_count = 0;
-        let mut latest_commit_id = None;
-        let mut latest_time = 0;
+    /// Totals for the status summary. Backups are counted from the cursors in the runtime
+    /// lock, which it doesn't save, see `summary_stats_with`.
+    pub fn summary_stats(&self) -> SummaryStats {
+        self.summary_stats_with(&mut RuntimeLock::load())
+    }
 
-        let mut cmd = std::process::Command::new("git");
-        cmd.current_dir(repo.path().parent().unwrap_or(repo.path()));
-        cmd.args(&["log", "--all", "--format=%H %s"]);
-        
-        if let Ok(output) = cmd.output() {
-            if let Ok(output_str) = String::from_utf8(output.stdout) {
-                for line in output_str.lines() {
-                    if line.ends_with("dura auto-backup") {
-                        backup_count += 1;
-                        if let Some(hash) = line.split_whitespace().next() {
-                            if let Ok(oid) = git2::Oid::from_str(hash) {
-                                if let Ok(commit) = repo.find_commit(oid) {
-                                    let commit_time = commit.time().seconds();
-                                    if commit_time > latest_time {
-                                        latest_time = commit_time;
-                                        latest_commit_id = Some(oid.to_string());
-                                    }
-                                }
-                            }
-                        }
+    /// Like `summary_stats`, but counts backups with the cursors in `runtime_lock` and leaves
+    /// the new ones there, for the daemon to save.
+    pub fn summary_stats_with(&self, runtime_lock: &mut RuntimeLock) -> SummaryStats {
+        let mut stats = SummaryStats {
+            total_repos: self.repos.len(),
+            ..SummaryStats::default()
+        };
+        for path in self.repos.keys() {
+            let repo = match Repository::open(path) {
+                Ok(repo) => repo,
+                Err(_) => {
+                    stats.inaccessible_repos += 1;
+                    continue;
+                }
+            };
+            match self.dirty_file_count(Path::new(path), &repo) {
+                Ok(dirty_files) => {
+                    if dirty_files > 0 {
+                        stats.dirty_repos += 1;
                     }
+                    stats.dirty_files += dirty_files;
                 }
+                Err(_) => stats.inaccessible_repos += 1,
             }
+            stats.total_backups += self.backup_count(path, &repo, runtime_lock);
         }
-        
-        (backup_count, latest_commit_id, latest_time)
+        stats
+    }
+
+    /// Machine-wide rollup of `collect_status`, without any paths or labels, so it can be
+    /// shared as is. Repos whose status can't be read count as clean, like in `collect_status`.
+    pub fn global_uncommitted_summary(&self) -> GlobalSummary {
+        let now = SystemTime::now();
+        let statuses = self.collect_status();
+        let mut summary = GlobalSummary {
+            total_repos: statuses.len(),
+            ..GlobalSummary::default()
+        };
+        let mut oldest: Option<SystemTime> = None;
+        for status in statuses.iter().filter(|status| status.uncommitted_changes) {
+            summary.dirty_repos += 1;
+            let repo = match Repository::open(&status.path) {
+                Ok(repo) => repo,
+                Err(_) => continue,
+            };
+            summary.dirty_files += self.dirty_file_count(&status.path, &repo).unwrap_or(0);
+            if let Ok(Some(changed)) =
+                self.oldest_change_after(&status.path, &repo, status.last_backup)
+            {
+                oldest = Some(oldest.map_or(changed, |oldest| oldest.min(changed)));
+            }
+        }
+        summary.oldest_unbacked_change =
+            oldest.map(|changed| now.duration_since(changed).unwrap_or(Duration::ZERO));
+        summary
+    }
+
+    /// Appends a row of `summary_stats`, stamped with the current time, to the CSV at `path`.
+    /// The header is written only when the file is new or empty, so existing rows are never
+    /// touched. For the daemon, which saves the backup count cursors with it.
+    pub fn stats_over_time(&self, path: &Path) -> io::Result<()> {
+        if read_only::is_enabled() {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "timestamp,total_repos,dirty_repos,total_backups")?;
+        }
+        let mut runtime_lock = RuntimeLock::load();
+        let stats = self.summary_stats_with(&mut runtime_lock);
+        runtime_lock.save();
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        writeln!(
+            file,
+            "{},{},{},{}",
+            timestamp, stats.total_repos, stats.dirty_repos, stats.total_backups
+        )
+    }
+
+    /// Watched repos that share an `origin` remote URL, i.e. the same project cloned more than
+    /// once. Only URLs with at least two repos are included; repos without an origin are left
+    /// out.
+    pub fn group_by_origin(&self) -> BTreeMap<String, Vec<PathBuf>> {
+        let mut groups: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+        for path in self.repos.keys() {
+            let url = Repository::open(path)
+                .and_then(|repo| repo.config())
+                .and_then(|config| config.get_string("remote.origin.url"));
+            if let Ok(url) = url {
+                let url = url.trim_end_matches('/').to_string();
+                groups.entry(url).or_default().push(PathBuf::from(path));
+            }
+        }
+        groups.retain(|_, paths| paths.len() > 1);
+        groups
+    }
+
+    /// Watched repos grouped by their canonical parent directory.
+    pub fn group_by_parent(&self) -> BTreeMap<PathBuf, RepoGroup> {
+        let mut groups: BTreeMap<PathBuf, RepoGroup> = BTreeMap::new();
+        for path in self.repos.keys() {
+            let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+            let parent = path.parent().unwrap_or(&path).to_path_buf();
+            let group = groups.entry(parent).or_default();
+            if let Ok(repo) = Repository::open(&path) {
+                if matches!(self.has_changes(&path, &repo), Ok(true)) {
+                    group.dirty += 1;
+                }
+            }
+            group.repos.push(path);
+        }
+        groups
+    }
+
+    /// Like `print_summary`, but condensed to one line per parent directory, e.g.
+    /// `~/work (12 repos, 3 dirty)`, optionally followed by the group's repos.
+    pub fn print_grouped_summary(&self, list_members: bool) -> io::Result<()> {
+        output::print_checked(|out| self.write_grouped_summary(out, list_members))
     }
 
-    pub fn print_summary(&self) {
-        let symbols = Self::get_symbols();
-        let [ok, modified, error, _warning, _info, _time, _stats, _folder] = symbols;
+    pub fn write_grouped_summary(
+        &self,
+        out: &mut dyn Write,
+        list_members: bool,
+    ) -> io::Result<()> {
+        for (parent, group) in self.group_by_parent() {
+            writeln!(
+                out,
+                "{} ({} repos, {} dirty)",
+                parent.display(),
+                group.repos.len(),
+                group.dirty
+            )?;
+            if list_members {
+                for repo in &group.repos {
+                    writeln!(out, "  {}", repo.display())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The summary in `format`. Human is `print_summary`'s, without counting backups. The
+    /// machine formats are rendered from a single `collect_status`, totals included.
+    pub fn print_summary_as(&self, format: OutputFormat) -> io::Result<()> {
+        output::print_checked(|out| self.write_summary_as(out, format))
+    }
+
+    pub fn write_summary_as(&self, out: &mut dyn Write, format: OutputFormat) -> io::Result<()> {
+        let render = match format {
+            OutputFormat::Human => return self.write_summary(out, false),
+            OutputFormat::Json => output::render_json,
+            OutputFormat::Porcelain => output::render_porcelain,
+        };
+        let statuses = self.collect_status();
+        write!(out, "{}", render(&SummaryStats::of(&statuses), &statuses))
+    }
+
+    /// The status of every watched repo. Counting backups walks each repo's whole history, so
+    /// unless `with_backups` is set that's skipped, and only the newest backup the runtime
+    /// cache knows of is shown.
+    pub fn print_summary(&self, with_backups: bool) -> io::Result<()> {
+        output::print_checked(|out| self.write_summary(out, with_backups))
+    }
+
+    /// What `print_summary` prints, written to `out`. Stops at the first failed write.
+    pub fn write_summary(&self, out: &mut dyn Write, with_backups: bool) -> io::Result<()> {
+        let symbols = self.get_symbols();
+        let [ok, modified, error, warning, _info, _time, _stats, _folder] = symbols;
+        let color = term::use_color();
 
-        println!("Dura Status Summary");
-        println!("-------------------");
+        writeln!(out, "Dura Status Summary")?;
+        writeln!(out, "-------------------")?;
         
         // Add server status at the top
-        let runtime_lock = RuntimeLock::load();
-        match runtime_lock.pid {
-            Some(pid) => {
-                let uptime = runtime_lock.start_time
-                    .and_then(|start| SystemTime::now().duration_since(start).ok())
-                    .map(|duration| {
-                        let days = duration.as_secs() / 86400;
-                        let hours = (duration.as_secs() % 86400) / 3600;
-                        let minutes = (duration.as_secs() % 3600) / 60;
-                        if days > 0 {
-                            format!("{}d {}h", days, hours)
-                        } else if hours > 0 {
-                            format!("{}h {}m", hours, minutes)
-                        } else {
-                            format!("{}m", minutes)
-                        }
-                    })
-                    .unwrap_or_else(|| "unknown time".to_string());
-                println!("Server: Running (PID: {}, Uptime: {})", pid, uptime);
-            },
-            None => println!("Server: Not running"),
+        let mut runtime_lock = RuntimeLock::load();
+        writeln!(out, "Server: {}", runtime_lock.server_status(SystemTime::now()))?;
+        if let Some(total) = runtime_lock.total_runtime(SystemTime::now()) {
+            writeln!(out, "Total runtime (all sessions): {}", database::format_duration(total))?;
         }
-        println!();
+        if runtime_lock.config_drifted(self) {
+            writeln!(
+                out,
+                "{} Config on disk differs from the running daemon's, it applies on the next \
+                 poll.",
+                warning
+            )?;
+        }
+        writeln!(out)?;
 
         let total_repos = self.repos.len();
         let mut total_backups = 0;
         let mut repos_with_changes = 0;
+        let mut total_dirty_files = 0;
         let mut inaccessible_repos = 0;
+        let duplicates = self.group_by_origin();
+        let mut sizes = RepoSizes::new();
 
-        for (path, _config) in &self.repos {
-            let path = PathBuf::from(path);
+        for key in self.repos.keys() {
+            let path = PathBuf::from(key);
+            let name = self.display_name(key);
+            if repo_status::is_unavailable(&path) {
+                inaccessible_repos += 1;
+                let line = format!("{} {} ({})", error, name, repo_status::UNAVAILABLE);
+                writeln!(out, "{}", term::paint(&line, Color::Red, color))?;
+                continue;
+            }
             if !path.exists() {
                 inaccessible_repos += 1;
-                println!("{} {}: Not found", error, path.display());
+                let line = format!("{} {}: Not found", error, name);
+                writeln!(out, "{}", term::paint(&line, Color::Red, color))?;
                 continue;
             }
 
             match Repository::open(&path) {
                 Ok(repo) => {
-                    let has_changes = repo.statuses(Some(git2::StatusOptions::new()
-                        .include_untracked(true)
-                        .include_ignored(false)
-                        .include_unmodified(false)))
-                        .map(|statuses| !statuses.is_empty())
-                        .unwrap_or(false);
-                    
+                    let (dirty_files, status_error) = match self.dirty_file_count(&path, &repo) {
+                        Ok(dirty_files) => (dirty_files, String::new()),
+                        Err(e) => {
+                            inaccessible_repos += 1;
+                            (0, format!(" (status error: {})", e.message()))
+                        }
+                    };
+                    let has_changes = dirty_files > 0;
+
                     if has_changes {
                         repos_with_changes += 1;
                     }
+                    total_dirty_files += dirty_files;
 
-                    let (backup_count, latest_commit_id, latest_time) = self.count_backups(&repo);
-                    total_backups += backup_count;
+                    let (counts, latest) = if with_backups {
+                        let summary = self.count_backups(&repo);
+                        total_backups += summary.count;
+                        let counts =
+                            format!(": {} backups, today: {}", summary.count, summary.today);
+                        (Some((counts, summary.future_dated)), summary.latest())
+                    } else {
+                        (None, self.cached_last_backup(key, &repo, &runtime_lock))
+                    };
+                    let (latest_commit_id, latest_time) = latest
+                        .map_or((None, 0), |(oid, time)| (Some(oid.to_string()), time));
+                    let inactive = !self.backs_up_current_branch(&path, &repo);
+                    // without the counts, a backup missing from the cache may still exist
+                    let stale = !inactive
+                        && (counts.is_some() || latest.is_some())
+                        && self.backup_is_stale(
+                            has_changes,
+                            latest.map(|(_, time)| time),
+                            SystemTime::now(),
+                        );
                     
                     let commit_info = latest_commit_id
                         .map(|id| format!(" [{}]", &id[..7]))
@@ -313,114 +2230,361 @@ impl Config {
                         String::new()
                     };
                     
-                    println!("{}{}: {} backups{}{}{}", 
-                        if has_changes { modified } else { ok },
-                        path.display(),
-                        backup_count,
+                    let also_watched: String = duplicates
+                        .values()
+                        .filter(|paths| paths.contains(&path))
+                        .flatten()
+                        .filter(|other| **other != path)
+                        .map(|other| format!(" (also watched at {})", other.display()))
+                        .collect();
+                    let head_state = snapshots::head_state(&repo);
+                    let state = match (head_state, RepoState::from(repo.state())) {
+                        (HeadState::Unborn, _) => " (no commits yet)".to_string(),
+                        (HeadState::Broken(target), _) => format!(" (broken HEAD: {})", target),
+                        (HeadState::Born, RepoState::Normal) => String::new(),
+                        (HeadState::Born, state) => format!(" ({} in progress)", state),
+                    };
+                    let read_only = repo_status::is_read_only(&repo)
+                        || runtime_lock.read_only_until(&path, SystemTime::now()).is_some();
+                    let skipped = if read_only {
+                        format!(" ({})", repo_status::READ_ONLY)
+                    } else if snapshots::index_locked(&repo) {
+                        " (git busy)".to_string()
+                    } else if self.exceeds_size_limit(&path, &mut sizes) {
+                        " (skipped: exceeds size limit)".to_string()
+                    } else if inactive {
+                        " (inactive on this branch)".to_string()
+                    } else {
+                        String::new()
+                    };
+
+                    let line = format!("{}{}{}{}{}{}{}{}{}{}{}", 
+                        if !status_error.is_empty() {
+                            error
+                        } else if has_changes {
+                            modified
+                        } else {
+                            ok
+                        },
+                        name,
+                        counts.as_ref().map_or("", |(counts, _)| counts.as_str()),
                         commit_info,
                         time_info,
-                        if has_changes { " (uncommitted changes)" } else { "" }
+                        if has_changes { " (uncommitted changes)" } else { "" },
+                        if stale { " (stale)" } else { "" },
+                        status_error,
+                        state,
+                        skipped,
+                        also_watched
                     );
+                    let line_color = if !status_error.is_empty() || stale {
+                        Color::Red
+                    } else if has_changes {
+                        Color::Yellow
+                    } else {
+                        Color::Green
+                    };
+                    writeln!(out, "{}", term::paint(&line, line_color, color))?;
+                    let future_dated = counts.map_or(0, |(_, future_dated)| future_dated);
+                    if future_dated > 0 {
+                        writeln!(
+                            out,
+                            "  {} {} backups are dated in the future, check the system clock",
+                            warning, future_dated
+                        )?;
+                    }
                 }
                 Err(_) => {
                     inaccessible_repos += 1;
-                    println!("{} {}: Not a git repository", error, path.display());
+                    let retry = runtime_lock
+                        .open_retry_at(&path, SystemTime::now())
+                        .and_then(|at| at.duration_since(SystemTime::now()).ok())
+                        .map(|wait| format!(" (retrying in {}s)", wait.as_secs().max(1)))
+                        .unwrap_or_default();
+                    let line = format!("{} {}: Not a git repository{}", error, name, retry);
+                    writeln!(out, "{}", term::paint(&line, Color::Red, color))?;
                 }
             }
         }
 
-        println!("\nOverall Status:");
-        println!("Watching {} repositories ({} accessible)", 
+        writeln!(out, "\nOverall Status:")?;
+        writeln!(out, "Watching {} repositories ({} accessible)", 
                 total_repos, 
-                total_repos - inaccessible_repos);
-        println!("Total backups: {}", total_backups);
+                total_repos - inaccessible_repos)?;
+        if with_backups {
+            writeln!(out, "Total backups: {}", total_backups)?;
+        } else {
+            let cached = self.total_backups_cached(&mut runtime_lock);
+            writeln!(out, "Total backups: {} (cached)", cached)?;
+        }
         if repos_with_changes > 0 {
-            println!("Repositories with uncommitted changes: {}", repos_with_changes);
+            writeln!(out, "Repositories with uncommitted changes: {}", repos_with_changes)?;
+            writeln!(out, "Files with uncommitted changes: {}", total_dirty_files)?;
         }
         if inaccessible_repos > 0 {
-            println!("Inaccessible repositories: {}", inaccessible_repos);
+            writeln!(out, "Inaccessible repositories: {}", inaccessible_repos)?;
         }
+
+        Ok(())
     }
 
-    pub fn print_detailed_info(&self) {
-        let symbols = Self::get_symbols();
-        let [ok, modified, error, warning, info, time, stats, folder] = symbols;
+    /// Details of the watched repos that need attention, see `needs_attention`, or of all of
+    /// them with `all`.
+    pub fn print_detailed_info(&self, all: bool) -> io::Result<()> {
+        output::print_checked(|out| self.write_detailed_info(out, all))
+    }
 
-        for (path, config) in &self.repos {
-            let path = PathBuf::from(path);
-            println!("{} {}", folder, path.display());
+    pub fn write_detailed_info(&self, out: &mut dyn Write, all: bool) -> io::Result<()> {
+        let runtime_lock = RuntimeLock::load();
 
-            if !path.exists() {
-                println!("  {} Path does not exist", error);
-                continue;
+        let mut omitted = 0;
+        for (key, config) in &self.repos {
+            if all || self.needs_attention(Path::new(key)) {
+                self.write_repo_detail(out, key, config, &runtime_lock)?;
+            } else {
+                omitted += 1;
             }
+        }
+        if omitted > 0 {
+            writeln!(out, "{} clean repositories not shown, see --all", omitted)?;
+        }
 
-            match Repository::open(&path) {
-                Ok(repo) => {
-                    println!("  {} Valid Git repository", ok);
-                    
-                    match repo.statuses(Some(git2::StatusOptions::new()
-                        .include_untracked(true)
-                        .include_ignored(false)
-                        .include_unmodified(false))) 
-                    {
-                        Ok(statuses) => {
-                            let mut has_changes = false;
-                            for entry in statuses.iter() {
-                                let status = entry.status();
-                                if status.is_wt_new() || 
-                                   status.is_wt_modified() || 
-                                   status.is_wt_deleted() ||
-                                   status.is_index_new() ||
-                                   status.is_index_modified() ||
-                                   status.is_index_deleted() {
-                                    if let Some(path) = entry.path() {
-                                        println!("  {} Change detected: {} ({:?})", 
-                                               modified, path, status);
-                                    }
-                                    has_changes = true;
-                                }
-                            }
+        Ok(())
+    }
+
+    /// Like `print_detailed_info`, but only for the watch roots covering `paths`, in the order
+    /// given. A path can also be a watch's label. Paths that no watch covers are reported as
+    /// such.
+    pub fn print_detailed_info_for_many(&self, paths: &[PathBuf]) -> io::Result<()> {
+        output::print_checked(|out| self.write_detailed_info_for_many(out, paths))
+    }
+
+    pub fn write_detailed_info_for_many(
+        &self,
+        out: &mut dyn Write,
+        paths: &[PathBuf],
+    ) -> io::Result<()> {
+        let [_ok, _modified, error, ..] = self.get_symbols();
+        let color = term::use_color();
+        let runtime_lock = RuntimeLock::load();
+
+        for path in paths {
+            let labeled = path.to_str().and_then(|label| self.find_by_label(label));
+            match labeled.or_else(|| self.find_watch_root(path)) {
+                Some((key, config)) => {
+                    self.write_repo_detail(out, key, config, &runtime_lock)?
+                }
+                None => {
+                    let line = format!("{} {}: Not watched\n", error, path.display());
+                    writeln!(out, "{}", term::paint(&line, Color::Red, color))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the repo at `path` is anything but clean and backup-ready: it can't be read, has
+    /// uncommitted changes, no commits, an operation in progress or no usable commit identity.
+    pub fn needs_attention(&self, path: &Path) -> bool {
+        let repo = match Repository::open(path) {
+            Ok(repo) if path.exists() && !repo_status::is_unavailable(path) => repo,
+            _ => return true,
+        };
+        snapshots::head_state(&repo) != HeadState::Born
+            || repo.state() != git2::RepositoryState::Clean
+            || !matches!(self.dirty_file_count(path, &repo), Ok(0))
+            || snapshots::resolve_identity(self, &repo).is_err()
+    }
+
+    fn write_repo_detail(
+        &self,
+        out: &mut dyn Write,
+        key: &str,
+        config: &WatchConfig,
+        runtime_lock: &RuntimeLock,
+    ) -> io::Result<()> {
+        let symbols = self.get_symbols();
+        let [ok, modified, error, warning, info, time, stats, folder] = symbols;
+        let color = term::use_color();
+        let paint = |line: String, line_color| term::paint(&line, line_color, color);
+
+        let path = PathBuf::from(key);
+        writeln!(out, "{} {}", folder, self.display_name(key))?;
+
+        if repo_status::is_unavailable(&path) {
+            let line = format!("  {} Path is {}", error, repo_status::UNAVAILABLE);
+            writeln!(out, "{}", paint(line, Color::Red))?;
+            return Ok(());
+        }
+        if !path.exists() {
+            let line = format!("  {} Path does not exist", error);
+            writeln!(out, "{}", paint(line, Color::Red))?;
+            return Ok(());
+        }
 
-                            if has_changes {
-                                println!("  {} Has uncommitted changes", warning);
-                            } else {
-                                println!("  {} No uncommitted changes", ok);
+        match Repository::open(&path) {
+            Ok(repo) => {
+                let line = format!("  {} Valid Git repository", ok);
+                writeln!(out, "{}", paint(line, Color::Green))?;
+                match snapshots::head_state(&repo) {
+                    HeadState::Born => {}
+                    HeadState::Unborn => writeln!(out, "  {} No commits yet", info)?,
+                    HeadState::Broken(target) => {
+                        let outcome = if self.backup_broken_head_repos {
+                            "backups go to dura/unborn"
+                        } else {
+                            "backups are skipped"
+                        };
+                        let line = format!(
+                            "  {} Broken HEAD: {} doesn't exist, {}",
+                            error, target, outcome
+                        );
+                        writeln!(out, "{}", paint(line, Color::Red))?;
+                    }
+                }
+                let state = RepoState::from(repo.state());
+                if state != RepoState::Normal {
+                    writeln!(out, "  {} Operation in progress: {}", warning, state)?;
+                }
+                // the arrows go with the emoji, terminals that can't show one can't show both
+                let arrows = self.fancy_symbols(&term::TermEnv::detect());
+                match Upstream::of_head(&repo) {
+                    Some(upstream) if arrows => writeln!(
+                        out,
+                        "  {} Upstream: {} ↑{} ↓{}",
+                        info, upstream.name, upstream.ahead, upstream.behind
+                    )?,
+                    Some(upstream) => writeln!(
+                        out,
+                        "  {} Upstream: {} (ahead {}, behind {})",
+                        info, upstream.name, upstream.ahead, upstream.behind
+                    )?,
+                    None => writeln!(out, "  {} No upstream branch", info)?,
+                }
+                match snapshots::resolve_identity(self, &repo) {
+                    Ok((name, email)) => {
+                        writeln!(out, "  {} Commit identity: {} <{}>", info, name, email)?
+                    }
+                    Err(e) => writeln!(out, "  {} Commit identity: {}", warning, e.message())?,
+                }
+                if let Some(last_error) = runtime_lock.last_error(&path) {
+                    let ago = SystemTime::now()
+                        .duration_since(last_error.at)
+                        .unwrap_or_default();
+                    let line = format!(
+                        "  {} Last error: {} ({} ago)",
+                        warning,
+                        last_error.message,
+                        database::format_duration(ago)
+                    );
+                    writeln!(out, "{}", paint(line, Color::Red))?;
+                }
+                
+                match self.dirty_statuses(&path, &repo) {
+                    Ok(statuses) => {
+                        let mut has_changes = false;
+                        for entry in statuses.iter() {
+                            let status = entry.status();
+                            if status.is_wt_new() || 
+                               status.is_wt_modified() || 
+                               status.is_wt_deleted() ||
+                               status.is_index_new() ||
+                               status.is_index_modified() ||
+                               status.is_index_deleted() {
+                                if let Some(path) = entry.path() {
+                                    let line = format!("  {} Change detected: {} ({:?})",
+                                           modified, path, status);
+                                    writeln!(out, "{}", paint(line, Color::Yellow))?;
+                                }
+                                has_changes = true;
                             }
                         }
-                        Err(e) => println!("  {} Unable to check repository status: {}", 
-                                         warning, e),
-                    }
 
-                    let (backup_count, latest_commit_id, latest_time) = self.count_backups(&repo);
-                    if backup_count > 0 {
-                        if let Some(id) = latest_commit_id {
-                            let time_sys = SystemTime::UNIX_EPOCH + 
-                                     Duration::from_secs(latest_time as u64);
-                            let datetime: DateTime<Local> = time_sys.into();
-                            println!("  {} Last backup: {} ({})", 
-                                   time,
-                                   datetime.format("%Y-%m-%d %H:%M:%S"),
-                                   &id[..7]);
+                        if has_changes {
+                            let throttled = self
+                                .next_backup_eta_with(&path, runtime_lock)
+                                .filter(|eta| !eta.is_zero())
+                                .map(|eta| {
+                                    // rounded up, "in 0m" would read like it's due now
+                                    let minutes = eta.as_secs().div_ceil(60);
+                                    format!(" (next backup in {}m)", minutes)
+                                })
+                                .unwrap_or_default();
+                            let line = format!("  {} Has uncommitted changes{}", warning,
+                                               throttled);
+                            writeln!(out, "{}", paint(line, Color::Yellow))?;
+                        } else {
+                            let line = format!("  {} No uncommitted changes", ok);
+                            writeln!(out, "{}", paint(line, Color::Green))?;
                         }
-                        println!("  {} Total backups: {}", stats, backup_count);
-                    } else {
-                        println!("  {} No backups found", info);
                     }
+                    Err(e) => writeln!(out, "  {} Unable to check repository status: {}", 
+                                     warning, e)?,
+                }
 
-                    // Print watch configuration
-                    println!("  Watch Configuration:");
-                    if config.include.is_empty() {
-                        println!("    Include: All files");
-                    } else {
-                        println!("    Include: {:?}", config.include);
+                // walked once, the count, branches and ages below all come from it
+                let found = self.backups_of(&repo);
+                let now = Local::now();
+                let summary = backups::summarize(&found, now, self.clamp_commit_time);
+                let last_backup = summary.latest();
+                let BackupSummary {
+                    count: backup_count,
+                    future_dated,
+                    ..
+                } = summary;
+                if future_dated > 0 {
+                    writeln!(
+                        out,
+                        "  {} {} backups are dated in the future, check the system clock",
+                        warning, future_dated
+                    )?;
+                }
+                if backup_count > 0 {
+                    if let Some((id, latest_time)) = last_backup {
+                        let id = id.to_string();
+                        let time_sys = SystemTime::UNIX_EPOCH + 
+                                 Duration::from_secs(latest_time as u64);
+                        let datetime: DateTime<Local> = time_sys.into();
+                        writeln!(out, "  {} Last backup: {} ({})", 
+                               time,
+                               datetime.format("%Y-%m-%d %H:%M:%S"),
+                               &id[..7])?;
+                    }
+                    writeln!(out, "  {} Total backups: {}", stats, backup_count)?;
+                    writeln!(out, "  Backups by branch:")?;
+                    for (branch, count) in backups::count_by_branch(&found) {
+                        writeln!(out, "    {}: {}", branch, count)?;
+                    }
+                    writeln!(out, "  Backup age:")?;
+                    let histogram = backups::age_histogram(&found, now);
+                    let widest = histogram.buckets().iter().map(|(_, n)| *n).max().unwrap_or(0);
+                    for (label, count) in histogram.buckets() {
+                        // scaled so the biggest bucket is 20 characters wide
+                        let bar = "#".repeat((count * 20).div_ceil(widest.max(1)));
+                        writeln!(out, "    {:<9} | {:<20} {}", label, bar, count)?;
                     }
-                    println!("    Max depth: {}\n", config.max_depth);
+                } else {
+                    writeln!(out, "  {} No backups found", info)?;
                 }
-                Err(e) => {
-                    println!("  {} Not a valid git repository: {}\n", error, e);
+
+                // Print watch configuration
+                writeln!(out, "  Watch Configuration:")?;
+                if config.include.is_empty() {
+                    writeln!(out, "    Include: All files")?;
+                } else {
+                    writeln!(out, "    Include: {:?}", config.include)?;
+                }
+                if let Some(hook) = &config.post_backup_hook {
+                    writeln!(out, "    Post-backup hook: {}", hook)?;
                 }
+                writeln!(out, "    Max depth: {}\n", config.max_depth)?;
+            }
+            Err(e) => {
+                let line = format!("  {} Not a valid git repository: {}\n", error, e);
+                writeln!(out, "{}", paint(line, Color::Red))?;
             }
         }
+        Ok(())
     }
 }
@@ -1,20 +1,358 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::fs::{create_dir_all, File};
 use std::io::Result;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 use std::{env, fs, io};
-use std::time::SystemTime;
 
 use serde::{Deserialize, Serialize};
+use tracing::info;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+use crate::backups::CountCursor;
+use crate::config::Config;
+use crate::read_only;
+use crate::repo_status::RepoStatus;
+use crate::snapshots::CaptureStatus;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct RuntimeLock {
     pub pid: Option<u32>,
     pub start_time: Option<SystemTime>,
+    /// Refreshed by the daemon on every poll, to tell a wedged daemon from a working one
+    #[serde(default)]
+    pub last_heartbeat: Option<SystemTime>,
+    /// How long all earlier daemon sessions ran, added up whenever one ends, see `end_session`
+    #[serde(default)]
+    pub accumulated_runtime: Option<Duration>,
+    /// What dura knows about each repo, keyed by the repo's path
+    #[serde(default)]
+    pub repos: BTreeMap<String, RepoCache>,
+    /// `Config::content_hash` of the config the daemon loaded last, see `config_drifted`
+    #[serde(default)]
+    pub config_hash: Option<String>,
+}
+
+/// Latest `Config::collect_status` results, see `status_cache_ttl`. Kept in a file of its own,
+/// since it's written by commands that only read everything else, while the daemon may be
+/// saving the runtime lock.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct StatusCache {
+    pub computed_at: SystemTime,
+    pub statuses: Vec<RepoStatus>,
+}
+
+impl StatusCache {
+    pub fn default_path() -> PathBuf {
+        RuntimeLock::get_dura_cache_home().join("status.json")
+    }
+
+    /// The saved cache, None if there's none or it can't be read.
+    pub fn load() -> Option<Self> {
+        let reader = io::BufReader::new(File::open(Self::default_path()).ok()?);
+        serde_json::from_reader(reader).ok()
+    }
+
+    /// Saves the cache, if dura isn't read-only. It's only a cache, so failing to is fine.
+    pub fn save(&self) {
+        if read_only::is_enabled() {
+            return;
+        }
+        let path = Self::default_path();
+        RuntimeLock::create_dir(&path);
+        if let Ok(json) = serde_json::to_string(self) {
+            let _ = fs::write(path, json);
+        }
+    }
+}
+
+/// How long the daemon can go without a heartbeat before it's reported as stale. It beats on
+/// every poll, so this allows for quite a few slow polls.
+pub const HEARTBEAT_STALE_AFTER: Duration = Duration::from_secs(60);
+
+/// How long a repo that failed to open is skipped for. Doubles with each failure in a row, up
+/// to `OPEN_RETRY_MAX`.
+pub const OPEN_RETRY_BASE: Duration = Duration::from_secs(10);
+
+/// Longest a repo that keeps failing to open is skipped for, so it's still picked up again
+/// reasonably soon once e.g. its mount comes back.
+pub const OPEN_RETRY_MAX: Duration = Duration::from_secs(10 * 60);
+
+/// How many errors an `ErrorRing` keeps.
+pub const RECENT_ERRORS: usize = 5;
+
+/// Per-repo runtime state. Everything in here is only a cache, it can always be rebuilt from
+/// the repo itself.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct RepoCache {
+    /// Hash of the newest dura backup
+    pub last_backup: Option<String>,
+    /// Commit time of `last_backup`, in seconds since the unix epoch
+    pub last_backup_time: Option<i64>,
+    /// Times in a row the repo couldn't be opened, see `RuntimeLock::record_open_failure`
+    #[serde(default)]
+    pub open_failures: u32,
+    /// Until when the repo is skipped after failing to open
+    #[serde(default)]
+    pub open_retry_at: Option<SystemTime>,
+    /// Where the last backup count left off, so the next one only walks new commits
+    #[serde(default)]
+    pub count_cursor: Option<CountCursor>,
+    /// Backups recorded since `count_cursor` was taken, so the cached count keeps up with
+    /// them without a walk, see `Config::total_backups_cached`
+    #[serde(default)]
+    pub backups_since_count: usize,
+    /// Whether the repo had uncommitted changes when the daemon last looked, see
+    /// `RuntimeLock::record_dirty`. None until it has looked.
+    #[serde(default)]
+    pub dirty: Option<bool>,
+    /// The last backup errors, oldest first
+    #[serde(default)]
+    pub recent_errors: ErrorRing,
+    /// Until when the repo is skipped after a backup failed to write to it, see
+    /// `RuntimeLock::record_read_only`
+    #[serde(default)]
+    pub read_only_until: Option<SystemTime>,
+}
+
+/// A backup error, and when it happened.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct RecentError {
+    pub at: SystemTime,
+    pub message: String,
+}
+
+/// The last `RECENT_ERRORS` errors of a repo. Older ones are dropped as new ones come in, so a
+/// repo that fails on every poll doesn't grow the runtime lock.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+#[serde(transparent)]
+pub struct ErrorRing {
+    errors: VecDeque<RecentError>,
+}
+
+impl ErrorRing {
+    pub fn push(&mut self, at: SystemTime, message: impl Into<String>) {
+        if self.errors.len() == RECENT_ERRORS {
+            self.errors.pop_front();
+        }
+        self.errors.push_back(RecentError {
+            at,
+            message: message.into(),
+        });
+    }
+
+    pub fn latest(&self) -> Option<&RecentError> {
+        self.errors.back()
+    }
+
+    /// Oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &RecentError> {
+        self.errors.iter()
+    }
 }
 
 impl RuntimeLock {
     pub fn empty() -> Self {
-        Self { pid: None, start_time: None }
+        Self {
+            pid: None,
+            start_time: None,
+            last_heartbeat: None,
+            accumulated_runtime: None,
+            repos: BTreeMap::new(),
+            config_hash: None,
+        }
+    }
+
+    /// Remembers `status` as the newest backup of the repo at `path`.
+    pub fn record_backup(&mut self, path: &Path, status: &CaptureStatus) {
+        let time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let entry = self
+            .repos
+            .entry(path.to_string_lossy().to_string())
+            .or_default();
+        entry.last_backup = Some(status.commit_hash.clone());
+        entry.last_backup_time = Some(time);
+        entry.read_only_until = None;
+        entry.backups_since_count += 1;
+    }
+
+    /// Drops what's cached about repos that no watch in `cfg` covers anymore, e.g. after
+    /// `dura unwatch`. Returns how many were dropped.
+    pub fn purge_unwatched(&mut self, cfg: &Config) -> usize {
+        let before = self.repos.len();
+        self.repos
+            .retain(|path, _| cfg.watch_entry_for(Path::new(path)).is_some());
+        before - self.repos.len()
+    }
+
+    /// Remembers that the repo at `path` couldn't be opened at `now`, so it's skipped for a
+    /// while instead of failing on every poll. Returns how long it's skipped for.
+    pub fn record_open_failure(&mut self, path: &Path, now: SystemTime) -> Duration {
+        let entry = self
+            .repos
+            .entry(path.to_string_lossy().to_string())
+            .or_default();
+        entry.open_failures = entry.open_failures.saturating_add(1);
+        let doublings = (entry.open_failures - 1).min(31);
+        let retry_in = OPEN_RETRY_BASE
+            .saturating_mul(1 << doublings)
+            .min(OPEN_RETRY_MAX);
+        entry.open_retry_at = Some(now + retry_in);
+        retry_in
+    }
+
+    /// Forgets the open failures of the repo at `path`. Returns whether there were any.
+    pub fn record_open_success(&mut self, path: &Path) -> bool {
+        match self.repos.get_mut(path.to_string_lossy().as_ref()) {
+            Some(entry) if entry.open_failures > 0 => {
+                entry.open_failures = 0;
+                entry.open_retry_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Remembers whether the repo at `path` has uncommitted changes. Returns whether it just
+    /// went from clean to dirty, which is only once per such change. A repo that's dirty the
+    /// first time it's recorded wasn't seen clean, so that's not a change.
+    pub fn record_dirty(&mut self, path: &Path, dirty: bool) -> bool {
+        let entry = self
+            .repos
+            .entry(path.to_string_lossy().to_string())
+            .or_default();
+        let was_clean = entry.dirty == Some(false);
+        entry.dirty = Some(dirty);
+        was_clean && dirty
+    }
+
+    /// Whether the repo at `path` had uncommitted changes when `record_dirty` was last called
+    /// for it, None if it never was.
+    pub fn dirty_state(&self, path: &Path) -> Option<bool> {
+        self.repos.get(path.to_string_lossy().as_ref())?.dirty
+    }
+
+    /// Remembers that backing up the repo at `path` failed at `at` with `message`, see
+    /// `RepoCache::recent_errors`.
+    pub fn record_error(&mut self, path: &Path, at: SystemTime, message: impl Into<String>) {
+        self.repos
+            .entry(path.to_string_lossy().to_string())
+            .or_default()
+            .recent_errors
+            .push(at, message);
+    }
+
+    /// Remembers that a backup of the repo at `path` couldn't be written at `now`, e.g. because
+    /// it's on a read-only mount, so it's skipped for `OPEN_RETRY_MAX` instead of failing on
+    /// every poll.
+    pub fn record_read_only(&mut self, path: &Path, now: SystemTime) {
+        self.repos
+            .entry(path.to_string_lossy().to_string())
+            .or_default()
+            .read_only_until = Some(now + OPEN_RETRY_MAX);
+    }
+
+    /// Until when the repo at `path` is skipped at `now`, after a backup failed to write to it.
+    pub fn read_only_until(&self, path: &Path, now: SystemTime) -> Option<SystemTime> {
+        self.repos
+            .get(path.to_string_lossy().as_ref())
+            .and_then(|entry| entry.read_only_until)
+            .filter(|until| *until > now)
+    }
+
+    /// The newest error of the repo at `path`, if it had any.
+    pub fn last_error(&self, path: &Path) -> Option<&RecentError> {
+        self.repos
+            .get(path.to_string_lossy().as_ref())
+            .and_then(|entry| entry.recent_errors.latest())
+    }
+
+    /// When the repo at `path` is tried again, if it's still skipped at `now` after failing to
+    /// open.
+    pub fn open_retry_at(&self, path: &Path, now: SystemTime) -> Option<SystemTime> {
+        self.repos
+            .get(path.to_string_lossy().as_ref())
+            .and_then(|entry| entry.open_retry_at)
+            .filter(|retry_at| *retry_at > now)
+    }
+
+    /// e.g. "Running (PID: 42, Uptime: 3h 5m)", or "Running but stale (...)" when the daemon
+    /// hasn't sent a heartbeat for `HEARTBEAT_STALE_AFTER`.
+    pub fn server_status(&self, now: SystemTime) -> String {
+        let pid = match self.pid {
+            Some(pid) => pid,
+            None => return "Not running".to_string(),
+        };
+        match self.heartbeat_age(now) {
+            Some(age) if self.is_stale(now) => format!(
+                "Running but stale (last heartbeat {} ago, PID: {})",
+                format_duration(age),
+                pid
+            ),
+            _ => {
+                let uptime = self
+                    .start_time
+                    .and_then(|start| now.duration_since(start).ok())
+                    .map(format_duration)
+                    .unwrap_or_else(|| "unknown time".to_string());
+                format!("Running (PID: {}, Uptime: {})", pid, uptime)
+            }
+        }
+    }
+
+    /// Whether a daemon is running with a different config than `config`, e.g. because it was
+    /// edited since the daemon loaded it.
+    pub fn config_drifted(&self, config: &Config) -> bool {
+        self.pid.is_some()
+            && self
+                .config_hash
+                .as_ref()
+                .is_some_and(|hash| *hash != config.content_hash())
+    }
+
+    /// Marks the daemon as stopped at `now`, adding the session that started at `start_time`
+    /// to `accumulated_runtime`. The session ends at its last heartbeat, if that's earlier, so
+    /// the time after a crash isn't counted.
+    pub fn end_session(&mut self, now: SystemTime) {
+        if let Some(start) = self.start_time.take() {
+            let end = self
+                .last_heartbeat
+                .map_or(now, |heartbeat| heartbeat.min(now));
+            let session = end.duration_since(start).unwrap_or_default();
+            self.accumulated_runtime = Some(self.accumulated_runtime.unwrap_or_default() + session);
+        }
+        self.pid = None;
+    }
+
+    /// How long the daemon ran over all sessions, including the current one if it's running.
+    /// None if it never ran, as far as the lock knows.
+    pub fn total_runtime(&self, now: SystemTime) -> Option<Duration> {
+        let current = self
+            .start_time
+            .filter(|_| self.pid.is_some())
+            .map(|start| now.duration_since(start).unwrap_or_default());
+        match (self.accumulated_runtime, current) {
+            (None, None) => None,
+            (accumulated, current) => {
+                Some(accumulated.unwrap_or_default() + current.unwrap_or_default())
+            }
+        }
+    }
+
+    /// Whether the daemon's pid is set, but it hasn't sent a heartbeat for
+    /// `HEARTBEAT_STALE_AFTER`. A lock without any heartbeat is never stale, older daemons
+    /// didn't send them.
+    pub fn is_stale(&self, now: SystemTime) -> bool {
+        self.pid.is_some()
+            && self
+                .heartbeat_age(now)
+                .is_some_and(|age| age > HEARTBEAT_STALE_AFTER)
+    }
+
+    fn heartbeat_age(&self, now: SystemTime) -> Option<Duration> {
+        self.last_heartbeat
+            .and_then(|heartbeat| now.duration_since(heartbeat).ok())
     }
 
     pub fn default_path() -> PathBuf {
@@ -28,7 +366,7 @@ impl RuntimeLock {
     /// Windows :   %AppData%\Local\dura
     ///
     /// This can be overridden by setting DURA_CACHE_HOME environment variable.
-    fn get_dura_cache_home() -> PathBuf {
+    pub(crate) fn get_dura_cache_home() -> PathBuf {
         // The environment variable lets us run tests independently, but I'm sure someone will come
         // up with another reason to use it.
         if let Ok(env_var) = env::var("DURA_CACHE_HOME") {
@@ -75,9 +413,30 @@ impl RuntimeLock {
 
     /// Attempts to create parent dirs, serialize `self` as JSON and write to disk.
     pub fn save_to_path(&self, path: &Path) {
+        if read_only::is_enabled() {
+            info!(
+                "Saving runtime lock to {} skipped (read-only)",
+                path.display()
+            );
+            return;
+        }
         Self::create_dir(path);
 
         let json = serde_json::to_string(self).unwrap();
         fs::write(path, json).unwrap()
     }
 }
+
+/// Coarse, human-readable duration: "2d 3h", "3h 5m" or "5m"
+pub fn format_duration(duration: Duration) -> String {
+    let days = duration.as_secs() / 86400;
+    let hours = (duration.as_secs() % 86400) / 3600;
+    let minutes = (duration.as_secs() % 3600) / 60;
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
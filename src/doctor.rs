@@ -0,0 +1,88 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::config::LoadSource;
+
+/// Everything `dura doctor` knows about this installation, see `Config::doctor`. Meant to be
+/// pasted into bug reports as is.
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub dura_version: String,
+    pub git2_version: String,
+    pub libgit2_version: String,
+    pub config: LoadSource,
+    pub runtime_lock: RuntimeLockCheck,
+    pub repos: Vec<RepoCheck>,
+}
+
+/// The runtime lock as the daemon left it.
+#[derive(Debug, Clone, Serialize)]
+pub struct RuntimeLockCheck {
+    pub path: PathBuf,
+    /// Why the lock couldn't be read. A missing lock isn't an error, the server just never ran.
+    pub error: Option<String>,
+    pub pid: Option<u32>,
+    /// The daemon's pid is set but it stopped sending heartbeats
+    pub stale: bool,
+    pub server_status: String,
+}
+
+/// Whether a watched repo can be opened.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepoCheck {
+    pub path: PathBuf,
+    pub error: Option<String>,
+}
+
+/// Every path dura uses, after `DURA_CONFIG_HOME` and `DURA_CACHE_HOME` are applied, see
+/// `Config::debug_paths`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolvedPaths {
+    pub config_home: PathBuf,
+    pub config_file: PathBuf,
+    pub cache_home: PathBuf,
+    pub runtime_lock: PathBuf,
+    pub repos: Vec<ResolvedRepoPaths>,
+}
+
+/// Where a watched repo really is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ResolvedRepoPaths {
+    /// The repo's key in the config's `repos`
+    pub key: String,
+    /// None when the path can't be resolved, e.g. because it's gone
+    pub canonical: Option<PathBuf>,
+    /// None when the path isn't a git repo
+    pub git_dir: Option<PathBuf>,
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "dura {} (git2 {}, libgit2 {})",
+            self.dura_version, self.git2_version, self.libgit2_version
+        )?;
+        writeln!(f, "{}", self.config)?;
+        match &self.runtime_lock.error {
+            None => writeln!(f, "Runtime lock: {}", self.runtime_lock.path.display())?,
+            Some(e) => writeln!(
+                f,
+                "Runtime lock: unable to load {} ({})",
+                self.runtime_lock.path.display(),
+                e
+            )?,
+        }
+        writeln!(f, "Server: {}", self.runtime_lock.server_status)?;
+        writeln!(f, "Repos: {}", self.repos.len())?;
+        for repo in &self.repos {
+            match &repo.error {
+                None => writeln!(f, "  OK    {}", repo.path.display())?,
+                Some(e) => writeln!(f, "  FAIL  {}: {}", repo.path.display(), e)?,
+            }
+        }
+        Ok(())
+    }
+}
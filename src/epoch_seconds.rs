@@ -0,0 +1,53 @@
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Writes `time` as whole seconds since the unix epoch, which every JSON consumer can read,
+/// unlike serde's default `{secs_since_epoch, nanos_since_epoch}` object. Times before the
+/// epoch are written as 0.
+pub fn serialize<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    serializer.serialize_u64(secs)
+}
+
+/// Reads seconds since the unix epoch, or the object serde writes for a `SystemTime` by default,
+/// which is what runtime locks had before. For `#[serde(with = "epoch_seconds")]`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SystemTime, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Epoch {
+        Seconds(u64),
+        Legacy(SystemTime),
+    }
+
+    Ok(match Epoch::deserialize(deserializer)? {
+        Epoch::Seconds(secs) => SystemTime::UNIX_EPOCH + Duration::from_secs(secs),
+        Epoch::Legacy(time) => time,
+    })
+}
+
+/// The same for an `Option<SystemTime>`.
+pub mod option {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => super::serialize(time, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        #[derive(Deserialize)]
+        struct Epoch(#[serde(with = "super")] SystemTime);
+
+        Ok(Option::<Epoch>::deserialize(deserializer)?.map(|Epoch(time)| time))
+    }
+}
@@ -1,11 +1,16 @@
-use std::collections::btree_map;
+use std::collections::HashSet;
 use std::fs;
+use std::iter;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
+use crate::compiled_watch::CompiledWatch;
 use crate::config::{Config, WatchConfig};
 use crate::snapshots;
 
+/// The `max_depth` that puts no limit on how deep repos are looked for.
+const UNLIMITED_DEPTH: u8 = u8::MAX;
+
 /// Internal structure to facilitate "recursion" without blowing up the stack. Without this, we
 /// could call self.next() recursively whenever there was an I/O error or when we reached the end
 /// of a directory listing. There's no stack space used because we just mutate GitRepoIter, so
@@ -16,7 +21,13 @@ enum CallState {
     Done,
 }
 
-/// Iterator over all Git repos covered by a config.
+/// A directory being scanned: the watched dir it's under, that watch's config and compiled
+/// patterns, and the directory's remaining entries.
+type Scan = (Rc<PathBuf>, Rc<WatchConfig>, Rc<CompiledWatch>, fs::ReadDir);
+
+/// Iterator over all Git repos covered by a config. Directories with a `.git` that git can't
+/// open are yielded too, rather than scanned as plain directories, so the poller can tell a
+/// broken repo apart and back off from it, see `RuntimeLock::record_open_failure`.
 ///
 /// The process is naturally recursive, traversing a directory structure, which made it a poor fit
 /// for a more typical filter/map chain.
@@ -26,19 +37,47 @@ enum CallState {
 ///  2. Empty iterator: If we get to the end of a sub-iterator, pop & start from the top
 ///
 pub struct GitRepoIter<'a> {
-    config_iter: btree_map::Iter<'a, String, Rc<WatchConfig>>,
+    /// Watch roots that haven't been scanned yet
+    config_iter: Box<dyn Iterator<Item = (PathBuf, Rc<WatchConfig>)> + 'a>,
     /// A stack, because we can't use recursion with an iterator (at least not between elements)
-    sub_iter: Vec<(Rc<PathBuf>, Rc<WatchConfig>, fs::ReadDir)>,
+    sub_iter: Vec<Scan>,
+    /// Canonical paths of every directory scanned or yielded so far, so that symlink loops, or
+    /// symlinks to somewhere already covered, can't visit a real directory twice. `max_depth`
+    /// alone would only stop a loop after many rounds.
+    visited: HashSet<PathBuf>,
 }
 
 impl<'a> GitRepoIter<'a> {
     pub fn new(config: &'a Config) -> Self {
+        Self::with_roots(
+            config
+                .repos
+                .iter()
+                .map(|(path, watch_config)| (PathBuf::from(path), Rc::clone(watch_config))),
+        )
+    }
+
+    fn with_roots(roots: impl Iterator<Item = (PathBuf, Rc<WatchConfig>)> + 'a) -> Self {
         Self {
-            config_iter: config.repos.iter(),
+            config_iter: Box::new(roots),
             sub_iter: Vec::new(),
+            visited: HashSet::new(),
         }
     }
+}
+
+impl GitRepoIter<'static> {
+    /// Repos under a single `root`, scanned as if it was watched with `watch_config`. Unlike
+    /// `new`, this doesn't need a `Config`.
+    pub fn under(root: &Path, watch_config: &WatchConfig) -> Self {
+        Self::with_roots(iter::once((
+            root.to_path_buf(),
+            Rc::new(watch_config.clone()),
+        )))
+    }
+}
 
+impl<'a> GitRepoIter<'a> {
     fn get_next(&mut self) -> CallState {
         // pop
         //
@@ -46,21 +85,22 @@ impl<'a> GitRepoIter<'a> {
         // borrow a shared reference, which precludes us from borrowing as mutable when we want to
         // use the iterator. But that means we have to return it to the vec.
         match self.sub_iter.pop() {
-            Some((base_path, watch_config, mut dir_iter)) => {
-                let mut next_next: Option<(Rc<PathBuf>, Rc<WatchConfig>, fs::ReadDir)> = None;
+            Some((base_path, watch_config, patterns, mut dir_iter)) => {
+                let mut next_next: Option<Scan> = None;
                 let mut ret_val = CallState::Recurse;
-                let max_depth: usize = watch_config.max_depth.into();
                 if let Some(Ok(entry)) = dir_iter.next() {
                     let child_path = entry.path();
-                    if is_valid_directory(base_path.as_path(), child_path.as_path(), &watch_config)
+                    if is_valid_directory(base_path.as_path(), child_path.as_path(), &patterns)
+                        && self.first_visit(child_path.as_path())
                     {
-                        if snapshots::is_repo(child_path.as_path()) {
+                        if looks_like_repo(child_path.as_path()) {
                             ret_val = CallState::Yield(child_path);
-                        } else if self.sub_iter.len() < max_depth {
+                        } else if descends(watch_config.max_depth, self.sub_iter.len()) {
                             if let Ok(child_dir_iter) = fs::read_dir(child_path.as_path()) {
                                 next_next = Some((
                                     Rc::clone(&base_path),
                                     Rc::clone(&watch_config),
+                                    Rc::clone(&patterns),
                                     child_dir_iter,
                                 ))
                             }
@@ -68,7 +108,7 @@ impl<'a> GitRepoIter<'a> {
                     }
                     // un-pop
                     self.sub_iter
-                        .push((Rc::clone(&base_path), Rc::clone(&watch_config), dir_iter));
+                        .push((base_path, watch_config, patterns, dir_iter));
                 }
                 if let Some(tuple) = next_next {
                     // directory recursion
@@ -79,13 +119,12 @@ impl<'a> GitRepoIter<'a> {
             None => {
                 // Finished dir, queue up next hashmap pair
                 match self.config_iter.next() {
-                    Some((base_path, watch_config)) => {
-                        let path = PathBuf::from(base_path);
+                    Some((path, watch_config)) => {
                         let dir_iter_opt = path.parent().and_then(|p| fs::read_dir(p).ok());
                         if let Some(dir_iter) = dir_iter_opt {
-                            // clone because we're going from more global to less global scope
+                            let patterns = CompiledWatch::cached(&watch_config);
                             self.sub_iter
-                                .push((Rc::new(path), Rc::clone(watch_config), dir_iter));
+                                .push((Rc::new(path), watch_config, patterns, dir_iter));
                         }
                         CallState::Recurse
                     }
@@ -95,6 +134,12 @@ impl<'a> GitRepoIter<'a> {
             }
         }
     }
+
+    /// Records `path` as visited, returning whether it wasn't already.
+    fn first_visit(&mut self, path: &Path) -> bool {
+        let real_path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.visited.insert(real_path)
+    }
 }
 
 impl<'a> Iterator for GitRepoIter<'a> {
@@ -111,34 +156,29 @@ impl<'a> Iterator for GitRepoIter<'a> {
     }
 }
 
+/// Whether `path` is a repo, or has a `.git` of one that may be broken.
+fn looks_like_repo(path: &Path) -> bool {
+    path.join(".git").exists() || snapshots::is_repo(path)
+}
+
+/// Whether a directory `depth` levels below the watched path, which is at 0, is scanned for
+/// repos, see `WatchConfig::max_depth`.
+fn descends(max_depth: u8, depth: usize) -> bool {
+    max_depth == UNLIMITED_DEPTH || depth < max_depth.into()
+}
+
 /// Checks the provided `child_path` is a directory.
-/// If either `includes` or `excludes` are set,
+/// If either includes or excludes are set,
 /// checks whether the path is included/excluded respectively.
-fn is_valid_directory(base_path: &Path, child_path: &Path, value: &WatchConfig) -> bool {
+fn is_valid_directory(base_path: &Path, child_path: &Path, patterns: &CompiledWatch) -> bool {
     if !child_path.is_dir() {
         return false;
     }
 
-    if !child_path.starts_with(base_path) {
-        return false;
-    }
-
-    let includes = &value.include;
-    let excludes = &value.exclude;
-
-    let mut include = true;
-
-    if !excludes.is_empty() {
-        include = !excludes
-            .iter()
-            .any(|exclude| child_path.starts_with(base_path.join(exclude)));
-    }
-
-    if !include && !includes.is_empty() {
-        include = includes
-            .iter()
-            .any(|include| base_path.join(include).starts_with(child_path));
-    }
+    let relative = match child_path.strip_prefix(base_path) {
+        Ok(relative) => relative,
+        Err(_) => return false,
+    };
 
-    include
+    patterns.admits(relative)
 }
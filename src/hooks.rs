@@ -0,0 +1,68 @@
+use std::env;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use git2::{Oid, Repository};
+
+use crate::snapshots::CaptureStatus;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Runs a watch's `post_backup_hook` after a successful backup and waits for it to finish.
+///
+/// The command runs through the user's shell ($SHELL, or `sh`; `cmd` on Windows) in the repo's
+/// directory, with these environment variables set:
+///  * DURA_REPO: path of the repository
+///  * DURA_COMMIT: hash of the backup commit
+///  * DURA_FILES: files changed by the backup, one per line
+pub fn run_post_backup_hook(
+    command: &str,
+    repo_path: &Path,
+    status: &CaptureStatus,
+) -> Result<ExitStatus> {
+    let files = changed_files(repo_path, status)?;
+
+    let exit_status = shell(command)
+        .current_dir(repo_path)
+        .env("DURA_REPO", repo_path)
+        .env("DURA_COMMIT", &status.commit_hash)
+        .env("DURA_FILES", files.join("\n"))
+        .status()?;
+    Ok(exit_status)
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let shell = env::var("SHELL")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "sh".to_string());
+    let mut cmd = Command::new(shell);
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let shell = env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string());
+    let mut cmd = Command::new(shell);
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+/// Files that differ between the backup commit and its parent.
+fn changed_files(repo_path: &Path, status: &CaptureStatus) -> Result<Vec<String>> {
+    let repo = Repository::open(repo_path)?;
+    let commit = repo.find_commit(Oid::from_str(&status.commit_hash)?)?;
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), None)?;
+    Ok(diff
+        .deltas()
+        .flat_map(|d| d.new_file().path())
+        .filter_map(|p| p.to_str())
+        .map(|p| p.to_string())
+        .collect())
+}
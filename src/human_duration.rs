@@ -0,0 +1,92 @@
+use std::fmt;
+use std::time::Duration;
+
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{InstanceType, Schema, SchemaObject};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serializer};
+
+/// Writes `duration` the way people do, e.g. "1h 30m".
+pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&humantime::format_duration(*duration))
+}
+
+/// Reads a duration like "30s", "5m" or "1h 30m", or a plain number of seconds, which is what
+/// config files had before. For `#[serde(with = "human_duration")]`.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    deserializer.deserialize_any(DurationVisitor)
+}
+
+/// JSON Schema of what `deserialize` accepts, for `#[schemars(schema_with = "...")]`.
+pub fn schema(_: &mut SchemaGenerator) -> Schema {
+    duration_schema(vec![InstanceType::String, InstanceType::Integer])
+}
+
+fn duration_schema(types: Vec<InstanceType>) -> Schema {
+    let mut schema = SchemaObject {
+        instance_type: Some(types.into()),
+        ..Default::default()
+    };
+    schema.metadata().examples = vec!["30s".into(), "1h 30m".into(), 300.into()];
+    schema.into()
+}
+
+/// The same for an `Option<Duration>`.
+pub mod option {
+    use super::*;
+
+    pub fn schema(_: &mut SchemaGenerator) -> Schema {
+        duration_schema(vec![
+            InstanceType::String,
+            InstanceType::Integer,
+            InstanceType::Null,
+        ])
+    }
+
+    pub fn serialize<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Duration>, D::Error> {
+        #[derive(Deserialize)]
+        struct Human(#[serde(with = "super")] Duration);
+
+        Ok(Option::<Human>::deserialize(deserializer)?.map(|Human(duration)| duration))
+    }
+}
+
+struct DurationVisitor;
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a duration like \"30s\" or \"5m\", or a number of seconds")
+    }
+
+    fn visit_u64<E: de::Error>(self, secs: u64) -> Result<Duration, E> {
+        Ok(Duration::from_secs(secs))
+    }
+
+    fn visit_i64<E: de::Error>(self, secs: i64) -> Result<Duration, E> {
+        u64::try_from(secs)
+            .map(Duration::from_secs)
+            .map_err(|_| E::invalid_value(de::Unexpected::Signed(secs), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Duration, E> {
+        humantime::parse_duration(s).map_err(|e| {
+            E::custom(format!(
+                "invalid duration '{s}' ({e}), expected e.g. \"30s\", \"5m\" or a number of seconds"
+            ))
+        })
+    }
+}
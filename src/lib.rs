@@ -1,9 +1,26 @@
+pub mod backup_queue;
+pub mod backups;
+pub mod capabilities;
+pub mod clock;
+pub mod compiled_watch;
 pub mod config;
 pub mod database;
+pub mod doctor;
+pub mod epoch_seconds;
 pub mod git_repo_iter;
+pub mod hooks;
+pub mod human_duration;
 pub mod log;
 pub mod logger;
 pub mod metrics;
+pub mod output;
 pub mod poll_guard;
 pub mod poller;
+pub mod preflight;
+pub mod presets;
+pub mod read_only;
+pub mod repo_size;
+pub mod repo_status;
 pub mod snapshots;
+pub mod term;
+pub mod timeout;
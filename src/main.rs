@@ -1,19 +1,24 @@
 use std::fs::{File, OpenOptions};
-use std::io::{stdin, stdout, BufReader, BufWriter, Read, Write};
-use std::path::Path;
+use std::io::{stdin, stdout, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::fs;
+use std::time::SystemTime;
 
 use clap::builder::IntoResettable;
 use clap::{
     arg, crate_authors, crate_description, crate_name, crate_version, value_parser, Arg, Command,
 };
-use dura::config::{Config, WatchConfig};
+use dura::capabilities;
+use dura::config::{check_pattern, Config, WatchConfig, WatchConfigBuilder};
 use dura::database::RuntimeLock;
 use dura::logger::NestedJsonLayer;
 use dura::metrics;
+use dura::output::{exit_code_for, OutputFormat};
 use dura::poller;
+use dura::presets::{detect_project_kind, ProjectKind};
+use dura::read_only;
 use dura::snapshots;
+use git2::Repository;
 use tracing::info;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -38,12 +43,26 @@ async fn main() {
         .default_value(cwd.into_os_string().into_resettable())
         .help("The directory to watch. Defaults to current directory");
 
+    let arg_dry_run = arg!(--"dry-run" "Print what would change in the config without saving it")
+        .required(false)
+        .action(clap::builder::ArgAction::SetTrue);
+
     let matches = Command::new(crate_name!())
         .about(crate_description!())
         .version(version.into_resettable())
         .subcommand_required(true)
         .arg_required_else_help(true)
         .author(crate_authors!())
+        .arg(
+            arg!(--"read-only" "Audit mode: report status but never write config, runtime files or backups")
+                .global(true)
+                .action(clap::builder::ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(--config <FILE> "Config file to use for info, check, doctor, watch and unwatch, instead of the default")
+                .required(false)
+                .global(true)
+        )
         .subcommand(
             Command::new("capture")
                 .short_flag('C')
@@ -61,6 +80,53 @@ async fn main() {
                         .required(false)
                         .action(clap::builder::ArgAction::SetTrue)
                 )
+                .arg(
+                    arg!(-a --all "With --detail, also show repositories that are clean")
+                        .required(false)
+                        .action(clap::builder::ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!(-g --grouped "Summarize repositories grouped by parent directory. With --detail, also lists each group's repositories")
+                        .required(false)
+                        .action(clap::builder::ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!(--repo <PATH> "Only show detailed output for the repositories covering these paths, or with these labels. Can be repeated")
+                        .required(false)
+                        .action(clap::builder::ArgAction::Append)
+                )
+                .arg(
+                    arg!(--"with-backups" "Also count each repository's backups in the summary, which walks their whole history")
+                        .required(false)
+                        .action(clap::builder::ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!(--format <FORMAT> "Print the summary as human (the default), json or porcelain (stable, for scripts)")
+                        .required(false)
+                        .value_parser(value_parser!(String))
+                )
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Verifies dura can write to its config dir, cache dir and each watched repository's git dir.")
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("Prints versions, config and runtime lock state, and whether each watched repository opens. Useful for bug reports.")
+                .arg(
+                    arg!(--json "Print the report as JSON")
+                        .required(false)
+                        .action(clap::builder::ArgAction::SetTrue)
+                )
+                .arg(
+                    arg!(--paths "Print only the resolved config, cache and repository paths, as JSON")
+                        .required(false)
+                        .action(clap::builder::ArgAction::SetTrue)
+                )
+        )
+        .subcommand(
+            Command::new("capabilities")
+                .about("Prints the version and the features of this build as JSON, for tools that drive dura.")
         )
         .subcommand(
             Command::new("serve")
@@ -94,14 +160,28 @@ async fn main() {
                     .value_delimiter(',')
                     .help("Excludes specific directories relative to the watch directory")
                 )
+                .arg(arg!(-p --preset <KIND>)
+                    .required(false)
+                    .value_parser(value_parser!(String))
+                    .help("Starts from the excludes of a project preset: rust, node, python, generic, or auto to detect it")
+                )
                 .arg(arg!(-d --maxdepth)
                     .required(false)
                     .action(clap::builder::ArgAction::Set)
                     .value_parser(value_parser!(String))
-                    .default_value(&"255".to_string())
+                    .default_value("255".to_string())
                     .num_args(0..=1)
                     .help("Determines the depth to recurse into when scanning directories")
                 )
+                .arg(arg!(-l --label <LABEL> "Name to show the watch by instead of its path, unique among watches")
+                    .required(false)
+                    .value_parser(value_parser!(String))
+                )
+                .arg(arg!(--init "Creates a git repo in the directory first if it isn't in one yet")
+                    .required(false)
+                    .action(clap::builder::ArgAction::SetTrue)
+                )
+                .arg(arg_dry_run.clone())
         )
         .subcommand(
             Command::new("unwatch")
@@ -109,6 +189,24 @@ async fn main() {
                 .long_flag("unwatch")
                 .about("Remove the current working directory as a repository to watch.")
                 .arg(arg_directory)
+                .arg(arg_dry_run)
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Watch each git repo listed in a file, one path per line, e.g. from `ghq list --full-path`.")
+                .arg(Arg::new("list")
+                    .required(true)
+                    .help("File with the repos to watch")
+                )
+        )
+        .subcommand(
+            Command::new("clean")
+                .long_flag("clean")
+                .about("Remove watches whose paths are gone or have no git repos, asking for each.")
+                .arg(arg!(-y --yes "Removes them all without asking")
+                    .required(false)
+                    .action(clap::builder::ArgAction::SetTrue)
+                )
         )
         .subcommand(
             Command::new("kill")
@@ -134,12 +232,29 @@ async fn main() {
         )
         .get_matches();
 
+    if matches.get_flag("read-only") {
+        read_only::enable();
+    }
+    let config_path = matches
+        .get_one::<String>("config")
+        .map(PathBuf::from)
+        .unwrap_or_else(Config::default_path);
+
     match matches.subcommand() {
         Some(("capture", arg_matches)) => {
             let dir = Path::new(arg_matches.get_one::<String>("directory").unwrap());
-            match snapshots::capture(dir) {
+            let config = Config::load();
+            match snapshots::capture_with(&config, dir) {
                 Ok(oid_opt) => {
                     if let Some(oid) = oid_opt {
+                        let mut runtime_lock = RuntimeLock::load();
+                        runtime_lock.record_backup(dir, &oid);
+                        runtime_lock.save();
+                        for push in snapshots::push_backup(&config, dir, &oid) {
+                            if let Some(error) = push.error {
+                                eprintln!("Unable to push to {}: {}", push.remote, error);
+                            }
+                        }
                         println!("{oid}");
                     }
                 }
@@ -150,14 +265,75 @@ async fn main() {
             }
         }
         Some(("info", arg_matches)) => {
-            let config = Config::load();
-            if arg_matches.get_flag("detail"){
-                config.print_detailed_info();
+            let format = match arg_matches.get_one::<String>("format").map(|f| f.parse()) {
+                None => None,
+                Some(Ok(format)) => Some(format),
+                Some(Err(e)) => {
+                    eprintln!("{e}");
+                    process::exit(1);
+                }
+            };
+            let (config, source) = Config::load_verbose_from(config_path);
+            // human is what info prints without a format
+            let format = format.filter(|format| *format != OutputFormat::Human);
+            if format.is_none() {
+                println!("{source}\n");
+            }
+            let printed = if let Some(format) = format {
+                config.print_summary_as(format)
+            } else if arg_matches.get_flag("grouped") {
+                config.print_grouped_summary(arg_matches.get_flag("detail"))
+            } else if let Some(paths) = arg_matches.get_many::<String>("repo") {
+                let paths: Vec<PathBuf> = paths.map(PathBuf::from).collect();
+                config.print_detailed_info_for_many(&paths)
+            } else if arg_matches.get_flag("detail"){
+                config.print_detailed_info(arg_matches.get_flag("all"))
             } else {
-                config.print_summary();
+                config.print_summary(arg_matches.get_flag("with-backups"))
+            };
+            if let Err(e) = printed {
+                let code = exit_code_for(&e);
+                if code != 0 {
+                    eprintln!("Unable to write to stdout: {e}");
+                }
+                process::exit(code);
+            }
+        }
+        Some(("check", _)) => {
+            let results = Config::load_from(&config_path).preflight();
+            for result in &results {
+                match &result.error {
+                    None => println!("OK    {} {}", result.target, result.path.display()),
+                    Some(e) => println!("FAIL  {} {}: {}", result.target, result.path.display(), e),
+                }
+            }
+            if !results.iter().all(|result| result.passed()) {
+                process::exit(1);
             }
         }
+        Some(("doctor", arg_matches)) if arg_matches.get_flag("paths") => {
+            let mut paths = Config::load_from(&config_path).debug_paths();
+            // --config wins over DURA_CONFIG_HOME
+            paths.config_file = config_path;
+            println!("{}", serde_json::to_string_pretty(&paths).unwrap());
+        }
+        Some(("doctor", arg_matches)) => {
+            let report = Config::load_from(&config_path).doctor_at(config_path);
+            if arg_matches.get_flag("json") {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                print!("{report}");
+            }
+        }
+        Some(("capabilities", _)) => {
+            let capabilities = capabilities::capabilities();
+            println!("{}", serde_json::to_string_pretty(&capabilities).unwrap());
+        }
         Some(("serve", arg_matches)) => {
+            if read_only::is_enabled() {
+                eprintln!("Dura can't serve in read-only mode, it would never take a backup");
+                process::exit(1);
+            }
             let env_filter =
                 EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
 
@@ -203,23 +379,59 @@ async fn main() {
                 .unwrap_or_default()
                 .map(|s| s.to_string())
                 .collect::<Vec<String>>();
+            for pattern in include.iter().chain(&exclude) {
+                if let Err(e) = check_pattern(pattern) {
+                    eprintln!("{e}");
+                    process::exit(1);
+                }
+            }
             let max_depth = arg_matches
                 .get_one::<String>("maxdepth")
                 .unwrap_or(&"255".to_string())
                 .parse::<u8>()
                 .expect("Max depth must be between 0-255");
 
-            let watch_config = WatchConfig {
-                include,
-                exclude,
-                max_depth,
+            let preset = match arg_matches.get_one::<String>("preset").map(|s| s.as_str()) {
+                None => WatchConfig::new(),
+                Some("auto") => WatchConfig::preset(detect_project_kind(dir)),
+                Some(kind) => match kind.parse::<ProjectKind>() {
+                    Ok(kind) => WatchConfig::preset(kind),
+                    Err(e) => {
+                        eprintln!("{e}");
+                        process::exit(1);
+                    }
+                },
             };
 
-            watch_dir(dir, watch_config);
+            let mut watch_config = WatchConfigBuilder::from(preset)
+                .include(include)
+                .exclude(exclude)
+                .max_depth(max_depth);
+            if let Some(label) = arg_matches.get_one::<String>("label") {
+                watch_config = watch_config.label(label);
+            }
+            let watch_config = watch_config.build();
+
+            if arg_matches.get_flag("dry-run") {
+                plan_watch_dir(&config_path, dir, arg_matches.get_flag("init"));
+            } else {
+                watch_dir(&config_path, dir, watch_config, arg_matches.get_flag("init"));
+            }
         }
         Some(("unwatch", arg_matches)) => {
             let dir = Path::new(arg_matches.get_one::<String>("directory").unwrap());
-            unwatch_dir(dir)
+            if arg_matches.get_flag("dry-run") {
+                plan_unwatch_dir(&config_path, dir);
+            } else {
+                unwatch_dir(&config_path, dir);
+            }
+        }
+        Some(("import", arg_matches)) => {
+            let list = Path::new(arg_matches.get_one::<String>("list").unwrap());
+            import_repos(&config_path, list);
+        }
+        Some(("clean", arg_matches)) => {
+            clean_watches(&config_path, arg_matches.get_flag("yes"));
         }
         Some(("kill", _)) => {
             kill();
@@ -246,31 +458,18 @@ async fn main() {
     }
 }
 
-fn watch_dir(path: &std::path::Path, watch_config: WatchConfig) {
-    let mut config = Config::load();
-    let path = path
-        .to_str()
-        .expect("The provided path is not valid unicode")
-        .to_string();
+fn watch_dir(config_path: &Path, path: &Path, watch_config: WatchConfig, init_if_needed: bool) {
+    let mut config = Config::load_from(config_path);
 
-    config.set_watch(path, watch_config);
-    config.save();
+    if let Err(e) = config.watch(path, watch_config, init_if_needed) {
+        eprintln!("Unable to watch: {e}");
+        process::exit(1);
+    }
+    save_config(&config, config_path);
 }
 
-fn unwatch_dir(path: &std::path::Path) {
-    let mut config = Config::load();
-
-    // Try to canonicalize the path, if it fails (doesn't exist), use the original path
-    let path_str = match fs::canonicalize(path) {
-        Ok(canonical_path) => canonical_path
-            .to_str()
-            .expect("The provided path is not valid unicode")
-            .to_string(),
-        Err(_) => path
-            .to_str()
-            .expect("The provided path is not valid unicode")
-            .to_string(),
-    };
+fn unwatch_dir(config_path: &Path, path: &Path) {
+    let mut config = Config::load_from(config_path);
 
     // Find non-existent paths
     let removed_paths: Vec<String> = config.repos
@@ -286,11 +485,100 @@ fn unwatch_dir(path: &std::path::Path) {
     }
 
     // Handle the specifically requested path
-    config.set_unwatch(path_str);
-    config.save();
+    if let Err(e) = config.set_unwatch(path) {
+        println!("{e}");
+    }
+    save_config(&config, config_path);
+}
+
+/// Watches the repos listed in `list`, see `Config::import_from_directory_list`.
+fn import_repos(config_path: &Path, list: &Path) {
+    let mut config = Config::load_from(config_path);
+    match config.import_from_directory_list(list) {
+        Ok(summary) => {
+            println!("Imported {} repos, skipped {}", summary.added, summary.skipped);
+            if summary.added > 0 {
+                save_config(&config, config_path);
+            }
+        }
+        Err(e) => {
+            eprintln!("Unable to import: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Removes the watches `Config::clean_candidates` finds, asking about each one unless `yes`.
+/// Without a terminal to ask on, nothing is removed unless `yes`.
+fn clean_watches(config_path: &Path, yes: bool) {
+    let mut config = Config::load_from(config_path);
+    let candidates = config.clean_candidates();
+    if candidates.is_empty() {
+        println!("Nothing to clean");
+        return;
+    }
+
+    let interactive = stdin().is_terminal();
+    let mut removed = 0;
+    for (key, reason) in candidates {
+        let remove = if yes {
+            true
+        } else if interactive {
+            print!("Remove {} ({})? [y/N] ", key, reason);
+            let _ = stdout().flush();
+            let mut answer = String::new();
+            stdin().read_line(&mut answer).is_ok()
+                && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+        } else {
+            println!("Skipped {} ({}), pass --yes to remove it", key, reason);
+            false
+        };
+        if remove {
+            config.repos.remove(&key);
+            println!("Removed {} ({})", key, reason);
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        save_config(&config, config_path);
+    }
+}
+
+/// Prints what `watch_dir` would change, without changing anything.
+fn plan_watch_dir(config_path: &Path, path: &Path, init_if_needed: bool) {
+    let config = Config::load_from(config_path);
+    if init_if_needed && path.is_dir() && Repository::discover(path).is_err() {
+        println!("Would create a git repo at {}", path.display());
+    }
+    match config.plan_watch(path) {
+        Ok(change) => println!("{change}"),
+        Err(e) => {
+            eprintln!("Unable to watch: {e}");
+            process::exit(1);
+        }
+    }
+}
+
+/// Prints what `unwatch_dir` would change, without changing anything.
+fn plan_unwatch_dir(config_path: &Path, path: &Path) {
+    let config = Config::load_from(config_path);
+    for missing in config.repos.keys().filter(|p| !Path::new(p).exists()) {
+        println!("Would remove non-existent path: {}", missing);
+    }
+    match config.plan_unwatch(path) {
+        Ok(change) => println!("{change}"),
+        Err(e) => println!("{e}"),
+    }
+}
+
+fn save_config(config: &Config, config_path: &Path) {
+    if let Err(e) = config.save_to_path(config_path) {
+        eprintln!("Unable to save the dura config: {e}");
+        process::exit(1);
+    }
 }
 
-#[cfg(all(unix))]
+#[cfg(unix)]
 fn check_if_user() -> bool {
     sudo::check() != sudo::RunningAs::Root
 }
@@ -308,6 +596,6 @@ fn check_if_user() -> bool {
 /// that any living poller should exit during their next check.
 fn kill() {
     let mut runtime_lock = RuntimeLock::load();
-    runtime_lock.pid = None;
+    runtime_lock.end_session(SystemTime::now());
     runtime_lock.save();
 }
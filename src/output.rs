@@ -0,0 +1,126 @@
+use std::fmt::{self, Write};
+use std::io;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use serde::Serialize;
+
+use crate::config::SummaryStats;
+use crate::repo_status::RepoStatus;
+
+/// How `Config::print_summary_as` renders the summary. `Json` and `Porcelain` are rendered from
+/// the same `SummaryStats` and statuses, so they can't disagree with each other, and don't use
+/// `Config::fancy_symbols` or colors: they're plain ASCII wherever they're written, whatever
+/// `DURA_FANCY` or the terminal say.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// For people, may change between versions. The same as `dura info` without a format.
+    Human,
+    Json,
+    /// Stable, line-based output for scripts. See `render_porcelain` for the format.
+    Porcelain,
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            OutputFormat::Human => "human",
+            OutputFormat::Json => "json",
+            OutputFormat::Porcelain => "porcelain",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(OutputFormat::Human),
+            "json" => Ok(OutputFormat::Json),
+            "porcelain" => Ok(OutputFormat::Porcelain),
+            _ => Err(format!(
+                "Unknown output format '{s}', expected one of human, json, porcelain"
+            )),
+        }
+    }
+}
+
+/// Runs `write` against a locked stdout, and flushes it. A failed write is returned instead of
+/// panicking like `println!` does, e.g. when piped to `head`, see `exit_code_for`.
+pub fn print_checked(write: impl FnOnce(&mut dyn io::Write) -> io::Result<()>) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write(&mut out).and_then(|()| io::Write::flush(&mut out))
+}
+
+/// How dura exits after failing to write its output. A closed pipe means whoever reads the
+/// output has all they wanted, so that's a success.
+pub fn exit_code_for(e: &io::Error) -> i32 {
+    match e.kind() {
+        io::ErrorKind::BrokenPipe => 0,
+        _ => 1,
+    }
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    stats: &'a SummaryStats,
+    repos: &'a [RepoStatus],
+}
+
+/// The summary of `stats` and `repos` as pretty-printed JSON, see `OutputFormat::Json`.
+pub fn render_json(stats: &SummaryStats, repos: &[RepoStatus]) -> String {
+    let mut json = serde_json::to_string_pretty(&Summary { stats, repos }).unwrap();
+    json.push('\n');
+    json
+}
+
+/// One `key value` line per stat, in `SummaryStats` order, then one line per repo:
+///
+/// ```text
+/// repo\t<path>\t<condition>\t<last backup, unix secs or ->\t<state>
+/// ```
+///
+/// where the condition is one of clean, dirty, missing, unavailable, not-a-repo or status-error.
+pub fn render_porcelain(stats: &SummaryStats, repos: &[RepoStatus]) -> String {
+    let mut out = String::new();
+    writeln!(out, "total_repos {}", stats.total_repos).unwrap();
+    writeln!(out, "dirty_repos {}", stats.dirty_repos).unwrap();
+    writeln!(out, "dirty_files {}", stats.dirty_files).unwrap();
+    writeln!(out, "total_backups {}", stats.total_backups).unwrap();
+    writeln!(out, "inaccessible_repos {}", stats.inaccessible_repos).unwrap();
+    for repo in repos {
+        let last_backup = repo
+            .last_backup
+            .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or_else(|| "-".to_string(), |since| since.as_secs().to_string());
+        writeln!(
+            out,
+            "repo\t{}\t{}\t{}\t{}",
+            repo.path.display(),
+            repo_condition(repo),
+            last_backup,
+            repo.state
+        )
+        .unwrap();
+    }
+    out
+}
+
+fn repo_condition(repo: &RepoStatus) -> &'static str {
+    if repo.unavailable {
+        "unavailable"
+    } else if !repo.exists {
+        "missing"
+    } else if !repo.is_git_repo {
+        "not-a-repo"
+    } else if repo.status_error.is_some() {
+        "status-error"
+    } else if repo.uncommitted_changes {
+        "dirty"
+    } else {
+        "clean"
+    }
+}
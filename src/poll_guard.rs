@@ -53,6 +53,23 @@ impl PollGuard {
         false
     }
 
+    /// When the most recently modified file in `dir` was modified, if that was after the last
+    /// backup, i.e. when `dir_changed` would say it changed. Unlike `dir_changed`, this walks
+    /// every file, except for those in `.git`, which dura's own backups keep touching.
+    pub fn last_change(&mut self, dir: &Path) -> Option<SystemTime> {
+        let watermark = self.get_watermark(dir).ok()?;
+        WalkDir::new(dir)
+            .into_iter()
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+            .filter(|modified| {
+                modified
+                    .duration_since(watermark)
+                    .is_ok_and(|since| since.as_secs_f32() > 1.0)
+            })
+            .max()
+    }
+
     /// Find the last known commit timestamp
     fn get_watermark(&mut self, path: &Path) -> Result<SystemTime> {
         // Get git repo, create if necessary
@@ -1,41 +1,252 @@
-use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::time::{Instant, SystemTime};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
+use git2::Repository;
 use tokio::time;
-use tracing::{debug, error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 
-use crate::config::Config;
-use crate::database::RuntimeLock;
+use crate::backup_queue::{backup_queue, BackupQueue, BACKUP_QUEUE_CAPACITY};
+use crate::capabilities;
+use crate::config::{Config, ConfigError, WatchConfig};
+use crate::database::{RuntimeLock, OPEN_RETRY_MAX};
+use crate::hooks;
 use crate::log::{Operation, StatCollector};
 use crate::poll_guard::PollGuard;
+use crate::repo_size::RepoSizes;
+use crate::repo_status;
 use crate::snapshots;
+use crate::timeout;
 
-/// If the directory is a repo, attempts to create a snapshot.
-/// Otherwise, recurses into each child directory.
-#[tracing::instrument]
-fn process_directory(current_path: &Path, guard: &mut PollGuard) {
+/// How often every watched repo is checked for changes.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a single repo's backup, its status or its post-backup hook may take before dura
+/// gives up waiting on it.
+const GIT_OPERATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long pushing a backup to its watch's `push_remotes` may take, after the backup is made.
+const PUSH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often a row is appended to the configured `stats_csv`.
+const STATS_SAMPLE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How many polls in a row have to find nothing changed before `IdleBackoff` slows down.
+pub const IDLE_CYCLES_BEFORE_BACKOFF: u32 = 12;
+
+/// Time between polls, which doubles with each poll after `IDLE_CYCLES_BEFORE_BACKOFF` quiet
+/// ones, up to a maximum, and drops back to the base interval when a change shows up. Saves
+/// battery on laptops that sit idle.
+#[derive(Debug)]
+pub struct IdleBackoff {
+    base: Duration,
+    max: Duration,
+    idle_cycles: u32,
+    interval: Duration,
+}
+
+impl IdleBackoff {
+    /// Starts out polling every `base`, which is also the maximum until `set_max`.
+    pub fn new(base: Duration) -> Self {
+        Self {
+            base,
+            max: base,
+            idle_cycles: 0,
+            interval: base,
+        }
+    }
+
+    /// A maximum below the base interval means no backoff at all.
+    pub fn set_max(&mut self, max: Duration) {
+        self.max = max.max(self.base);
+        self.interval = self.interval.min(self.max);
+    }
+
+    /// Records a poll, and whether it found any change. Returns how long to wait until the next.
+    pub fn record_cycle(&mut self, changed: bool) -> Duration {
+        if changed {
+            self.idle_cycles = 0;
+            self.interval = self.base;
+        } else {
+            self.idle_cycles = self.idle_cycles.saturating_add(1);
+            if self.idle_cycles > IDLE_CYCLES_BEFORE_BACKOFF {
+                self.interval = (self.interval * 2).min(self.max);
+            }
+        }
+        self.interval
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+}
+
+/// Holds back backups of repos whose files are still being written, until they've been quiet
+/// for a window. The poller records when it saw each repo last change, and queues the repos
+/// that are due. A zero window makes every recorded repo due right away.
+#[derive(Debug)]
+pub struct Debouncer {
+    window: Duration,
+    last_change: HashMap<PathBuf, SystemTime>,
+}
+
+impl Debouncer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last_change: HashMap::new(),
+        }
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window;
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Records that `repo` changed at `at`. Earlier changes are superseded.
+    pub fn record(&mut self, repo: PathBuf, at: SystemTime) {
+        let last = self.last_change.entry(repo).or_insert(at);
+        *last = at.max(*last);
+    }
+
+    /// Repos without a change in the window before `now`, in path order. They're forgotten,
+    /// so each quiet spell yields a repo once.
+    pub fn due(&mut self, now: SystemTime) -> Vec<PathBuf> {
+        let window = self.window;
+        let mut due: Vec<PathBuf> = self
+            .last_change
+            .iter()
+            .filter(|(_, at)| now.duration_since(**at).is_ok_and(|quiet| quiet >= window))
+            .map(|(repo, _)| repo.clone())
+            .collect();
+        for repo in &due {
+            self.last_change.remove(repo);
+        }
+        due.sort();
+        due
+    }
+}
+
+/// The daemon's config as the backup worker sees it. It's kept serialized, because a `Config`
+/// shares its watch configs through `Rc`s and so can't cross threads, and updated on every
+/// reload.
+#[derive(Clone)]
+struct SharedConfig(Arc<Mutex<String>>);
+
+impl SharedConfig {
+    fn new(config: &Config) -> Self {
+        let shared = Self(Arc::new(Mutex::new(String::new())));
+        shared.set(config);
+        shared
+    }
+
+    fn set(&self, config: &Config) {
+        match toml::to_string(config) {
+            Ok(serialized) => *self.0.lock().unwrap() = serialized,
+            Err(e) => warn!("Unable to hand the reloaded config to the backup worker: {e}"),
+        }
+    }
+
+    fn get(&self) -> Config {
+        toml::from_str(&self.0.lock().unwrap()).unwrap_or_else(|_| Config::empty())
+    }
+}
+
+/// Attempts to create a snapshot of a repo that the watcher saw change.
+///
+/// The snapshot runs on its own thread, with a timeout, so one repo on a hung mount can't freeze
+/// the whole daemon. `busy` tracks repos whose snapshot is still running after timing out, they
+/// are skipped until it finishes. The backup goes by `config`, the daemon's running config.
+#[tracing::instrument(skip(busy, config))]
+fn process_directory(
+    current_path: &Path,
+    busy: &Arc<Mutex<HashSet<PathBuf>>>,
+    config: &SharedConfig,
+) {
     let mut op: Option<snapshots::CaptureStatus> = None;
     let mut error: Option<String> = None;
     let start_time = Instant::now();
 
-    if guard.dir_changed(current_path) {
+    if !busy.lock().unwrap().insert(current_path.to_path_buf()) {
         debug!(
-            "Potential change detected in repo: path = {path}",
-            path = current_path.to_str().unwrap_or("")
+            "Skipping repo, its previous backup is still running: path = {path}",
+            path = current_path.display()
         );
-        match snapshots::capture(current_path) {
-            Ok(Some(status)) => op = Some(status),
-            Ok(None) => (),
-            Err(err) => {
-                error = Some(format!("{err}"));
+        return;
+    }
+    let path = current_path.to_path_buf();
+    let thread_busy = Arc::clone(busy);
+    let thread_config = config.clone();
+    let result = timeout::run_with_timeout(GIT_OPERATION_TIMEOUT, move || {
+        let result = snapshots::capture_with(&thread_config.get(), path.as_path());
+        thread_busy.lock().unwrap().remove(&path);
+        result
+    });
+
+    match result {
+        None => {
+            warn!(
+                "Backup timed out, skipping repo this cycle: path = {path}, timeout = {timeout:?}",
+                path = current_path.display(),
+                timeout = GIT_OPERATION_TIMEOUT
+            );
+            let mut runtime_lock = RuntimeLock::load();
+            let message = format!("backup timed out after {GIT_OPERATION_TIMEOUT:?}");
+            runtime_lock.record_error(current_path, SystemTime::now(), message);
+            runtime_lock.save();
+        }
+        Some(Ok(Some(mut status))) => {
+            let mut runtime_lock = RuntimeLock::load();
+            runtime_lock.record_backup(current_path, &status);
+            runtime_lock.record_open_success(current_path);
+            runtime_lock.save();
+            status.pushes = push_backup(&config.get(), current_path, &status);
+            run_hook(&config.get(), current_path, &status);
+            for push in &status.pushes {
+                if let Some(error) = &push.error {
+                    warn!(
+                        "Unable to push backup: repo = {repo}, remote = {remote}, error = {error}",
+                        repo = current_path.display(),
+                        remote = push.remote
+                    );
+                }
             }
+            op = Some(status);
+        }
+        Some(Ok(None)) => {
+            let mut runtime_lock = RuntimeLock::load();
+            if runtime_lock.record_open_success(current_path) {
+                runtime_lock.save();
+            }
+        }
+        Some(Err(err)) => {
+            let mut runtime_lock = RuntimeLock::load();
+            runtime_lock.record_error(current_path, SystemTime::now(), err.message());
+            let write_error = Repository::open(current_path)
+                .is_ok_and(|repo| repo_status::is_write_error(&err, repo.path()));
+            if write_error {
+                runtime_lock.record_read_only(current_path, SystemTime::now());
+                warn!(
+                    "Unable to write a backup, skipping repo for {OPEN_RETRY_MAX:?}: path = {path}",
+                    path = current_path.display()
+                );
+            } else if !snapshots::is_repo(current_path) {
+                let retry_in = runtime_lock.record_open_failure(current_path, SystemTime::now());
+                warn!(
+                    "Unable to open repo, skipping it for {retry_in:?}: path = {path}",
+                    path = current_path.display()
+                );
+            }
+            runtime_lock.save();
+            error = Some(format!("{err}"));
         }
-    } else {
-        trace!(
-            "No files in repo have changed: path = {path}",
-            path = current_path.to_str().unwrap_or("")
-        );
     }
 
     let latency = (Instant::now() - start_time).as_secs_f32();
@@ -54,9 +265,132 @@ fn process_directory(current_path: &Path, guard: &mut PollGuard) {
     }
 }
 
-#[tracing::instrument]
-fn do_task(stats: &mut StatCollector, guard: &mut PollGuard) {
-    let runtime_lock = RuntimeLock::load();
+/// Pushes the backup `status` of `repo` to its watch's `push_remotes`. Remotes that are still
+/// being pushed to after `PUSH_TIMEOUT` are left to finish in the background, and reported as
+/// failed.
+fn push_backup(
+    config: &Config,
+    repo: &Path,
+    status: &snapshots::CaptureStatus,
+) -> Vec<snapshots::RemotePush> {
+    let watch_config = WatchConfig::clone(&config.watch_config_for(repo).unwrap_or_default());
+    if watch_config.push_remotes.is_empty() {
+        return Vec::new();
+    }
+    let remotes = watch_config.push_remotes.clone();
+    let (path, status) = (repo.to_path_buf(), status.clone());
+    let pushes = timeout::run_with_timeout(PUSH_TIMEOUT, move || {
+        snapshots::push_backup_for(&watch_config, &path, &status)
+    });
+    pushes.unwrap_or_else(|| {
+        remotes
+            .into_iter()
+            .map(|remote| snapshots::RemotePush {
+                remote,
+                error: Some(format!("push timed out after {PUSH_TIMEOUT:?}")),
+            })
+            .collect()
+    })
+}
+
+/// Runs the repo's post-backup hook from `config`, if it has one. A failing hook is only
+/// logged, it must never take the daemon down, and one that's still running after
+/// `GIT_OPERATION_TIMEOUT` is left to finish in the background.
+fn run_hook(config: &Config, repo: &Path, status: &snapshots::CaptureStatus) {
+    let hook = config
+        .watch_config_for(repo)
+        .and_then(|watch_config| watch_config.post_backup_hook.clone());
+    let Some(hook) = hook else {
+        return;
+    };
+    let (path, status) = (repo.to_path_buf(), status.clone());
+    let result = timeout::run_with_timeout(GIT_OPERATION_TIMEOUT, move || {
+        hooks::run_post_backup_hook(&hook, &path, &status).map_err(|e| e.to_string())
+    });
+    match result {
+        Some(Ok(exit_status)) if exit_status.success() => (),
+        Some(Ok(exit_status)) => warn!(
+            "Post-backup hook failed: repo = {repo}, status = {exit_status}",
+            repo = repo.display()
+        ),
+        Some(Err(e)) => warn!(
+            "Unable to run post-backup hook: repo = {repo}, error = {e}",
+            repo = repo.display()
+        ),
+        None => warn!(
+            "Post-backup hook still running, not waiting for it: repo = {repo}, timeout = \
+             {timeout:?}",
+            repo = repo.display(),
+            timeout = GIT_OPERATION_TIMEOUT
+        ),
+    }
+}
+
+/// Logs when the repo at `path` just got uncommitted changes after being clean, see
+/// `Config::notify_on_dirty`.
+fn notify_if_newly_dirty(config: &Config, path: &Path) {
+    let watch_config = WatchConfig::clone(&config.watch_config_for(path).unwrap_or_default());
+    let repo_path = path.to_path_buf();
+    let dirty = timeout::run_with_timeout(GIT_OPERATION_TIMEOUT, move || {
+        let repo = Repository::open(&repo_path)?;
+        let statuses = repo.statuses(Some(&mut watch_config.dirty_status_options()))?;
+        Ok::<bool, git2::Error>(!statuses.is_empty())
+    });
+    let Some(Ok(dirty)) = dirty else {
+        return;
+    };
+    let mut runtime_lock = RuntimeLock::load();
+    if runtime_lock.record_dirty(path, dirty) {
+        info!(
+            "Repo has uncommitted changes, dura is watching: path = {path}",
+            path = path.display()
+        );
+    }
+    runtime_lock.save();
+}
+
+/// What the poller carries over from one poll to the next.
+pub struct PollState {
+    stats: StatCollector,
+    guard: PollGuard,
+    sizes: RepoSizes,
+    last_sample: Option<Instant>,
+    backoff: IdleBackoff,
+    debouncer: Debouncer,
+}
+
+impl PollState {
+    pub fn new() -> Self {
+        Self {
+            stats: StatCollector::new(),
+            guard: PollGuard::new(),
+            sizes: RepoSizes::new(),
+            last_sample: None,
+            backoff: IdleBackoff::new(POLL_INTERVAL),
+            debouncer: Debouncer::new(Duration::ZERO),
+        }
+    }
+}
+
+impl Default for PollState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Scans every watched repo and queues a backup for the ones that changed. Exits the process
+/// when another poller took over the runtime lock.
+#[tracing::instrument(skip(config, queue, state))]
+pub fn do_task(config: &Config, queue: &BackupQueue, state: &mut PollState) {
+    let PollState {
+        stats,
+        guard,
+        sizes,
+        last_sample,
+        backoff,
+        debouncer,
+    } = state;
+    let mut runtime_lock = RuntimeLock::load();
     if runtime_lock.pid != Some(process::id()) {
         error!(
             "Shutting down because other poller took lock: {:?}",
@@ -64,33 +398,251 @@ fn do_task(stats: &mut StatCollector, guard: &mut PollGuard) {
         );
         process::exit(1);
     }
+    runtime_lock.last_heartbeat = Some(SystemTime::now());
+    runtime_lock.config_hash = Some(config.content_hash());
+    runtime_lock.save();
 
-    let config = Config::load();
-
+    debouncer.set_window(Duration::from_millis(config.debounce_ms.unwrap_or(0)));
+    let now = SystemTime::now();
+    let mut changed = false;
     let loop_start = Instant::now();
     for repo in config.git_repos() {
         let dir_start = Instant::now();
-        process_directory(repo.as_path(), guard);
+        let throttled = config
+            .next_backup_eta_with(repo.as_path(), &runtime_lock)
+            .is_some_and(|eta| !eta.is_zero());
+        if config.exceeds_size_limit(repo.as_path(), sizes) {
+            trace!(
+                "Skipping repo, it exceeds the size limit: path = {path}",
+                path = repo.to_str().unwrap_or("")
+            );
+        } else if runtime_lock
+            .open_retry_at(repo.as_path(), SystemTime::now())
+            .is_some()
+        {
+            trace!(
+                "Skipping repo, it failed to open recently: path = {path}",
+                path = repo.to_str().unwrap_or("")
+            );
+        } else if !snapshots::is_repo(repo.as_path()) {
+            // loaded again, notify_if_newly_dirty may have saved the lock since
+            let mut failures = RuntimeLock::load();
+            let retry_in = failures.record_open_failure(repo.as_path(), SystemTime::now());
+            failures.save();
+            warn!(
+                "Unable to open repo, skipping it for {retry_in:?}: path = {path}",
+                path = repo.display()
+            );
+        } else if runtime_lock
+            .read_only_until(repo.as_path(), SystemTime::now())
+            .is_some()
+        {
+            trace!(
+                "Skipping repo, a backup failed to write to it recently: path = {path}",
+                path = repo.to_str().unwrap_or("")
+            );
+        } else if throttled {
+            // the poll guard compares against the last backup, the change is still seen later
+            trace!(
+                "Skipping repo, it was backed up less than its interval ago: path = {path}",
+                path = repo.to_str().unwrap_or("")
+            );
+        } else if guard.dir_changed(repo.as_path()) {
+            debug!(
+                "Potential change detected in repo: path = {path}",
+                path = repo.to_str().unwrap_or("")
+            );
+            // With a window it matters how recent the change is. When that can't be told, it's
+            // taken to be old enough, and a file dated in the future counts as changed now.
+            let changed_at = if debouncer.window().is_zero() {
+                now
+            } else {
+                guard
+                    .last_change(repo.as_path())
+                    .map_or(SystemTime::UNIX_EPOCH, |at| at.min(now))
+            };
+            if config.notify_on_dirty {
+                notify_if_newly_dirty(config, repo.as_path());
+            }
+            debouncer.record(repo, changed_at);
+            changed = true;
+        } else {
+            trace!(
+                "No files in repo have changed: path = {path}",
+                path = repo.to_str().unwrap_or("")
+            );
+            // without a state to start from, the first change from clean to dirty is missed
+            if config.notify_on_dirty && runtime_lock.dirty_state(repo.as_path()).is_none() {
+                notify_if_newly_dirty(config, repo.as_path());
+            }
+        }
         stats.record_dir(Instant::now() - dir_start);
     }
     stats.record_loop(Instant::now() - loop_start);
+    for repo in debouncer.due(now) {
+        queue.request(repo);
+    }
 
     if stats.should_log() {
         info!(operation = stats.log_str().as_str(), "poller_stats");
     }
+
+    sample_stats(config, last_sample);
+
+    backoff.set_max(config.max_poll_interval.unwrap_or(POLL_INTERVAL));
+    let interval = backoff.record_cycle(changed);
+    trace!("Next poll in {interval:?}");
+}
+
+/// Watches that a config reload added and removed, see `reload`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ConfigReload {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Replaces the daemon's `running` config with the one on disk, and records the new config's
+/// hash in the runtime lock so `info` stops reporting drift. Fails, keeping `running`, if the
+/// file can't be read or parsed, e.g. while it's half edited.
+pub fn reload(running: &mut Config) -> Result<ConfigReload, ConfigError> {
+    let loaded = Config::load_file(&Config::default_path())?;
+    let changes = ConfigReload {
+        added: loaded
+            .repos
+            .keys()
+            .filter(|key| !running.repos.contains_key(*key))
+            .cloned()
+            .collect(),
+        removed: running
+            .repos
+            .keys()
+            .filter(|key| !loaded.repos.contains_key(*key))
+            .cloned()
+            .collect(),
+    };
+    let mut runtime_lock = RuntimeLock::load();
+    runtime_lock.config_hash = Some(loaded.content_hash());
+    runtime_lock.save();
+    *running = loaded;
+    Ok(changes)
+}
+
+fn reload_and_log(running: &mut Config, shared: &SharedConfig) {
+    match reload(running) {
+        Ok(ConfigReload { added, removed }) => {
+            shared.set(running);
+            info!("Reloaded config: added = {added:?}, removed = {removed:?}");
+        }
+        Err(e) => warn!("Unable to reload the config, keeping the running one: {e}"),
+    }
+}
+
+/// When the config file was last modified, used to reload it after it's edited, on platforms
+/// without SIGHUP too.
+fn config_modified() -> Option<SystemTime> {
+    fs::metadata(Config::default_path())
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(unix)]
+type ReloadSignal = tokio::signal::unix::Signal;
+#[cfg(not(unix))]
+type ReloadSignal = ();
+
+#[cfg(unix)]
+fn reload_signal() -> Option<ReloadSignal> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    signal(SignalKind::hangup())
+        .map_err(|e| warn!("Unable to listen for SIGHUP, config edits are still picked up: {e}"))
+        .ok()
+}
+
+#[cfg(not(unix))]
+fn reload_signal() -> Option<ReloadSignal> {
+    None
+}
+
+/// Resolves when a reload was requested, never if that can't be listened for.
+async fn reload_requested(signal: &mut Option<ReloadSignal>) {
+    #[cfg(unix)]
+    if let Some(signal) = signal {
+        if signal.recv().await.is_some() {
+            return;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signal;
+    std::future::pending::<()>().await
+}
+
+/// Appends to the configured stats CSV, at most once per `STATS_SAMPLE_INTERVAL`.
+fn sample_stats(config: &Config, last_sample: &mut Option<Instant>) {
+    let Some(csv) = &config.stats_csv else {
+        return;
+    };
+    if last_sample.is_some_and(|at| at.elapsed() < STATS_SAMPLE_INTERVAL) {
+        return;
+    }
+    *last_sample = Some(Instant::now());
+    if let Err(e) = config.stats_over_time(csv) {
+        warn!(
+            "Unable to write stats: path = {path}, error = {e}",
+            path = csv.display()
+        );
+    }
 }
 
 pub async fn start() {
     let mut runtime_lock = RuntimeLock::load();
+    // a session that was never ended, e.g. because the daemon crashed
+    runtime_lock.end_session(SystemTime::now());
     runtime_lock.pid = Some(process::id());
     runtime_lock.start_time = Some(SystemTime::now());
+    runtime_lock.last_heartbeat = runtime_lock.start_time;
+    let mut config_mtime = config_modified();
+    let mut config = Config::load();
+    runtime_lock.config_hash = Some(config.content_hash());
+    let purged = runtime_lock.purge_unwatched(&config);
+    if purged > 0 {
+        info!("Dropped cached state of {purged} repos that are no longer watched");
+    }
     runtime_lock.save();
     info!(pid = std::process::id());
+    let features = capabilities::features();
+    for (missing, transport) in [(!features.https, "HTTPS"), (!features.ssh, "SSH")] {
+        if missing {
+            info!("libgit2 was built without {transport} support, such remotes can't be reached");
+        }
+    }
+
+    let (queue, receiver) = backup_queue(BACKUP_QUEUE_CAPACITY);
+    let shared_config = SharedConfig::new(&config);
+    let worker_config = shared_config.clone();
+    thread::spawn(move || {
+        let busy = Arc::new(Mutex::new(HashSet::new()));
+        while let Some(repo) = receiver.recv() {
+            process_directory(repo.as_path(), &busy, &worker_config);
+        }
+    });
 
-    let mut stats = StatCollector::new();
-    let mut guard = PollGuard::new();
+    let mut state = PollState::new();
+    let mut reload_signal = reload_signal();
     loop {
-        time::sleep(time::Duration::from_secs(5)).await;
-        do_task(&mut stats, &mut guard);
+        tokio::select! {
+            _ = time::sleep(state.backoff.interval()) => (),
+            _ = reload_requested(&mut reload_signal) => {
+                info!("Received SIGHUP, reloading config");
+                config_mtime = config_modified();
+                reload_and_log(&mut config, &shared_config);
+            }
+        }
+        let mtime = config_modified();
+        if mtime != config_mtime {
+            config_mtime = mtime;
+            reload_and_log(&mut config, &shared_config);
+        }
+        do_task(&config, &queue, &mut state);
     }
 }
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::read_only;
+
+/// A directory dura needs to be able to write to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightTarget {
+    ConfigHome,
+    CacheHome,
+    /// The `.git` directory of a watched repository
+    GitDir,
+}
+
+impl fmt::Display for PreflightTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreflightTarget::ConfigHome => f.write_str("config dir"),
+            PreflightTarget::CacheHome => f.write_str("cache dir"),
+            PreflightTarget::GitDir => f.write_str("git dir"),
+        }
+    }
+}
+
+/// Outcome of checking one directory, see `Config::preflight`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightResult {
+    pub target: PreflightTarget,
+    pub path: PathBuf,
+    /// Why the check failed, or None if dura can write there
+    pub error: Option<String>,
+}
+
+impl PreflightResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+const MARKER_FILE: &str = ".dura-preflight";
+
+/// Checks that `dir` is writable by creating and removing a marker file. The directory itself
+/// is created if it's missing, the same as dura does before saving to it. In read-only mode
+/// nothing is written, see `check_permissions`.
+pub fn check_writable(target: PreflightTarget, dir: &Path) -> PreflightResult {
+    fn try_write(dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let marker = dir.join(MARKER_FILE);
+        fs::write(&marker, "")?;
+        fs::remove_file(&marker)
+    }
+
+    let result = if read_only::is_enabled() {
+        check_permissions(dir)
+    } else {
+        try_write(dir)
+    };
+    PreflightResult {
+        target,
+        path: dir.to_path_buf(),
+        error: result.err().map(|e| e.to_string()),
+    }
+}
+
+/// Checks the permissions of `dir`, or of the closest parent it would be created in if it's
+/// missing, without writing anything. Only as good as the permissions, e.g. a read-only mount
+/// can still look writable.
+fn check_permissions(dir: &Path) -> io::Result<()> {
+    let existing = dir.ancestors().find(|path| path.exists()).unwrap_or(dir);
+    let metadata = fs::metadata(existing)?;
+    if !metadata.is_dir() {
+        let message = format!("{} is not a directory", existing.display());
+        return Err(io::Error::other(message));
+    }
+    if metadata.permissions().readonly() {
+        let message = format!("{} is read-only", existing.display());
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, message));
+    }
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Kinds of projects with a built-in set of excludes, see `WatchConfig::preset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    Rust,
+    Node,
+    Python,
+    /// Anything else. Excludes nothing, same as `WatchConfig::new`
+    Generic,
+}
+
+impl ProjectKind {
+    /// Build output and dependency directories that aren't worth a dura backup.
+    pub fn excludes(&self) -> &'static [&'static str] {
+        match self {
+            ProjectKind::Rust => &["target/**"],
+            ProjectKind::Node => &["node_modules/**"],
+            ProjectKind::Python => &["__pycache__/**", ".venv/**", "venv/**", ".tox/**"],
+            ProjectKind::Generic => &[],
+        }
+    }
+}
+
+impl fmt::Display for ProjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ProjectKind::Rust => "rust",
+            ProjectKind::Node => "node",
+            ProjectKind::Python => "python",
+            ProjectKind::Generic => "generic",
+        })
+    }
+}
+
+impl FromStr for ProjectKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rust" => Ok(ProjectKind::Rust),
+            "node" => Ok(ProjectKind::Node),
+            "python" => Ok(ProjectKind::Python),
+            "generic" => Ok(ProjectKind::Generic),
+            _ => Err(format!(
+                "Unknown project kind '{s}', expected one of rust, node, python, generic"
+            )),
+        }
+    }
+}
+
+/// Guesses the kind of project in `path` from the marker files at its top level.
+pub fn detect_project_kind(path: &Path) -> ProjectKind {
+    let has = |file: &str| path.join(file).exists();
+    if has("Cargo.toml") {
+        ProjectKind::Rust
+    } else if has("package.json") {
+        ProjectKind::Node
+    } else if has("pyproject.toml") || has("setup.py") || has("requirements.txt") {
+        ProjectKind::Python
+    } else {
+        ProjectKind::Generic
+    }
+}
@@ -0,0 +1,17 @@
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Read-only "audit" mode, for running dura purely to report status.
+///
+/// While enabled, dura never writes anything: saving the config or the runtime lock and taking
+/// snapshots are all skipped. It's enabled for the whole process, either by the `--read-only`
+/// flag or by setting the DURA_READ_ONLY environment variable.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    READ_ONLY.store(true, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    READ_ONLY.load(Ordering::SeqCst) || env::var("DURA_READ_ONLY").is_ok_and(|v| !v.is_empty())
+}
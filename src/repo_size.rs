@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use git2::Repository;
+use walkdir::WalkDir;
+
+use crate::timeout;
+
+/// How long a measured size is trusted before the git dir is walked again.
+const SIZE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// How long walking a git dir may take, e.g. on a hung mount, before it's taken to be empty
+/// until the next measurement.
+const MEASURE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Sizes of repos' git dirs, measured on first use and cached for `SIZE_TTL`, because walking a
+/// big `.git` on every poll would cost more than the backup it's meant to avoid.
+pub struct RepoSizes {
+    sizes: HashMap<PathBuf, (Instant, u64)>,
+}
+
+impl RepoSizes {
+    pub fn new() -> Self {
+        Self {
+            sizes: Default::default(),
+        }
+    }
+
+    /// Size in bytes of the git dir of the repo at `repo_path`. Unreadable files count as empty.
+    pub fn size(&mut self, repo_path: &Path) -> u64 {
+        match self.sizes.get(repo_path) {
+            Some((measured_at, size)) if measured_at.elapsed() < SIZE_TTL => *size,
+            _ => {
+                let path = repo_path.to_path_buf();
+                let size = timeout::run_with_timeout(MEASURE_TIMEOUT, move || git_dir_size(&path))
+                    .unwrap_or(0);
+                self.sizes
+                    .insert(repo_path.to_path_buf(), (Instant::now(), size));
+                size
+            }
+        }
+    }
+
+    pub fn exceeds(&mut self, repo_path: &Path, limit_mb: u64) -> bool {
+        self.size(repo_path) > limit_mb.saturating_mul(1024 * 1024)
+    }
+}
+
+impl Default for RepoSizes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn git_dir_size(repo_path: &Path) -> u64 {
+    let git_dir = match Repository::open(repo_path) {
+        Ok(repo) => repo.path().to_path_buf(),
+        Err(_) => return 0,
+    };
+    WalkDir::new(git_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
@@ -1,12 +1,235 @@
-// src/repo_status.rs
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use std::{fmt, fs, io};
 
-#[derive(Debug)]
+use git2::{Repository, RepositoryState};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::epoch_seconds;
+use crate::read_only;
+
+/// What `dura info` and `--format json` report about a watched repo. The JSON shape, including
+/// the field order, is what scripts read, so it's locked by `tests/fixtures/repo_status.json`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case")]
 pub struct RepoStatus {
     pub path: PathBuf,
+    /// The watch's `WatchConfig::label`, if it has one
+    #[serde(default)]
+    pub label: Option<String>,
     pub exists: bool,
+    /// The path can't be resolved right now, see `is_unavailable`. Its watch is kept, so it's
+    /// picked up again once the path comes back.
+    #[serde(default)]
+    pub unavailable: bool,
     pub is_git_repo: bool,
+    #[serde(default, with = "epoch_seconds::option")]
     pub last_backup: Option<SystemTime>,
+    /// How many backups the repo has
+    #[serde(default)]
+    pub backups: usize,
     pub uncommitted_changes: bool,
-}
\ No newline at end of file
+    /// How many files have uncommitted changes, see `Config::dirty_file_count`
+    #[serde(default)]
+    pub dirty_files: usize,
+    /// Why git couldn't tell whether there are uncommitted changes, e.g. a corrupt index.
+    /// `uncommitted_changes` is false then, but that doesn't mean the repo is clean.
+    #[serde(default)]
+    pub status_error: Option<String>,
+    /// Until when dura skips the repo because it failed to open, see
+    /// `RuntimeLock::record_open_failure`
+    #[serde(default, with = "epoch_seconds::option")]
+    pub open_retry_at: Option<SystemTime>,
+    /// Git operation the repo is in the middle of, if any. Dura keeps backing up during
+    /// these, but the backups contain e.g. conflict markers.
+    pub state: RepoState,
+    /// How the current branch compares to its upstream. None when it has no upstream, or HEAD
+    /// isn't on a branch.
+    #[serde(default)]
+    pub upstream: Option<Upstream>,
+}
+
+/// A branch's upstream, and how many commits each side has that the other doesn't.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct Upstream {
+    /// e.g. "origin/main"
+    pub name: String,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+impl Upstream {
+    /// The upstream of the branch HEAD is on, see `RepoStatus::upstream`.
+    pub fn of_head(repo: &Repository) -> Option<Self> {
+        let head = repo.head().ok().filter(|head| head.is_branch())?;
+        let branch = git2::Branch::wrap(head);
+        let upstream = branch.upstream().ok()?;
+        let name = upstream.name().ok()??.to_string();
+        let local = branch.get().target()?;
+        let remote = upstream.get().target()?;
+        let (ahead, behind) = repo.graph_ahead_behind(local, remote).ok()?;
+        Some(Self {
+            name,
+            ahead,
+            behind,
+        })
+    }
+}
+
+impl RepoStatus {
+    pub fn collect(config: &Config, path: &Path) -> Self {
+        let mut status = Self {
+            path: path.to_path_buf(),
+            label: path
+                .to_str()
+                .and_then(|key| config.repos.get(key))
+                .and_then(|watch_config| watch_config.label.clone()),
+            exists: path.exists(),
+            unavailable: is_unavailable(path),
+            is_git_repo: false,
+            last_backup: None,
+            backups: 0,
+            uncommitted_changes: false,
+            dirty_files: 0,
+            status_error: None,
+            open_retry_at: None,
+            state: RepoState::Normal,
+            upstream: None,
+        };
+        if let Ok(repo) = Repository::open(path) {
+            status.is_git_repo = true;
+            match config.dirty_file_count(path, &repo) {
+                Ok(dirty_files) => {
+                    status.uncommitted_changes = dirty_files > 0;
+                    status.dirty_files = dirty_files;
+                }
+                Err(e) => status.status_error = Some(e.message().to_string()),
+            }
+            status.state = repo.state().into();
+            status.upstream = Upstream::of_head(&repo);
+            let summary = config.count_backups(&repo);
+            status.backups = summary.count;
+            if summary.count > 0 {
+                status.last_backup = Some(
+                    SystemTime::UNIX_EPOCH + Duration::from_secs(summary.latest_time.max(0) as u64),
+                );
+            }
+        }
+        status
+    }
+
+    /// Whether the repo's directory, or its git dir, was modified after `time`. Used to tell
+    /// when a cached status is stale.
+    pub fn modified_since(&self, time: SystemTime) -> bool {
+        [self.path.clone(), self.path.join(".git")]
+            .iter()
+            .filter_map(|path| path.metadata().and_then(|m| m.modified()).ok())
+            .any(|modified| modified > time)
+    }
+}
+
+/// How dura describes a watched path that `is_unavailable`.
+pub const UNAVAILABLE: &str = "unavailable — path cannot be resolved";
+
+/// Whether resolving `path` fails for a reason other than it not existing, e.g. because the
+/// mount it's on went away or its permissions changed. Treated as temporary, unlike a path
+/// that's gone.
+pub fn is_unavailable(path: &Path) -> bool {
+    matches!(fs::canonicalize(path), Err(e) if e.kind() != io::ErrorKind::NotFound)
+}
+
+/// How dura describes a repo it can't write backups to, see `is_read_only`.
+pub const READ_ONLY: &str = "read-only, backups disabled";
+
+/// Whether `repo`'s git dir can't be written to, e.g. a checkout on a read-only volume, found
+/// out by creating a file in it. Backups aren't attempted then. In read-only mode nothing may
+/// be written, so it only goes by the permissions, which fall short for other users' repos,
+/// root and read-only mounts.
+pub fn is_read_only(repo: &Repository) -> bool {
+    if read_only::is_enabled() {
+        return fs::metadata(repo.path()).is_ok_and(|metadata| metadata.permissions().readonly());
+    }
+    let probe = repo
+        .path()
+        .join(format!("dura-write-check-{}", std::process::id()));
+    match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            false
+        }
+        Err(e) => matches!(
+            e.kind(),
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+        ),
+    }
+}
+
+/// Whether `error` means git couldn't write to the object or ref store in `git_dir`, because
+/// its file system is read-only or writing isn't permitted. Failing to read a file of the
+/// working tree isn't such an error, the repo can still be written to.
+pub fn is_write_error(error: &git2::Error, git_dir: &Path) -> bool {
+    let message = error.message();
+    let lowercase = message.to_lowercase();
+    let denied =
+        lowercase.contains("read-only file system") || lowercase.contains("permission denied");
+    let in_store = ["objects", "refs", "logs"]
+        .iter()
+        .any(|store| message.contains(git_dir.join(store).to_string_lossy().as_ref()));
+    error.class() == git2::ErrorClass::Os && denied && in_store
+}
+
+/// Simplified `git2::RepositoryState`, e.g. the variants for the different kinds of rebase are
+/// collapsed into one.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RepoState {
+    /// No operation in progress
+    Normal,
+    Merge,
+    Revert,
+    CherryPick,
+    Bisect,
+    Rebase,
+    ApplyMailbox,
+}
+
+impl From<RepositoryState> for RepoState {
+    fn from(state: RepositoryState) -> Self {
+        match state {
+            RepositoryState::Clean => RepoState::Normal,
+            RepositoryState::Merge => RepoState::Merge,
+            RepositoryState::Revert | RepositoryState::RevertSequence => RepoState::Revert,
+            RepositoryState::CherryPick | RepositoryState::CherryPickSequence => {
+                RepoState::CherryPick
+            }
+            RepositoryState::Bisect => RepoState::Bisect,
+            RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge => RepoState::Rebase,
+            RepositoryState::ApplyMailbox | RepositoryState::ApplyMailboxOrRebase => {
+                RepoState::ApplyMailbox
+            }
+        }
+    }
+}
+
+impl fmt::Display for RepoState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            RepoState::Normal => "none",
+            RepoState::Merge => "merge",
+            RepoState::Revert => "revert",
+            RepoState::CherryPick => "cherry-pick",
+            RepoState::Bisect => "bisect",
+            RepoState::Rebase => "rebase",
+            RepoState::ApplyMailbox => "am",
+        };
+        write!(f, "{}", name)
+    }
+}
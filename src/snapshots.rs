@@ -1,15 +1,64 @@
-use git2::{BranchType, DiffOptions, Error, IndexAddOption, Repository, Signature};
+use chrono::{Local, TimeZone};
+use git2::{
+    BranchType, Commit, Cred, CredentialType, DiffOptions, Error, ErrorCode, Index, IndexAddOption,
+    Oid, PushOptions, RemoteCallbacks, Repository, Signature, StatusOptions, Time, Tree,
+};
 use serde::{Deserialize, Serialize};
-use std::fmt;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{fmt, fs};
+use tracing::{debug, info, warn};
 
-use crate::config::Config;
+use crate::backups::{self, PrunePolicy};
+use crate::capabilities::{self, Features};
+use crate::clock::{Clock, SystemClock};
+use crate::compiled_watch::CompiledWatch;
+use crate::config::{BackupStrategy, Config, DuraFiles, SubmoduleMode, WatchConfig};
+use crate::read_only;
+use crate::repo_status;
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq)]
+/// Commit summary dura writes on every backup, and how backups are recognized later on, see
+/// `backups::is_backup_summary`. It's the whole message, so nothing can come after it.
+pub const BACKUP_MESSAGE: &str = "dura auto-backup";
+
+/// Notes ref dura writes a `BackupNote` to for each backup, when `Config::write_backup_notes`
+/// is on. Backups with a note are recognized by it even if their message was changed.
+pub const NOTES_REF: &str = "refs/notes/dura";
+
+/// Metadata about a backup, stored as JSON in its note.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct BackupNote {
+    pub dura_version: String,
+    /// Files that differ from the backup's parent
+    pub files_changed: usize,
+}
+
+/// The ref `git stash` keeps its newest entry in. The older ones are in its reflog.
+pub const STASH_REF: &str = "refs/stash";
+
+/// Branch for backups of a repo without any commits, since there's no HEAD commit to name a
+/// `dura/<commit>` branch after. Its backups have no parent.
+pub const UNBORN_BRANCH: &str = "dura/unborn";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct CaptureStatus {
     pub dura_branch: String,
     pub commit_hash: String,
     pub base_hash: String,
+    /// How pushing the backup to each of the watch's `push_remotes` went, in their order.
+    /// Empty until `push_backup` is done with it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pushes: Vec<RemotePush>,
+}
+
+/// The outcome of pushing a backup to one of `WatchConfig::push_remotes`.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct RemotePush {
+    /// The remote as it's listed in the watch, a name or a URL
+    pub remote: String,
+    /// Why the push failed, None if it succeeded
+    pub error: Option<String>,
 }
 
 impl fmt::Display for CaptureStatus {
@@ -26,21 +75,280 @@ pub fn is_repo(path: &Path) -> bool {
     Repository::open(path).is_ok()
 }
 
+/// Whether another git process holds `repo`'s index lock, e.g. in the middle of a commit or a
+/// rebase. Backups wait for the next poll then, rather than read a half-written index.
+pub fn index_locked(repo: &Repository) -> bool {
+    repo.path().join("index.lock").exists()
+}
+
+/// What HEAD is on, see `head_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadState {
+    /// A commit, or something `Repository::head` reports an error for
+    Born,
+    /// A branch without commits yet, in a freshly initialized repo
+    Unborn,
+    /// A branch that doesn't exist although other branches do, e.g. because it got deleted.
+    /// Holds the ref HEAD points to.
+    Broken(String),
+}
+
+/// Where HEAD is, telling a freshly initialized repo from one whose branch is gone. Git can't
+/// tell them apart, HEAD points to a branch that doesn't exist in both, so a repo with any
+/// branch but dura's own counts as broken. This lists the branches, so callers that need both
+/// `is_unborn` and `broken_head` should call it once instead.
+pub fn head_state(repo: &Repository) -> HeadState {
+    if !repo
+        .head()
+        .is_err_and(|e| e.code() == ErrorCode::UnbornBranch)
+    {
+        return HeadState::Born;
+    }
+    let has_branches = repo
+        .branches(Some(BranchType::Local))
+        .is_ok_and(|mut branches| {
+            branches.any(|branch| {
+                branch.is_ok_and(|(branch, _)| {
+                    branch
+                        .name()
+                        .ok()
+                        .flatten()
+                        .is_some_and(|name| !name.starts_with("dura/"))
+                })
+            })
+        });
+    let target = repo
+        .find_reference("HEAD")
+        .ok()
+        .and_then(|head| head.symbolic_target().map(str::to_string));
+    match target {
+        Some(target) if has_branches => HeadState::Broken(target),
+        _ => HeadState::Unborn,
+    }
+}
+
+/// Whether `repo` is freshly initialized, with HEAD on a branch that has no commits yet.
+pub fn is_unborn(repo: &Repository) -> bool {
+    head_state(repo) == HeadState::Unborn
+}
+
+/// The ref HEAD points to when that doesn't exist, although other branches do, e.g. after the
+/// branch HEAD was on got deleted, see `head_state`. Such a repo isn't backed up until HEAD is
+/// fixed, unless `Config::backup_broken_head_repos` is set.
+pub fn broken_head(repo: &Repository) -> Option<String> {
+    match head_state(repo) {
+        HeadState::Broken(target) => Some(target),
+        _ => None,
+    }
+}
+
+/// Name of the branch HEAD is on, e.g. "main", also when it has no commits yet. None when HEAD
+/// is detached.
+pub fn current_branch(repo: &Repository) -> Option<String> {
+    let head = repo.find_reference("HEAD").ok()?;
+    head.symbolic_target()?
+        .strip_prefix("refs/heads/")
+        .map(str::to_string)
+}
+
+/// Backs up the repo at `path`, going by the config at the default path. The backup isn't
+/// pushed to the watch's `push_remotes` yet, see `push_backup`, so that a slow remote can't
+/// hold up a backup that's already made.
 pub fn capture(path: &Path) -> Result<Option<CaptureStatus>, Error> {
+    capture_with(&Config::load(), path)
+}
+
+/// Like `capture`, but with `dura_cfg` instead of loading the config from disk, for embedding
+/// dura or testing it without config files.
+pub fn capture_with(dura_cfg: &Config, path: &Path) -> Result<Option<CaptureStatus>, Error> {
+    capture_with_clock(dura_cfg, path, &SystemClock)
+}
+
+/// Like `capture_with`, but the backup commit is dated by `clock`, e.g. a `FixedClock` for
+/// reproducible backups.
+pub fn capture_with_clock(
+    dura_cfg: &Config,
+    path: &Path,
+    clock: &dyn Clock,
+) -> Result<Option<CaptureStatus>, Error> {
+    Ok(match capture_outcome(dura_cfg, path, clock)? {
+        CaptureOutcome::Backup(status) => Some(status),
+        CaptureOutcome::Unchanged | CaptureOutcome::Skipped(_) => None,
+    })
+}
+
+/// What `capture_outcome` did with a repo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureOutcome {
+    /// A backup was made
+    Backup(CaptureStatus),
+    /// There was nothing to back up, e.g. nothing changed since the last backup, or the watch
+    /// leaves the current branch out
+    Unchanged,
+    /// The repo may well have changes, but couldn't be backed up right now
+    Skipped(SkipReason),
+}
+
+/// Why `capture_outcome` didn't look at a repo's changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// dura is in read-only mode, see `read_only::is_enabled`
+    ReadOnlyMode,
+    /// Another git process holds the index lock, see `index_locked`
+    IndexLocked,
+    /// HEAD points to a branch that's gone, see `broken_head`
+    BrokenHead(String),
+    /// The git dir can't be written to, see `repo_status::is_read_only`
+    ReadOnlyGitDir,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::ReadOnlyMode => write!(f, "read-only"),
+            SkipReason::IndexLocked => write!(f, "git busy"),
+            SkipReason::BrokenHead(target) => write!(f, "broken HEAD: {}", target),
+            SkipReason::ReadOnlyGitDir => write!(f, "{}", repo_status::READ_ONLY),
+        }
+    }
+}
+
+/// Like `capture_with_clock`, but tells a repo without changes apart from one that was skipped,
+/// and why.
+pub fn capture_outcome(
+    dura_cfg: &Config,
+    path: &Path,
+    clock: &dyn Clock,
+) -> Result<CaptureOutcome, Error> {
+    let skip = |reason: SkipReason| {
+        info!("Backup of {} skipped ({})", path.display(), reason);
+        Ok(CaptureOutcome::Skipped(reason))
+    };
+    if read_only::is_enabled() {
+        return skip(SkipReason::ReadOnlyMode);
+    }
+
     let repo = Repository::open(path)?;
-    let head = repo.head()?.peel_to_commit()?;
-    let message = "dura auto-backup";
+    if index_locked(&repo) {
+        return skip(SkipReason::IndexLocked);
+    }
+    let head_state = head_state(&repo);
+    if let HeadState::Broken(target) = &head_state {
+        if !dura_cfg.backup_broken_head_repos {
+            return skip(SkipReason::BrokenHead(target.clone()));
+        }
+        warn!(
+            "HEAD of {} points to {}, which doesn't exist, backing up to {}",
+            path.display(),
+            target,
+            UNBORN_BRANCH
+        );
+    }
+    if repo_status::is_read_only(&repo) {
+        return skip(SkipReason::ReadOnlyGitDir);
+    }
+    Ok(match back_up(dura_cfg, path, &repo, &head_state, clock)? {
+        Some(status) => CaptureOutcome::Backup(status),
+        None => CaptureOutcome::Unchanged,
+    })
+}
+
+/// `capture_outcome` for a repo that can be backed up.
+fn back_up(
+    dura_cfg: &Config,
+    path: &Path,
+    repo: &Repository,
+    head_state: &HeadState,
+    clock: &dyn Clock,
+) -> Result<Option<CaptureStatus>, Error> {
+    // None when HEAD is unborn, i.e. nothing has been committed yet, or broken and backed up all
+    // the same
+    let back_up_unborn = match head_state {
+        HeadState::Broken(_) => true,
+        _ => dura_cfg.backup_unborn_repos,
+    };
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(e) if e.code() == ErrorCode::UnbornBranch && back_up_unborn => None,
+        Err(e) if e.code() == ErrorCode::UnbornBranch => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let head_id = head.as_ref().map_or_else(Oid::zero, |head| head.id());
+    let watch_config = dura_cfg.watch_config_for(path).unwrap_or_default();
+    if !watch_config.backs_up_branch(current_branch(repo).as_deref()) {
+        debug!(
+            "Backup of {} skipped, not on one of its branches",
+            path.display()
+        );
+        return Ok(None);
+    }
+    let submodule_mode = watch_config.submodule_mode;
+    let workdir = repo.workdir().unwrap_or(path).to_path_buf();
+    let forced = forced_pathspecs(dura_cfg, &watch_config, path, &workdir);
+    let watch_prefix = watch_prefix(dura_cfg, path);
+    let dura_files = DuraFiles::current();
+    let real_workdir = fs::canonicalize(&workdir).unwrap_or_else(|_| workdir.clone());
 
     // status check
-    if repo.statuses(None)?.is_empty() {
+    let mut status_opts = StatusOptions::new();
+    status_opts
+        .include_untracked(watch_config.backup_untracked)
+        .recurse_untracked_dirs(watch_config.backup_untracked)
+        .include_ignored(!forced.is_empty())
+        .exclude_submodules(submodule_mode == SubmoduleMode::Ignore);
+    let statuses = repo.statuses(Some(&mut status_opts))?;
+    if statuses.is_empty() {
         return Ok(None);
     }
+    let own_writes_only = statuses.iter().all(|entry| {
+        entry
+            .path()
+            .is_some_and(|changed| dura_files.is_dura_write(&real_workdir.join(changed)))
+    });
+    if own_writes_only {
+        debug!(
+            "Backup of {} skipped, only dura's own files changed",
+            path.display()
+        );
+        return Ok(None);
+    }
+    // before anything is written, so a bad identity doesn't leave a branch without a backup
+    let (name, email) = resolve_identity(dura_cfg, repo)?;
+    if dura_cfg.backup_strategy == BackupStrategy::Stash {
+        let committer = signature_at(&name, &email, clock)?;
+        return stash_backup(
+            repo,
+            head.as_ref(),
+            &watch_config,
+            &workdir,
+            &watch_prefix,
+            &forced,
+            &committer,
+        );
+    }
 
-    let branch_name = format!("dura/{}", head.id());
-    let branch_commit = match repo.find_branch(&branch_name, BranchType::Local) {
+    let branch_name = match &head {
+        Some(head) => format!("dura/{}", head.id()),
+        None => UNBORN_BRANCH.to_string(),
+    };
+    // The repo dura branches live in. With a mirror, the working repo only gets the objects.
+    let mirror = watch_config
+        .mirror_path
+        .as_deref()
+        .map(open_mirror)
+        .transpose()?;
+    let refs_repo = mirror.as_ref().unwrap_or(repo);
+    let branch_commit = match refs_repo.find_branch(&branch_name, BranchType::Local) {
         Ok(mut branch) => {
-            match branch.get().peel_to_commit() {
-                Ok(commit) if commit.id() != head.id() => Some(commit),
+            let tip = branch.get().target();
+            if let (Some(mirror), Some(tip)) = (&mirror, tip) {
+                // e.g. garbage collected from the working repo since the last backup
+                if repo.find_commit(tip).is_err() {
+                    copy_objects(mirror, repo, tip)?;
+                }
+            }
+            match tip.map(|tip| repo.find_commit(tip)) {
+                Some(Ok(commit)) if commit.id() != head_id => Some(commit),
                 _ => {
                     // Dura branch exist but no commit is made by dura
                     // So we clean this branch
@@ -51,74 +359,501 @@ pub fn capture(path: &Path) -> Result<Option<CaptureStatus>, Error> {
         }
         Err(_) => None,
     };
-    let parent_commit = branch_commit.as_ref().unwrap_or(&head);
-
-    // tree
-    let mut index = repo.index()?;
-    index.add_all(["*"].iter(), IndexAddOption::DEFAULT, None)?;
+    let parent_commit = branch_commit.as_ref().or(head.as_ref());
+    let parent_tree = parent_commit.map(|commit| commit.tree()).transpose()?;
 
-    let dirty_diff = repo.diff_tree_to_index(
-        Some(&parent_commit.tree()?),
-        Some(&index),
-        Some(DiffOptions::new().include_untracked(true)),
+    let (mut index, files_changed) = stage_backup(
+        repo,
+        &watch_config,
+        &workdir,
+        &watch_prefix,
+        &forced,
+        parent_tree.as_ref(),
     )?;
-    if dirty_diff.deltas().len() == 0 {
+    if files_changed == 0 {
         return Ok(None);
     }
 
     let tree_oid = index.write_tree()?;
     let tree = repo.find_tree(tree_oid)?;
-    if repo.find_branch(&branch_name, BranchType::Local).is_err() {
-        repo.branch(branch_name.as_str(), &head, false)?;
+    if let (Some(head), None) = (&head, &mirror) {
+        if repo.find_branch(&branch_name, BranchType::Local).is_err() {
+            repo.branch(branch_name.as_str(), head, false)?;
+        }
     }
 
-    let committer = Signature::now(&get_git_author(&repo), &get_git_email(&repo))?;
+    let committer = signature_at(&name, &email, clock)?;
+    let branch_ref = format!("refs/heads/{}", &branch_name);
     let oid = repo.commit(
-        Some(&format!("refs/heads/{}", &branch_name)),
+        mirror.is_none().then_some(branch_ref.as_str()),
         &committer,
         &committer,
-        message,
+        BACKUP_MESSAGE,
         &tree,
-        &[parent_commit],
+        parent_commit.as_slice(),
     )?;
+    if let Some(mirror) = &mirror {
+        copy_objects(repo, mirror, oid)?;
+        mirror.reference(&branch_ref, oid, true, BACKUP_MESSAGE)?;
+    }
+    if dura_cfg.write_backup_notes {
+        let note = BackupNote {
+            dura_version: env!("CARGO_PKG_VERSION").to_string(),
+            files_changed,
+        };
+        let json = serde_json::to_string(&note).map_err(|e| Error::from_str(&e.to_string()))?;
+        refs_repo.note(&committer, &committer, Some(NOTES_REF), oid, &json, false)?;
+    }
+    let mut oid = oid;
+    let policies = [
+        watch_config
+            .max_backups
+            .filter(|&keep| keep > 0)
+            .map(|keep| (PrunePolicy::KeepLatest { keep }, "max_backups")),
+        watch_config.thin_to_daily_after_days.map(|days| {
+            let keep_recent = days.max(1);
+            (
+                PrunePolicy::OnePerDay { keep_recent },
+                "thin_to_daily_after_days",
+            )
+        }),
+    ];
+    for (policy, option) in policies.into_iter().flatten() {
+        // the backup is made either way, so a failed prune is only worth a warning
+        match backups::prune_backups(refs_repo, policy, Local::now(), Some(oid)) {
+            Ok(0) => (),
+            Ok(pruned) => {
+                info!("Pruned {pruned} backups of {} for {option}", path.display());
+                // the new backup was recreated if an older one on its branch went
+                if let Some(tip) = refs_repo
+                    .find_reference(&branch_ref)
+                    .ok()
+                    .and_then(|r| r.target())
+                {
+                    oid = tip;
+                }
+            }
+            Err(e) => warn!("Unable to prune backups of {}: {}", path.display(), e),
+        }
+    }
 
     Ok(Some(CaptureStatus {
         dura_branch: branch_name,
         commit_hash: oid.to_string(),
+        base_hash: head_id.to_string(),
+        pushes: Vec::new(),
+    }))
+}
+
+/// A signature for `name` and `email` at `clock`'s time, in the local time zone like
+/// `Signature::now`.
+fn signature_at(name: &str, email: &str, clock: &dyn Clock) -> Result<Signature<'static>, Error> {
+    let secs = match clock.now().duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    };
+    let offset = Local.timestamp(secs, 0).offset().local_minus_utc() / 60;
+    Signature::new(name, email, &Time::new(secs, offset))
+}
+
+/// Stages the working tree into a fresh index, the way `watch_config` says, and counts the files
+/// that differ from `parent_tree`.
+fn stage_backup(
+    repo: &Repository,
+    watch_config: &WatchConfig,
+    workdir: &Path,
+    watch_prefix: &Path,
+    forced: &[String],
+    parent_tree: Option<&Tree>,
+) -> Result<(Index, usize), Error> {
+    let mut index = detached_index(repo)?;
+    let compiled = CompiledWatch::cached(watch_config);
+    let dura_files = DuraFiles::current();
+    let real_workdir = fs::canonicalize(workdir).unwrap_or_else(|_| workdir.to_path_buf());
+    let mut skip_large = |file: &Path, _: &[u8]| -> i32 {
+        if dura_files.is_cache_file(&real_workdir.join(file)) {
+            debug!(
+                "Leaving {} out of the backup, it's dura's own cache",
+                file.display()
+            );
+            return 1;
+        }
+        if compiled.has_excludes() && compiled.excludes_file(&watch_prefix.join(file)) {
+            debug!(
+                "Leaving {} out of the backup, it's excluded",
+                file.display()
+            );
+            return 1;
+        }
+        let limit_mb = match watch_config.max_file_size_mb {
+            Some(limit_mb) => limit_mb,
+            None => return 0,
+        };
+        match fs::metadata(workdir.join(file)) {
+            Ok(metadata)
+                if metadata.is_file() && metadata.len() > limit_mb.saturating_mul(1024 * 1024) =>
+            {
+                info!(
+                    "Leaving {} out of the backup, it's over {} MB",
+                    file.display(),
+                    limit_mb
+                );
+                1
+            }
+            _ => 0,
+        }
+    };
+    if watch_config.backup_untracked {
+        index.add_all(["*"].iter(), IndexAddOption::DEFAULT, Some(&mut skip_large))?;
+    } else {
+        // only files the index already knows about
+        index.update_all(["*"].iter(), Some(&mut skip_large))?;
+    }
+    if !forced.is_empty() {
+        index.add_all(forced.iter(), IndexAddOption::FORCE, Some(&mut skip_large))?;
+    }
+    stage_submodules(repo, &mut index, parent_tree, watch_config.submodule_mode)?;
+
+    let dirty_diff = repo.diff_tree_to_index(
+        parent_tree,
+        Some(&index),
+        Some(DiffOptions::new().include_untracked(true)),
+    )?;
+    let files_changed = dirty_diff.deltas().len();
+    Ok((index, files_changed))
+}
+
+/// `capture_with` for `BackupStrategy::Stash`. Writes the same commits `git stash` does, one
+/// for the index and one for the working tree on top of HEAD, and adds the latter to the stash,
+/// but leaves the working tree as it is.
+fn stash_backup(
+    repo: &Repository,
+    head: Option<&Commit>,
+    watch_config: &WatchConfig,
+    workdir: &Path,
+    watch_prefix: &Path,
+    forced: &[String],
+    committer: &Signature,
+) -> Result<Option<CaptureStatus>, Error> {
+    let Some(head) = head else {
+        info!("Backup skipped, a repo without commits has nothing to stash on");
+        return Ok(None);
+    };
+    // what's staged, before staging the backup replaces the repo's index
+    let staged = repo.find_tree(repo.index()?.write_tree()?)?;
+    // the newest backup stashed on the same commit, if it's on top of the stash
+    let previous = repo
+        .find_reference(STASH_REF)
+        .and_then(|reference| reference.peel_to_commit())
+        .ok()
+        .filter(|commit| backups::is_backup(commit) && commit.parent_id(0).ok() == Some(head.id()));
+    let base_tree = previous.as_ref().unwrap_or(head).tree()?;
+    let (mut index, files_changed) = stage_backup(
+        repo,
+        watch_config,
+        workdir,
+        watch_prefix,
+        forced,
+        Some(&base_tree),
+    )?;
+    if files_changed == 0 {
+        return Ok(None);
+    }
+    let tree = repo.find_tree(index.write_tree()?)?;
+
+    let branch = current_branch(repo).unwrap_or_else(|| "(no branch)".to_string());
+    let head_line = format!(
+        "{} {}",
+        &head.id().to_string()[..7],
+        head.summary().unwrap_or_default()
+    );
+    let index_oid = repo.commit(
+        None,
+        committer,
+        committer,
+        &format!("index on {branch}: {head_line}"),
+        &staged,
+        &[head],
+    )?;
+    let message = format!("On {branch}: {BACKUP_MESSAGE}");
+    let oid = repo.commit(
+        None,
+        committer,
+        committer,
+        &message,
+        &tree,
+        &[head, &repo.find_commit(index_oid)?],
+    )?;
+    // like git, so the entry below the new one stays in the stash
+    repo.reference_ensure_log(STASH_REF)?;
+    repo.reference(STASH_REF, oid, true, &message)?;
+
+    Ok(Some(CaptureStatus {
+        dura_branch: "stash@{0}".to_string(),
+        commit_hash: oid.to_string(),
         base_hash: head.id().to_string(),
+        pushes: Vec::new(),
     }))
 }
 
-fn get_git_author(repo: &Repository) -> String {
-    let dura_cfg = Config::load();
-    if let Some(value) = dura_cfg.commit_author {
-        return value;
+/// The watch's includes as pathspecs relative to `workdir`, when they're to be backed up even
+/// if gitignored. Includes are relative to the watched dir, which may be above the repo; ones
+/// outside of the repo are left out.
+fn forced_pathspecs(
+    dura_cfg: &Config,
+    watch_config: &WatchConfig,
+    path: &Path,
+    workdir: &Path,
+) -> Vec<String> {
+    let root = match dura_cfg.watch_entry_for(path) {
+        Some((root, _)) if watch_config.include_overrides_gitignore => PathBuf::from(root),
+        _ => return Vec::new(),
+    };
+    watch_config
+        .include
+        .iter()
+        .filter_map(|include| {
+            let full = root.join(include);
+            let relative = full.strip_prefix(workdir).ok()?;
+            let pathspec = relative.to_str()?;
+            (!pathspec.is_empty()).then(|| pathspec.to_string())
+        })
+        .collect()
+}
+
+/// Pushes `refspec` from `repo` to the remote at `url`, authenticating like git does, through
+/// the ssh agent or the user's credential helper. When `features` say this build can't reach
+/// `url`, that's the error, before anything is sent.
+pub fn push_to(
+    repo: &Repository,
+    url: &str,
+    refspec: &str,
+    features: &Features,
+) -> Result<(), Error> {
+    features
+        .require_transport(url)
+        .map_err(|e| Error::from_str(&e.to_string()))?;
+
+    let config = repo.config()?;
+    let mut attempts = 0;
+    let mut rejection = None;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        // libgit2 asks again after a rejected credential, so give up at some point
+        attempts += 1;
+        if attempts > 3 {
+            return Err(Error::from_str("authentication failed"));
+        }
+        match username {
+            Some(username) if allowed.contains(CredentialType::SSH_KEY) => {
+                Cred::ssh_key_from_agent(username)
+            }
+            _ if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) => {
+                Cred::credential_helper(&config, url, username)
+            }
+            _ => Cred::default(),
+        }
+    });
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            rejection = Some(format!("{reference} was rejected: {status}"));
+        }
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    repo.remote_anonymous(url)?
+        .push(&[refspec], Some(&mut options))?;
+    drop(options);
+    match rejection {
+        Some(rejection) => Err(Error::from_str(&rejection)),
+        None => Ok(()),
     }
+}
 
-    if !dura_cfg.commit_exclude_git_config {
-        if let Ok(git_cfg) = repo.config() {
-            if let Ok(value) = git_cfg.get_string("user.name") {
-                return value;
+/// Pushes the branch of `status`, a backup `capture_with` made of the repo at `path`, to the
+/// `push_remotes` of its watch in `dura_cfg`.
+pub fn push_backup(dura_cfg: &Config, path: &Path, status: &CaptureStatus) -> Vec<RemotePush> {
+    let watch_config = dura_cfg.watch_config_for(path).unwrap_or_default();
+    push_backup_for(&watch_config, path, status)
+}
+
+/// Like `push_backup`, with the watch's config already looked up, e.g. to push on another
+/// thread. A backup in the stash isn't pushed.
+pub fn push_backup_for(
+    watch_config: &WatchConfig,
+    path: &Path,
+    status: &CaptureStatus,
+) -> Vec<RemotePush> {
+    let remotes = &watch_config.push_remotes;
+    if remotes.is_empty() || !status.dura_branch.starts_with("dura/") {
+        return Vec::new();
+    }
+    let repo = match &watch_config.mirror_path {
+        Some(mirror_path) => open_mirror(mirror_path),
+        None => Repository::open(path),
+    };
+    match repo {
+        Ok(repo) => push_branch(
+            &repo,
+            remotes,
+            &format!("refs/heads/{}", status.dura_branch),
+        ),
+        Err(e) => remotes
+            .iter()
+            .map(|remote| RemotePush {
+                remote: remote.clone(),
+                error: Some(e.message().to_string()),
+            })
+            .collect(),
+    }
+}
+
+/// Pushes `branch_ref` to each of `remotes`, which are remote names of `repo` or URLs. Forced,
+/// since pruning rewrites dura branches.
+fn push_branch(repo: &Repository, remotes: &[String], branch_ref: &str) -> Vec<RemotePush> {
+    let refspec = format!("+{branch_ref}:{branch_ref}");
+    remotes
+        .iter()
+        .map(|remote| {
+            let url = match repo.find_remote(remote) {
+                Ok(found) => found.pushurl().or(found.url()).map(str::to_string),
+                Err(_) => Some(remote.clone()),
+            };
+            let result = match url {
+                Some(url) => push_to(repo, &url, &refspec, capabilities::features()),
+                None => Err(Error::from_str("the remote has no URL")),
+            };
+            RemotePush {
+                remote: remote.clone(),
+                error: result.err().map(|e| e.message().to_string()),
+            }
+        })
+        .collect()
+}
+
+/// The bare repo at `path` that backups are mirrored to, created if it doesn't exist yet.
+fn open_mirror(path: &Path) -> Result<Repository, Error> {
+    if path.exists() {
+        Repository::open_bare(path)
+    } else {
+        Repository::init_bare(path)
+    }
+}
+
+/// Copies the commit `oid`, its history and everything they point to, from `from` to `to`.
+/// What `to` already has through one of its refs is left out.
+fn copy_objects(from: &Repository, to: &Repository, oid: Oid) -> Result<(), Error> {
+    let mut walk = from.revwalk()?;
+    walk.push(oid)?;
+    for reference in to.references()?.flatten() {
+        if let Some(tip) = reference.target() {
+            if from.find_commit(tip).is_ok() {
+                walk.hide(tip)?;
             }
         }
     }
+    let mut builder = from.packbuilder()?;
+    builder.insert_walk(&mut walk)?;
+    if builder.object_count() == 0 {
+        return Ok(());
+    }
+    let odb = to.odb()?;
+    let mut writer = odb.packwriter()?;
+    builder.foreach(|chunk| writer.write_all(chunk).is_ok())?;
+    writer.commit()?;
+    Ok(())
+}
 
-    "dura".to_string()
+/// Where the repo at `path` sits in the watch covering it, since a watch's include and exclude
+/// patterns are relative to the watched dir. Empty when the repo is the watched dir, or isn't
+/// watched at all.
+fn watch_prefix(dura_cfg: &Config, path: &Path) -> PathBuf {
+    let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    dura_cfg
+        .watch_entry_for(&resolved)
+        .and_then(|(root, _)| resolved.strip_prefix(root).ok())
+        .map(Path::to_path_buf)
+        .unwrap_or_default()
 }
 
-fn get_git_email(repo: &Repository) -> String {
-    let dura_cfg = Config::load();
-    if let Some(value) = dura_cfg.commit_email {
-        return value;
+/// A copy of the user's staging area that lives in memory only. The snapshot is staged into
+/// it, so nothing dura does can end up in the real index, even if something writes it out.
+fn detached_index(repo: &Repository) -> Result<Index, Error> {
+    let mut index = Index::new()?;
+    for entry in repo.index()?.iter() {
+        index.add(&entry)?;
     }
+    // staging the working tree needs an index that belongs to the repo
+    repo.set_index(&mut index)?;
+    Ok(index)
+}
 
-    if !dura_cfg.commit_exclude_git_config {
-        if let Ok(git_cfg) = repo.config() {
-            if let Ok(value) = git_cfg.get_string("user.email") {
-                return value;
+/// Puts each submodule's entry in `index` in line with `submodule_mode`: the submodule's current
+/// commit when recording pointers, otherwise whatever `parent_tree` had.
+fn stage_submodules(
+    repo: &Repository,
+    index: &mut Index,
+    parent_tree: Option<&Tree>,
+    submodule_mode: SubmoduleMode,
+) -> Result<(), Error> {
+    for submodule in repo.submodules()? {
+        let path = submodule.path();
+        match submodule_mode {
+            SubmoduleMode::RecordPointer => {
+                // fails when the submodule isn't checked out, there's no pointer to record then
+                let _ = index.add_path(path);
+            }
+            SubmoduleMode::Ignore => {
+                let parent_entry = parent_tree.and_then(|tree| tree.get_path(path).ok());
+                match (index.get_path(path, 0), parent_entry) {
+                    (Some(mut entry), Some(parent_entry)) => {
+                        entry.id = parent_entry.id();
+                        index.add(&entry)?;
+                    }
+                    (Some(_), None) => index.remove_path(path)?,
+                    (None, _) => (),
+                }
             }
         }
     }
+    Ok(())
+}
+
+/// The name and email dura signs its commits with in `repo`. Each comes from dura's config,
+/// then git's config (unless excluded). Only when neither source has either of them does dura
+/// sign as itself; a name without an email, or the other way around, is an error rather than
+/// being completed with dura's defaults.
+pub fn resolve_identity(dura_cfg: &Config, repo: &Repository) -> Result<(String, String), Error> {
+    let name = identity_part(dura_cfg, &dura_cfg.commit_author, repo, "user.name");
+    let email = identity_part(dura_cfg, &dura_cfg.commit_email, repo, "user.email");
+    match (name, email) {
+        (Some(name), Some(email)) => Ok((name, email)),
+        (None, None) => Ok(("dura".to_string(), "dura@github.io".to_string())),
+        (Some(name), None) => Err(Error::from_str(&format!(
+            "commit author is {:?} but there's no email to go with it, set commit_email in \
+             dura's config or user.email in git's",
+            name
+        ))),
+        (None, Some(email)) => Err(Error::from_str(&format!(
+            "commit email is {:?} but there's no name to go with it, set commit_author in \
+             dura's config or user.name in git's",
+            email
+        ))),
+    }
+}
 
-    "dura@github.io".to_string()
+fn identity_part(
+    dura_cfg: &Config,
+    configured: &Option<String>,
+    repo: &Repository,
+    git_key: &str,
+) -> Option<String> {
+    if configured.is_some() {
+        return configured.clone();
+    }
+    if dura_cfg.commit_exclude_git_config {
+        return None;
+    }
+    repo.config().ok()?.get_string(git_key).ok()
 }
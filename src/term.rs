@@ -0,0 +1,105 @@
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// The bits of the environment that decide how terminal output looks.
+///
+/// Symbols (emoji vs. ASCII) and color are two separate decisions: a terminal can render emoji
+/// while the user still asks for no color via `NO_COLOR`, or vice versa with `DURA_PLAIN_TEXT`.
+#[derive(Debug, Clone, Default)]
+pub struct TermEnv {
+    pub is_tty: bool,
+    pub term: Option<String>,
+    pub no_color: bool,
+    pub clicolor: Option<String>,
+    pub clicolor_force: bool,
+    pub plain_text: bool,
+    pub fancy: bool,
+}
+
+impl TermEnv {
+    pub fn detect() -> Self {
+        Self {
+            is_tty: io::stdout().is_terminal(),
+            term: env::var("TERM").ok(),
+            // https://no-color.org: any non-empty value disables color
+            no_color: env::var("NO_COLOR").is_ok_and(|v| !v.is_empty()),
+            clicolor: env::var("CLICOLOR").ok(),
+            clicolor_force: env::var("CLICOLOR_FORCE").is_ok_and(|v| !v.is_empty() && v != "0"),
+            plain_text: env::var("DURA_PLAIN_TEXT").is_ok(),
+            fancy: env::var("DURA_FANCY").is_ok(),
+        }
+    }
+
+    fn is_dumb(&self) -> bool {
+        self.term.as_deref().is_some_and(|t| t == "dumb")
+    }
+
+    /// Whether to colorize output. `CLICOLOR_FORCE` wins, then `NO_COLOR` and `CLICOLOR=0`
+    /// disable it, and otherwise it's on for a terminal that isn't `dumb`.
+    pub fn use_color(&self) -> bool {
+        if self.clicolor_force {
+            return true;
+        }
+        if self.no_color || self.clicolor.as_deref() == Some("0") {
+            return false;
+        }
+        self.is_tty && !self.is_dumb()
+    }
+
+    /// Whether to print emoji symbols rather than ASCII ones. None of the color variables are
+    /// consulted here.
+    pub fn fancy_symbols(&self) -> bool {
+        // explicit overrides first
+        if self.plain_text {
+            return false;
+        }
+        if self.fancy {
+            return true;
+        }
+
+        // Not a terminal (e.g., pipe or redirect)
+        if !self.is_tty {
+            return false;
+        }
+
+        if let Some(term) = &self.term {
+            let term = term.to_lowercase();
+            if term == "dumb" || term == "vt100" || term.contains("linux") {
+                return false;
+            }
+        }
+
+        // Most modern terminals support Unicode
+        true
+    }
+}
+
+pub fn use_color() -> bool {
+    TermEnv::detect().use_color()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Green,
+    Yellow,
+    Red,
+}
+
+impl Color {
+    fn ansi_code(self) -> &'static str {
+        match self {
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Red => "31",
+        }
+    }
+}
+
+/// Wraps `text` in ANSI escapes for `color`, or returns it as-is when `enabled` is false.
+pub fn paint(text: &str, color: Color, enabled: bool) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", color.ansi_code(), text)
+    } else {
+        text.to_string()
+    }
+}
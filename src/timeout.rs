@@ -0,0 +1,21 @@
+use std::sync::mpsc::channel;
+use std::thread;
+use std::time::Duration;
+
+/// Runs `f` on a dedicated thread and waits at most `timeout` for it to finish.
+///
+/// Returns None on timeout. git2 operations can't be canceled, so a timed out `f` keeps running
+/// in the background until it finishes on its own; this only guarantees the caller isn't stuck
+/// waiting on it, e.g. on a hung network mount.
+pub fn run_with_timeout<T, F>(timeout: Duration, f: F) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        // The receiver is gone if we already timed out, nobody cares about the result anymore
+        let _ = sender.send(f());
+    });
+    receiver.recv_timeout(timeout).ok()
+}
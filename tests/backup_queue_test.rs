@@ -0,0 +1,30 @@
+use dura::backup_queue::backup_queue;
+use std::path::PathBuf;
+
+#[test]
+fn flood_for_one_repo_is_coalesced() {
+    let (queue, receiver) = backup_queue(4);
+    let repo = PathBuf::from("/tmp/some-repo");
+
+    // Would block forever on a full channel if requests weren't coalesced
+    let queued = (0..100).filter(|_| queue.request(repo.clone())).count();
+    assert_eq!(queued, 1);
+
+    let mut processed = Vec::new();
+    while let Some(path) = receiver.try_recv() {
+        processed.push(path);
+    }
+    assert_eq!(processed, vec![repo]);
+}
+
+#[test]
+fn repo_can_be_queued_again_after_it_was_received() {
+    let (queue, receiver) = backup_queue(4);
+    let repo = PathBuf::from("/tmp/some-repo");
+
+    assert!(queue.request(repo.clone()));
+    assert_eq!(receiver.try_recv(), Some(repo.clone()));
+    assert!(queue.request(repo.clone()));
+    assert_eq!(receiver.try_recv(), Some(repo));
+    assert_eq!(receiver.try_recv(), None);
+}
@@ -0,0 +1,535 @@
+use chrono::{Local, TimeZone};
+use dura::config::{BackupStrategy, Config, WatchConfig};
+use dura::database::RuntimeLock;
+use dura::{backups, snapshots};
+use git2::{Repository, Signature, Time};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+mod util;
+
+#[test]
+fn count_backups_by_branch() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+
+    // two backups on top of the first commit
+    repo.change_file("foo.txt");
+    let first = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    repo.change_file("foo.txt");
+    snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    // one backup on top of the second commit
+    repo.commit_all();
+    repo.change_file("foo.txt");
+    let second = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let counts = Config::empty().count_backups_by_branch(&git_repo);
+
+    assert_eq!(counts.len(), 2);
+    assert_eq!(counts.get(&first.dura_branch), Some(&2));
+    assert_eq!(counts.get(&second.dura_branch), Some(&1));
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Makes a dura backup as if the clock was `offset_secs` off
+fn backup_at(repo: &Repository, branch: &str, offset_secs: i64) -> git2::Oid {
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let time = Time::new(now() + offset_secs, 0);
+    let sig = Signature::new("dura", "dura@github.io", &time).unwrap();
+    repo.commit(
+        Some(&format!("refs/heads/{branch}")),
+        &sig,
+        &sig,
+        snapshots::BACKUP_MESSAGE,
+        &head.tree().unwrap(),
+        &[&head],
+    )
+    .unwrap()
+}
+
+#[test]
+fn future_dated_backups_are_detected() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let future = backup_at(&git_repo, "dura/future", 365 * 24 * 3600);
+    backup_at(&git_repo, "dura/recent", -60);
+
+    let summary = Config::empty().count_backups(&git_repo);
+
+    assert_eq!(summary.count, 2);
+    assert_eq!(summary.future_dated, 1);
+    assert_eq!(summary.latest_id, Some(future.to_string()));
+    assert!(summary.latest_time > now());
+}
+
+#[test]
+fn clamping_keeps_backup_times_out_of_the_future() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    backup_at(&git_repo, "dura/future", 365 * 24 * 3600);
+
+    let mut config = Config::empty();
+    config.clamp_commit_time = true;
+    let summary = config.count_backups(&git_repo);
+
+    assert_eq!(summary.future_dated, 1);
+    assert!(summary.latest_time <= now());
+}
+
+#[test]
+fn counts_backups_since_local_midnight() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let midnight = backups::local_midnight(Local::now());
+    backup_at(&git_repo, "dura/today", 0);
+    backup_at(&git_repo, "dura/yesterday", midnight - now() - 3600);
+    backup_at(&git_repo, "dura/just-before-midnight", midnight - now() - 1);
+
+    let summary = Config::empty().count_backups(&git_repo);
+
+    assert_eq!(summary.count, 3);
+    assert_eq!(summary.today, 1);
+}
+
+#[test]
+fn stale_cached_backup_falls_back_to_history() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let kept = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    repo.commit_all();
+    repo.change_file("foo.txt");
+    let deleted = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    repo.git(&["branch", "-D", &deleted.dura_branch]).unwrap();
+
+    let path = repo.dir.to_str().unwrap();
+    let mut runtime_lock = RuntimeLock::empty();
+    runtime_lock.record_backup(repo.dir.as_path(), &deleted);
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let (oid, _) = Config::empty()
+        .last_backup(path, &git_repo, &runtime_lock)
+        .unwrap();
+
+    assert_eq!(oid.to_string(), kept.commit_hash);
+}
+
+#[test]
+fn unknown_cached_backup_falls_back_to_history() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let real = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let path = repo.dir.to_str().unwrap();
+    let mut runtime_lock = RuntimeLock::empty();
+    runtime_lock
+        .repos
+        .entry(path.to_string())
+        .or_default()
+        .last_backup = Some("1111111111111111111111111111111111111111".to_string());
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let (oid, _) = Config::empty()
+        .last_backup(path, &git_repo, &runtime_lock)
+        .unwrap();
+
+    assert_eq!(oid.to_string(), real.commit_hash);
+}
+
+#[test]
+fn valid_cached_backup_is_used() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let backup = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let path = repo.dir.to_str().unwrap();
+    let mut runtime_lock = RuntimeLock::empty();
+    runtime_lock.record_backup(repo.dir.as_path(), &backup);
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    assert_eq!(
+        Config::empty()
+            .cached_last_backup(path, &git_repo, &runtime_lock)
+            .map(|(oid, _)| oid.to_string()),
+        Some(backup.commit_hash)
+    );
+}
+
+#[test]
+fn outdated_cached_backup_is_not_trusted() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let first = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    // a second tip, e.g. from a capture the daemon didn't record, with a later commit time
+    repo.commit_all();
+    thread::sleep(Duration::from_millis(1100));
+    repo.change_file("foo.txt");
+    let second = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    // still on a dura branch, but no longer the newest backup
+    let path = repo.dir.to_str().unwrap();
+    let mut runtime_lock = RuntimeLock::empty();
+    runtime_lock.record_backup(repo.dir.as_path(), &first);
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let (oid, _) = Config::empty()
+        .last_backup(path, &git_repo, &runtime_lock)
+        .unwrap();
+
+    assert_eq!(oid.to_string(), second.commit_hash);
+}
+
+#[test]
+fn age_histogram_buckets_by_local_time() {
+    // midday, so that "today" but more than an hour ago exists
+    let now = Local.ymd(2024, 6, 12).and_hms(12, 0, 0);
+    let midnight = backups::local_midnight(now);
+    let at = |time: i64| backups::Backup {
+        oid: git2::Oid::zero(),
+        time,
+        ref_name: "dura/test".to_string(),
+    };
+    let now_secs = now.timestamp();
+    let backups = [
+        at(now_secs + 24 * 60 * 60), // future-dated
+        at(now_secs - 60),
+        at(now_secs - 59 * 60),
+        at(now_secs - 2 * 60 * 60),
+        at(midnight),
+        at(midnight - 1),
+        at(backups::local_midnight(now - chrono::Duration::days(6))),
+        at(backups::local_midnight(now - chrono::Duration::days(6)) - 1),
+        at(0),
+    ];
+
+    let histogram = backups::age_histogram(&backups, now);
+
+    assert_eq!(
+        histogram,
+        backups::AgeHistogram {
+            last_hour: 3,
+            today: 2,
+            this_week: 2,
+            older: 2,
+        }
+    );
+}
+
+#[test]
+fn one_per_day_keeps_recent_days_and_firsts_of_older_ones() {
+    let now = Local.ymd(2024, 6, 12).and_hms(12, 0, 0);
+    let at = |id: u8, (y, m, d): (i32, u32, u32), (h, min): (u32, u32)| backups::Backup {
+        oid: git2::Oid::from_bytes(&[id; 20]).unwrap(),
+        time: Local.ymd(y, m, d).and_hms(h, min, 0).timestamp(),
+        ref_name: "dura/test".to_string(),
+    };
+    let backups = vec![
+        at(1, (2024, 6, 12), (11, 0)),
+        at(2, (2024, 6, 12), (9, 0)),
+        at(3, (2024, 6, 11), (23, 59)),
+        at(4, (2024, 6, 11), (0, 1)),
+        // older than the two recent days, only the first of each survives
+        at(5, (2024, 6, 10), (18, 0)),
+        at(6, (2024, 6, 10), (8, 0)),
+        at(7, (2024, 6, 10), (12, 0)),
+        at(8, (2024, 6, 1), (7, 0)),
+        at(9, (2024, 5, 31), (22, 0)),
+        at(10, (2024, 5, 31), (21, 0)),
+    ];
+
+    let policy = backups::PrunePolicy::OnePerDay { keep_recent: 2 };
+    let (kept, pruned) = policy.partition(&backups, now);
+
+    let ids = |list: &[backups::Backup]| -> Vec<u8> {
+        list.iter().map(|backup| backup.oid.as_bytes()[0]).collect()
+    };
+    assert_eq!(ids(&kept), vec![1, 2, 3, 4, 6, 8, 10]);
+    assert_eq!(ids(&pruned), vec![5, 7, 9]);
+}
+
+#[test]
+fn one_per_day_without_recent_days_thins_today_too() {
+    let now = Local.ymd(2024, 6, 12).and_hms(12, 0, 0);
+    let at = |id: u8, hour: u32| backups::Backup {
+        oid: git2::Oid::from_bytes(&[id; 20]).unwrap(),
+        time: Local.ymd(2024, 6, 12).and_hms(hour, 0, 0).timestamp(),
+        ref_name: "dura/test".to_string(),
+    };
+
+    let policy = backups::PrunePolicy::OnePerDay { keep_recent: 0 };
+    let (kept, pruned) = policy.partition(&[at(1, 11), at(2, 9), at(3, 10)], now);
+
+    assert_eq!(kept, vec![at(2, 9)]);
+    assert_eq!(pruned, vec![at(1, 11), at(3, 10)]);
+}
+
+#[test]
+fn backups_are_noted_when_enabled() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut config = Config::empty();
+    config.write_backup_notes = true;
+
+    repo.change_file("foo.txt");
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let oid = git2::Oid::from_str(&status.commit_hash).unwrap();
+    let note = backups::backup_note(&git_repo, oid).unwrap();
+    assert_eq!(note.files_changed, 1);
+    assert_eq!(note.dura_version, env!("CARGO_PKG_VERSION"));
+}
+
+#[test]
+fn noted_commits_count_as_backups_without_the_message() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let head = git_repo.head().unwrap().peel_to_commit().unwrap();
+    let sig = Signature::now("dura", "dura@github.io").unwrap();
+    let commit = |message: &str, branch: &str| {
+        git_repo
+            .commit(
+                Some(&format!("refs/heads/{branch}")),
+                &sig,
+                &sig,
+                message,
+                &head.tree().unwrap(),
+                &[&head],
+            )
+            .unwrap()
+    };
+    // e.g. reworded during a rebase
+    let noted = commit("reworded backup", "dura/noted");
+    commit("not a backup", "dura/plain");
+    git_repo
+        .note(
+            &sig,
+            &sig,
+            Some(snapshots::NOTES_REF),
+            noted,
+            r#"{"dura_version":"0.2.0","files_changed":1}"#,
+            false,
+        )
+        .unwrap();
+
+    let found = backups::find_backups(&git_repo).unwrap();
+
+    let oids: Vec<git2::Oid> = found.iter().map(|backup| backup.oid).collect();
+    assert_eq!(oids, vec![noted]);
+    assert_eq!(found[0].ref_name, "dura/noted");
+    assert_eq!(Config::empty().count_backups(&git_repo).count, 1);
+}
+
+#[test]
+fn keep_latest_keeps_the_newest_in_order() {
+    let now = Local.ymd(2024, 6, 12).and_hms(12, 0, 0);
+    let at = |id: u8, hour: u32| backups::Backup {
+        oid: git2::Oid::from_bytes(&[id; 20]).unwrap(),
+        time: Local.ymd(2024, 6, 12).and_hms(hour, 0, 0).timestamp(),
+        ref_name: "dura/test".to_string(),
+    };
+
+    let policy = backups::PrunePolicy::KeepLatest { keep: 2 };
+    let (kept, pruned) = policy.partition(&[at(1, 11), at(2, 9), at(3, 10)], now);
+
+    assert_eq!(kept, vec![at(1, 11), at(3, 10)]);
+    assert_eq!(pruned, vec![at(2, 9)]);
+}
+
+#[test]
+fn backup_message_is_found_despite_trailing_whitespace() {
+    for summary in [
+        "dura auto-backup",
+        "dura auto-backup  ",
+        "dura auto-backup\n",
+        "wip: dura auto-backup \t\r\n\n",
+    ] {
+        assert!(backups::is_backup_summary(summary), "{summary:?}");
+    }
+    assert!(!backups::is_backup_summary("dura auto-backup was here"));
+    assert!(!backups::is_backup_summary(""));
+}
+
+#[test]
+fn backups_with_padded_messages_are_counted() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let head = git_repo.head().unwrap().peel_to_commit().unwrap();
+    let sig = Signature::now("dura", "dura@github.io").unwrap();
+    let message = format!("{}  \n\n", snapshots::BACKUP_MESSAGE);
+    git_repo
+        .commit(
+            Some("refs/heads/dura/padded"),
+            &sig,
+            &sig,
+            &message,
+            &head.tree().unwrap(),
+            &[&head],
+        )
+        .unwrap();
+
+    assert_eq!(Config::empty().count_backups(&git_repo).count, 1);
+}
+
+#[test]
+fn captured_backups_end_in_the_backup_message() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let backup = git_repo
+        .find_commit(git2::Oid::from_str(&status.commit_hash).unwrap())
+        .unwrap();
+    let message = backup.message().unwrap();
+    assert!(
+        message.trim_end().ends_with(snapshots::BACKUP_MESSAGE),
+        "{message:?}"
+    );
+}
+
+#[test]
+fn count_since_only_walks_new_commits() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+
+    let (cursor, walk) = backups::count_since(&git_repo, None).unwrap();
+    assert_eq!((cursor.count, walk), (1, backups::CountWalk::Full));
+
+    repo.change_file("foo.txt");
+    snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    // a cursor that's off by a lot shows whether only the new backup was walked
+    let inflated = backups::CountCursor {
+        count: 100,
+        ..cursor.clone()
+    };
+    let (counted, walk) = backups::count_since(&git_repo, Some(&inflated)).unwrap();
+    assert_eq!(
+        (counted.count, walk),
+        (101, backups::CountWalk::Incremental)
+    );
+    let (counted, _) = backups::count_since(&git_repo, Some(&cursor)).unwrap();
+    assert_eq!(counted.count, 2);
+}
+
+#[test]
+fn count_since_recounts_after_a_rewrite() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let (cursor, _) = backups::count_since(&git_repo, None).unwrap();
+
+    git_repo
+        .find_branch(&status.dura_branch, git2::BranchType::Local)
+        .unwrap()
+        .delete()
+        .unwrap();
+    let (counted, walk) = backups::count_since(&git_repo, Some(&cursor)).unwrap();
+
+    assert_eq!((counted.count, walk), (0, backups::CountWalk::Full));
+}
+
+#[test]
+fn backup_count_keeps_its_cursor_in_the_runtime_lock() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let path = repo.dir.to_str().unwrap().to_string();
+    let path = path.as_str();
+    let config = Config::empty();
+    let mut lock = RuntimeLock::empty();
+
+    assert_eq!(config.backup_count(path, &git_repo, &mut lock), 1);
+    let cursor = lock.repos[path].count_cursor.clone().unwrap();
+    assert_eq!(cursor.count, 1);
+
+    repo.change_file("foo.txt");
+    snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    assert_eq!(config.backup_count(path, &git_repo, &mut lock), 2);
+    assert_ne!(lock.repos[path].count_cursor, Some(cursor));
+}
+
+#[test]
+fn cached_total_follows_recorded_backups() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let path = repo.dir.to_str().unwrap().to_string();
+    let mut config = Config::empty();
+    config
+        .repos
+        .insert(path.clone(), std::rc::Rc::new(WatchConfig::new()));
+    let mut lock = RuntimeLock::empty();
+    assert_eq!(config.total_backups_cached(&mut lock), 0);
+
+    // cold again, so the backup below is only found by counting
+    lock.repos.clear();
+    repo.change_file("foo.txt");
+    snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    assert_eq!(config.total_backups_cached(&mut lock), 1);
+    assert!(lock.repos[&path].count_cursor.is_some());
+
+    repo.change_file("foo.txt");
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    lock.record_backup(&repo.dir, &status);
+    assert_eq!(config.total_backups_cached(&mut lock), 2);
+
+    // a recount takes over from the recorded backups instead of adding to them
+    assert_eq!(config.backup_count(&path, &git_repo, &mut lock), 2);
+    assert_eq!(config.total_backups_cached(&mut lock), 2);
+}
+
+#[test]
+fn cached_total_includes_the_stash() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let git_repo = Repository::open(repo.dir.as_path()).unwrap();
+    let path = repo.dir.to_str().unwrap().to_string();
+    let mut config = Config::empty();
+    config.backup_strategy = BackupStrategy::Stash;
+    config
+        .repos
+        .insert(path.clone(), std::rc::Rc::new(WatchConfig::new()));
+    let mut lock = RuntimeLock::empty();
+    config.backup_count(&path, &git_repo, &mut lock);
+
+    repo.change_file("foo.txt");
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    lock.record_backup(&repo.dir, &status);
+
+    // the stash is read, so the recorded backup isn't counted twice
+    assert_eq!(config.total_backups_cached(&mut lock), 1);
+}
@@ -0,0 +1,93 @@
+use dura::capabilities::{capabilities, Features, MissingFeature, Transport};
+use dura::snapshots;
+
+#[test]
+fn serializes_version_and_features() {
+    let json = serde_json::to_value(capabilities()).unwrap();
+
+    assert_eq!(json["dura_version"], env!("CARGO_PKG_VERSION"));
+    let libgit2_version = json["libgit2_version"].as_str().unwrap();
+    assert_eq!(libgit2_version.split('.').count(), 3);
+    assert!(libgit2_version.split('.').all(|n| n.parse::<u32>().is_ok()));
+    let features = json["features"].as_object().unwrap();
+    for key in [
+        "json_output",
+        "porcelain_output",
+        "backup_notes",
+        "mirror_repos",
+        "signing",
+        "https",
+        "ssh",
+    ] {
+        assert!(features[key].is_boolean(), "{key}");
+    }
+}
+
+fn stub_features(https: bool, ssh: bool) -> Features {
+    Features {
+        https,
+        ssh,
+        ..capabilities().features
+    }
+}
+
+#[test]
+fn transports_are_told_apart_by_url() {
+    assert_eq!(
+        Transport::for_url("https://github.com/tkellogg/dura"),
+        Transport::Https
+    );
+    assert_eq!(
+        Transport::for_url("ssh://git@github.com/tkellogg/dura"),
+        Transport::Ssh
+    );
+    assert_eq!(
+        Transport::for_url("git@github.com:tkellogg/dura.git"),
+        Transport::Ssh
+    );
+    assert_eq!(Transport::for_url("/srv/git/dura.git"), Transport::Local);
+    assert_eq!(
+        Transport::for_url("file:///srv/git/dura.git"),
+        Transport::Local
+    );
+    assert_eq!(Transport::for_url("C:\\git\\dura.git"), Transport::Local);
+}
+
+#[test]
+fn missing_transports_are_reported_up_front() {
+    let features = stub_features(true, false);
+
+    assert_eq!(
+        features.require_transport("git@github.com:tkellogg/dura.git"),
+        Err(MissingFeature::Ssh)
+    );
+    assert_eq!(
+        features.require_transport("https://github.com/tkellogg/dura"),
+        Ok(())
+    );
+    assert_eq!(features.require_transport("/srv/git/dura.git"), Ok(()));
+    assert_eq!(
+        MissingFeature::Ssh.to_string(),
+        "this dura build's libgit2 lacks SSH support"
+    );
+}
+
+#[test]
+fn push_checks_the_transport_before_connecting() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = git2::Repository::init(tmp.path()).unwrap();
+
+    // nothing listens there, so any other error would mean the push was attempted
+    let error = snapshots::push_to(
+        &repo,
+        "ssh://git@example.invalid/dura.git",
+        "refs/heads/dura/x:refs/heads/dura/x",
+        &stub_features(true, false),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        error.message(),
+        "this dura build's libgit2 lacks SSH support"
+    );
+}
@@ -0,0 +1,76 @@
+use dura::compiled_watch::CompiledWatch;
+use dura::config::WatchConfig;
+use std::path::Path;
+use std::rc::Rc;
+
+/// Compiles the pattern on every call, one component at a time.
+fn naive_matches(pattern: &str, path: &Path, pattern_is_prefix: bool) -> bool {
+    let pattern = pattern.strip_suffix("/**").unwrap_or(pattern);
+    let pattern: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let path: Vec<&str> = path.to_str().unwrap().split('/').collect();
+    let lengths_fit = if pattern_is_prefix {
+        pattern.len() <= path.len()
+    } else {
+        path.len() <= pattern.len()
+    };
+    lengths_fit
+        && pattern
+            .iter()
+            .zip(&path)
+            .all(|(p, c)| glob::Pattern::new(p).unwrap().matches(c))
+}
+
+#[test]
+fn matches_like_compiling_on_every_call() {
+    let exclude = ["target/**", "*.cache", "libs/old-*", "node_modules"];
+    let include = ["libs/*-keep/src", "target/doc"];
+    let watch_config = WatchConfig::builder()
+        .exclude(exclude)
+        .include(include)
+        .build();
+    let compiled = CompiledWatch::new(&watch_config);
+
+    for path in [
+        "target",
+        "target/debug",
+        "target/doc",
+        "app",
+        "app/src",
+        "app.cache",
+        "app.cache/x",
+        "libs",
+        "libs/old-parser",
+        "libs/old-keep",
+        "libs/old-keep/src",
+        "libs/old-keep/src/deep",
+        "node_modules",
+        "node_modules_backup",
+    ] {
+        let path = Path::new(path);
+        assert_eq!(
+            compiled.is_excluded(path),
+            exclude.iter().any(|p| naive_matches(p, path, true)),
+            "exclude {path:?}"
+        );
+        assert_eq!(
+            compiled.leads_to_include(path),
+            include.iter().any(|p| naive_matches(p, path, false)),
+            "include {path:?}"
+        );
+    }
+}
+
+#[test]
+fn patterns_are_compiled_once_per_config() {
+    let watch_config = WatchConfig::builder().exclude(["*.cache"]).build();
+    // e.g. the same config loaded again on the next poll
+    let reloaded = watch_config.clone();
+    let changed = WatchConfig::builder().exclude(["*.tmp"]).build();
+
+    let first = CompiledWatch::cached(&watch_config);
+    for _ in 0..1000 {
+        assert!(Rc::ptr_eq(&first, &CompiledWatch::cached(&reloaded)));
+    }
+    assert!(!Rc::ptr_eq(&first, &CompiledWatch::cached(&changed)));
+    assert!(CompiledWatch::cached(&changed).is_excluded(Path::new("a.tmp")));
+}
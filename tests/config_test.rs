@@ -0,0 +1,1336 @@
+use dura::config::{
+    BackupNowError, BackupOutcome, CleanReason, Config, ConfigChange, ConfigError, ImportSummary,
+    LoadSource, WatchConfig, WatchOutcome, WatchPathError, CONFIG_VERSION,
+};
+use dura::database::RuntimeLock;
+use dura::repo_size::RepoSizes;
+use dura::snapshots::{self, SkipReason};
+use std::env;
+use std::fs;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+mod util;
+
+#[macro_use]
+extern crate serial_test;
+
+fn watch(config: &mut Config, path: &std::path::Path) {
+    config.repos.insert(
+        path.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+}
+
+#[test]
+fn watched_roots_collapses_nested_watches() {
+    let tmp = tempfile::tempdir().unwrap();
+    let parent = tmp.path().join("work");
+    let nested = parent.join("project");
+    fs::create_dir_all(&nested).unwrap();
+
+    let mut config = Config::empty();
+    watch(&mut config, &parent);
+    watch(&mut config, &nested);
+
+    assert_eq!(config.watched_roots(), vec![parent.canonicalize().unwrap()]);
+}
+
+#[test]
+fn watched_roots_keeps_unrelated_roots() {
+    let tmp = tempfile::tempdir().unwrap();
+    let first = tmp.path().join("first");
+    let second = tmp.path().join("second");
+    // a sibling whose name shares a prefix isn't nested
+    let prefixed = tmp.path().join("first-other");
+    for dir in [&first, &second, &prefixed] {
+        fs::create_dir_all(dir).unwrap();
+    }
+
+    let mut config = Config::empty();
+    watch(&mut config, &second);
+    watch(&mut config, &prefixed);
+    watch(&mut config, &first);
+
+    assert_eq!(
+        config.watched_roots(),
+        vec![
+            first.canonicalize().unwrap(),
+            prefixed.canonicalize().unwrap(),
+            second.canonicalize().unwrap(),
+        ]
+    );
+}
+
+#[test]
+fn group_by_parent_counts_dirty_repos() {
+    let work = tempfile::tempdir().unwrap();
+    let personal = tempfile::tempdir().unwrap();
+
+    let mut repos = Vec::new();
+    for (dir, name) in [
+        (&work, "one"),
+        (&work, "two"),
+        (&work, "three"),
+        (&personal, "notes"),
+    ] {
+        let repo = util::git_repo::GitRepo::new(dir.path().join(name));
+        repo.init();
+        repo.write_file("foo.txt");
+        repo.commit_all();
+        repos.push(repo);
+    }
+    repos[0].write_file("dirty.txt");
+    repos[1].write_file("dirty.txt");
+
+    let mut config = Config::empty();
+    for repo in &repos {
+        watch(&mut config, &repo.dir);
+    }
+    let groups = config.group_by_parent();
+
+    assert_eq!(groups.len(), 2);
+    let work_group = &groups[&work.path().canonicalize().unwrap()];
+    assert_eq!(work_group.repos.len(), 3);
+    assert_eq!(work_group.dirty, 2);
+    let personal_group = &groups[&personal.path().canonicalize().unwrap()];
+    assert_eq!(personal_group.repos.len(), 1);
+    assert_eq!(personal_group.dirty, 0);
+}
+
+#[test]
+#[serial]
+fn load_verbose_reports_loaded_path() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    let mut config = Config::empty();
+    config.commit_author = Some("someone".to_string());
+    config.save();
+
+    let (loaded, source) = Config::load_verbose();
+
+    assert_eq!(loaded, config);
+    assert_eq!(source, LoadSource::Loaded(tmp.path().join("config.toml")));
+}
+
+#[test]
+#[serial]
+fn load_verbose_reports_missing_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+
+    let (loaded, source) = Config::load_verbose();
+
+    assert_eq!(loaded, Config::empty());
+    assert_eq!(source, LoadSource::Missing(tmp.path().join("config.toml")));
+}
+
+#[test]
+#[serial]
+fn load_verbose_reports_parse_error() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    fs::write(tmp.path().join("config.toml"), "repos = [").unwrap();
+
+    let (loaded, source) = Config::load_verbose();
+
+    assert_eq!(loaded, Config::empty());
+    match source {
+        LoadSource::ParseError(path, _) => assert_eq!(path, tmp.path().join("config.toml")),
+        other => panic!("Expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn builder_matches_hand_built_config() {
+    let built = WatchConfig::builder()
+        .include(["src/keep"])
+        .exclude(vec!["src".to_string(), "docs".to_string()])
+        .max_depth(3)
+        .build();
+
+    let by_hand = WatchConfig {
+        include: vec!["src/keep".to_string()],
+        exclude: vec!["src".to_string(), "docs".to_string()],
+        max_depth: 3,
+        ..WatchConfig::new()
+    };
+    assert_eq!(built, by_hand);
+}
+
+#[test]
+fn builder_defaults_match_new() {
+    assert_eq!(WatchConfig::builder().build(), WatchConfig::new());
+}
+
+#[test]
+fn default_watch_config_serializes_every_field() {
+    let serialized = toml::to_string(&WatchConfig::new()).unwrap();
+
+    assert!(serialized.contains("include = []"), "{serialized}");
+    assert!(serialized.contains("exclude = []"), "{serialized}");
+    assert!(serialized.contains("max_depth = 255"), "{serialized}");
+    assert_eq!(
+        toml::from_str::<WatchConfig>(&serialized).unwrap(),
+        WatchConfig::new()
+    );
+}
+
+#[test]
+fn watch_config_missing_fields_uses_defaults() {
+    let watch_config: WatchConfig = toml::from_str("exclude = [\"target\"]").unwrap();
+
+    assert_eq!(
+        watch_config,
+        WatchConfig::builder().exclude(["target"]).build()
+    );
+}
+
+#[test]
+fn stats_over_time_appends_rows_under_one_header() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    dura::snapshots::capture(repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    // dirty again, so the repo counts as having uncommitted changes
+    repo.change_file("foo.txt");
+
+    let mut config = Config::empty();
+    watch(&mut config, &repo.dir);
+    let csv = tmp.path().join("stats/dura.csv");
+    config.stats_over_time(&csv).unwrap();
+    config.stats_over_time(&csv).unwrap();
+
+    let contents = fs::read_to_string(&csv).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(lines.len(), 3, "{contents}");
+    assert_eq!(lines[0], "timestamp,total_repos,dirty_repos,total_backups");
+    for row in &lines[1..] {
+        let columns: Vec<&str> = row.split(',').collect();
+        assert!(columns[0].parse::<u64>().is_ok(), "{row}");
+        assert_eq!(columns[1..], ["1", "1", "1"], "{row}");
+    }
+}
+
+#[test]
+fn group_by_origin_finds_clones_of_the_same_project() {
+    let first_tmp = tempfile::tempdir().unwrap();
+    let first = repo_and_file!(first_tmp, "foo.txt");
+    let second_tmp = tempfile::tempdir().unwrap();
+    let second = repo_and_file!(second_tmp, "foo.txt");
+    let other_tmp = tempfile::tempdir().unwrap();
+    let other = repo_and_file!(other_tmp, "foo.txt");
+    let no_remote_tmp = tempfile::tempdir().unwrap();
+    let no_remote = repo_and_file!(no_remote_tmp, "foo.txt");
+    first.set_config("remote.origin.url", "https://example.com/project.git");
+    second.set_config("remote.origin.url", "https://example.com/project.git/");
+    other.set_config("remote.origin.url", "https://example.com/other.git");
+
+    let mut config = Config::empty();
+    for repo in [&first, &second, &other, &no_remote] {
+        watch(&mut config, &repo.dir);
+    }
+    let groups = config.group_by_origin();
+
+    assert_eq!(groups.len(), 1);
+    let clones = &groups["https://example.com/project.git"];
+    assert_eq!(clones.len(), 2);
+    assert!(clones.contains(&first.dir));
+    assert!(clones.contains(&second.dir));
+}
+
+#[test]
+#[serial]
+fn set_watch_resolves_relative_paths() {
+    let tmp = tempfile::tempdir().unwrap();
+    fs::create_dir(tmp.path().join("project")).unwrap();
+    let cwd = env::current_dir().unwrap();
+
+    let mut config = Config::empty();
+    env::set_current_dir(tmp.path()).unwrap();
+    let result = config.set_watch("./project/../project", WatchConfig::new());
+    env::set_current_dir(cwd).unwrap();
+
+    result.unwrap();
+    let expected = tmp.path().join("project").canonicalize().unwrap();
+    assert!(config.repos.contains_key(expected.to_str().unwrap()));
+}
+
+#[test]
+fn set_watch_reports_missing_directory() {
+    let tmp = tempfile::tempdir().unwrap();
+    let missing = tmp.path().join("missing");
+
+    let mut config = Config::empty();
+    let result = config.set_watch(&missing, WatchConfig::new());
+
+    assert!(matches!(result, Err(WatchPathError::NotFound(path)) if path == missing));
+    assert!(config.repos.is_empty());
+}
+
+#[test]
+fn set_watch_reports_file_instead_of_directory() {
+    let tmp = tempfile::tempdir().unwrap();
+    let file = tmp.path().join("file.txt");
+    fs::write(&file, "").unwrap();
+
+    let mut config = Config::empty();
+    let result = config.set_watch(&file, WatchConfig::new());
+
+    assert!(matches!(result, Err(WatchPathError::NotADirectory(path)) if path == file));
+    assert!(config.repos.is_empty());
+}
+
+#[test]
+fn size_limit_skips_only_large_repos() {
+    let small_tmp = tempfile::tempdir().unwrap();
+    let small = repo_and_file!(small_tmp, "foo.txt");
+    let large_tmp = tempfile::tempdir().unwrap();
+    let large = repo_and_file!(large_tmp, "foo.txt");
+    fs::write(large.dir.join(".git/padding"), vec![0u8; 2 * 1024 * 1024]).unwrap();
+
+    let mut sizes = RepoSizes::new();
+    let mut config = Config::empty();
+    assert!(!config.exceeds_size_limit(&large.dir, &mut sizes));
+
+    config.max_repo_size_mb = Some(1);
+    assert!(config.exceeds_size_limit(&large.dir, &mut sizes));
+    assert!(!config.exceeds_size_limit(&small.dir, &mut sizes));
+}
+
+#[test]
+fn describe_explains_watch_behavior() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    repo.set_config("user.email", "repo@someemail.com");
+
+    let mut config = Config::empty();
+    config.commit_author = Some("dura-override".to_string());
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().exclude(["target"]).build()),
+    );
+    let description = config.describe(&repo.dir);
+
+    assert!(
+        description.contains("Author: dura-override (from dura's config)"),
+        "{description}"
+    );
+    assert!(
+        description.contains("Email: repo@someemail.com (from git's user.email)"),
+        "{description}"
+    );
+    assert!(
+        description.contains(&format!("every {}s", dura::poller::POLL_INTERVAL.as_secs())),
+        "{description}"
+    );
+    assert!(description.contains("Excludes: target"), "{description}");
+}
+
+#[test]
+fn describe_unwatched_path() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    let description = Config::empty().describe(tmp.path());
+
+    assert!(description.contains("is not watched"), "{description}");
+}
+
+#[cfg(unix)]
+#[test]
+fn watch_non_unicode_path() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().join(OsStr::from_bytes(b"caf\xe9"));
+    if fs::create_dir(&dir).is_err() {
+        // e.g. macOS file systems only allow UTF-8 names
+        return;
+    }
+
+    let mut config = Config::empty();
+    config.set_watch(&dir, WatchConfig::new()).unwrap();
+    let key = dir.canonicalize().unwrap().to_string_lossy().into_owned();
+    assert!(key.ends_with("caf\u{fffd}"));
+    assert!(config.repos.contains_key(&key));
+
+    config.set_unwatch(&dir).unwrap();
+    assert!(config.repos.is_empty());
+}
+
+#[test]
+fn version_0_config_is_migrated() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = tmp.path().join("repo");
+    fs::create_dir(&repo).unwrap();
+    let unnormalized = tmp.path().join("repo/../repo");
+    let path = tmp.path().join("config.toml");
+    fs::write(
+        &path,
+        format!("[repos.\"{}\"]\nmax_depth = 3\n", unnormalized.display()),
+    )
+    .unwrap();
+
+    let config = Config::load_file(&path).unwrap();
+
+    assert_eq!(config.version, CONFIG_VERSION);
+    assert!(!config.clamp_commit_time);
+    let key = repo.canonicalize().unwrap();
+    assert_eq!(
+        config.repos.get(key.to_str().unwrap()).map(|w| w.max_depth),
+        Some(3)
+    );
+    assert_eq!(config.repos.len(), 1);
+
+    config.save_to_path(&path).unwrap();
+    assert!(fs::read_to_string(&path)
+        .unwrap()
+        .starts_with(&format!("version = {CONFIG_VERSION}\n")));
+}
+
+#[test]
+fn newer_config_version_is_left_alone() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("config.toml");
+    fs::write(&path, "version = 99\n[repos]\n").unwrap();
+
+    let config = Config::load_file(&path).unwrap();
+
+    assert_eq!(config.version, 99);
+}
+
+#[test]
+#[serial]
+fn summary_stats_sums_dirty_files() {
+    let first_tmp = tempfile::tempdir().unwrap();
+    let mut first = repo_and_file!(first_tmp, "foo.txt");
+    first.change_file("foo.txt");
+    first.write_file("untracked.txt");
+    let second_tmp = tempfile::tempdir().unwrap();
+    let mut second = repo_and_file!(second_tmp, "foo.txt");
+    second.change_file("foo.txt");
+    let clean_tmp = tempfile::tempdir().unwrap();
+    let clean = repo_and_file!(clean_tmp, "foo.txt");
+
+    let mut config = Config::empty();
+    for repo in [&first, &second, &clean] {
+        watch(&mut config, &repo.dir);
+    }
+    watch(&mut config, &first_tmp.path().join("missing"));
+    let cache = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", cache.path());
+    let stats = config.summary_stats();
+    // only the daemon saves the runtime lock
+    assert!(!RuntimeLock::default_path().exists());
+
+    assert_eq!(stats.total_repos, 4);
+    assert_eq!(stats.dirty_repos, 2);
+    assert_eq!(stats.dirty_files, 3);
+    assert_eq!(stats.inaccessible_repos, 1);
+}
+
+#[test]
+fn untracked_files_are_not_dirty_without_backup_untracked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    repo.write_file("untracked.txt");
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+
+    let mut config = Config::empty();
+    watch(&mut config, &repo.dir);
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 1);
+
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().backup_untracked(false).build()),
+    );
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 0);
+}
+
+#[test]
+fn detailed_info_leaves_out_untracked_files_without_backup_untracked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    repo.write_file("untracked.txt");
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().backup_untracked(false).build()),
+    );
+
+    let mut out = Vec::new();
+    config.write_detailed_info(&mut out, true).unwrap();
+    let output = String::from_utf8(out).unwrap();
+
+    assert!(!output.contains("untracked.txt"), "{output}");
+    assert!(output.contains("No uncommitted changes"), "{output}");
+}
+
+/// A repo at `<tmp>/work/app` with one untracked file, and a config watching all of `tmp`.
+fn repo_under_work(tmp: &tempfile::TempDir) -> (util::git_repo::GitRepo, Config) {
+    let dir = tmp.path().join("work").join("app");
+    fs::create_dir_all(&dir).unwrap();
+    let repo = util::git_repo::GitRepo::new(dir);
+    repo.init();
+    repo.write_file("foo.txt");
+    repo.commit_all();
+    repo.write_file("untracked.txt");
+
+    let mut config = Config::empty();
+    watch(&mut config, tmp.path());
+    (repo, config)
+}
+
+fn pattern(tmp: &tempfile::TempDir, glob: &str) -> String {
+    format!("{}/{}", tmp.path().display(), glob)
+}
+
+fn ignoring_untracked() -> Rc<WatchConfig> {
+    Rc::new(WatchConfig::builder().backup_untracked(false).build())
+}
+
+#[test]
+fn repo_pattern_applies_to_matching_repos() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (repo, mut config) = repo_under_work(&tmp);
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 1);
+
+    config
+        .repo_patterns
+        .insert(pattern(&tmp, "other/*"), ignoring_untracked());
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 1);
+
+    config
+        .repo_patterns
+        .insert(pattern(&tmp, "work/*"), ignoring_untracked());
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 0);
+}
+
+#[test]
+fn describe_goes_by_repo_patterns() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (repo, mut config) = repo_under_work(&tmp);
+    config.repo_patterns.insert(
+        pattern(&tmp, "work/*"),
+        Rc::new(WatchConfig::builder().exclude(["target"]).build()),
+    );
+
+    let description = config.describe(&repo.dir);
+
+    assert!(description.contains("Excludes: target"), "{description}");
+}
+
+#[test]
+fn most_specific_repo_pattern_wins() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (repo, mut config) = repo_under_work(&tmp);
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+
+    config
+        .repo_patterns
+        .insert(pattern(&tmp, "*/app"), ignoring_untracked());
+    config
+        .repo_patterns
+        .insert(pattern(&tmp, "work/*"), Rc::new(WatchConfig::new()));
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 1);
+
+    config
+        .repo_patterns
+        .insert(pattern(&tmp, "work/a*"), ignoring_untracked());
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 0);
+}
+
+#[test]
+fn exact_repo_entry_beats_repo_patterns() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (repo, mut config) = repo_under_work(&tmp);
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+    config
+        .repo_patterns
+        .insert(pattern(&tmp, "work/*"), ignoring_untracked());
+
+    watch(&mut config, &repo.dir);
+    assert_eq!(config.dirty_file_count(&repo.dir, &git_repo).unwrap(), 1);
+}
+
+#[test]
+fn repo_patterns_roundtrip_through_toml() {
+    let mut config = Config::empty();
+    config
+        .repo_patterns
+        .insert("~/work/*".to_string(), ignoring_untracked());
+
+    let loaded: Config = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+    assert_eq!(loaded, config);
+
+    let old: Config = toml::from_str("version = 1\n[repos]\n").unwrap();
+    assert!(old.repo_patterns.is_empty());
+}
+
+fn watch_with_interval(config: &mut Config, path: &std::path::Path, interval_secs: u64) {
+    config.repos.insert(
+        path.to_str().unwrap().to_string(),
+        Rc::new(
+            WatchConfig::builder()
+                .min_backup_interval(Duration::from_secs(interval_secs))
+                .build(),
+        ),
+    );
+}
+
+fn lock_with_backup_at(path: &std::path::Path, time: SystemTime) -> RuntimeLock {
+    let mut lock = RuntimeLock::empty();
+    let cache = lock
+        .repos
+        .entry(path.to_str().unwrap().to_string())
+        .or_default();
+    cache.last_backup_time = Some(
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64,
+    );
+    lock
+}
+
+#[test]
+#[serial]
+fn next_backup_eta_counts_down_from_last_backup() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", tmp.path());
+    let repo = tmp.path().join("repo");
+    let now = SystemTime::now();
+    lock_with_backup_at(&repo, now - Duration::from_secs(60)).save();
+
+    let mut config = Config::empty();
+    assert_eq!(config.next_backup_eta(&repo), None);
+
+    watch_with_interval(&mut config, &repo, 300);
+    let eta = config.next_backup_eta(&repo).unwrap();
+    assert!(eta <= Duration::from_secs(240), "{eta:?}");
+    assert!(eta > Duration::from_secs(230), "{eta:?}");
+
+    watch_with_interval(&mut config, &repo, 30);
+    assert_eq!(config.next_backup_eta(&repo), Some(Duration::ZERO));
+}
+
+#[test]
+#[serial]
+fn next_backup_eta_is_zero_before_the_first_backup() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", tmp.path());
+    let repo = tmp.path().join("repo");
+
+    let mut config = Config::empty();
+    watch_with_interval(&mut config, &repo, 300);
+
+    assert_eq!(config.next_backup_eta(&repo), Some(Duration::ZERO));
+}
+
+#[test]
+fn reapply_presets_adds_preset_excludes_and_keeps_custom_ones() {
+    let rust_tmp = tempfile::tempdir().unwrap();
+    fs::write(rust_tmp.path().join("Cargo.toml"), "").unwrap();
+    let plain_tmp = tempfile::tempdir().unwrap();
+    let custom = Rc::new(WatchConfig::builder().exclude(["docs"]).build());
+    let mut config = Config::empty();
+    let rust_key = rust_tmp.path().to_str().unwrap().to_string();
+    config.repos.insert(rust_key.clone(), Rc::clone(&custom));
+    config.repos.insert(
+        plain_tmp.path().to_str().unwrap().to_string(),
+        Rc::clone(&custom),
+    );
+
+    assert_eq!(config.reapply_presets(), 1);
+
+    assert_eq!(config.repos[&rust_key].exclude, vec!["docs", "target/**"]);
+    let plain_key = plain_tmp.path().to_str().unwrap();
+    assert_eq!(config.repos[plain_key].exclude, vec!["docs"]);
+    // shared configs are copied before they're changed
+    assert_eq!(custom.exclude, vec!["docs"]);
+
+    assert_eq!(config.reapply_presets(), 0);
+}
+
+#[test]
+fn durations_are_read_as_human_strings_or_seconds() {
+    let config: Config = toml::from_str(
+        r#"
+        version = 1
+        status_cache_ttl = "30s"
+        max_poll_interval = "5m"
+        stale_after = 90
+        [repos."/work/app"]
+        min_backup_interval = "1h 30m"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(config.status_cache_ttl, Duration::from_secs(30));
+    assert_eq!(config.max_poll_interval, Some(Duration::from_secs(5 * 60)));
+    assert_eq!(config.stale_after, Some(Duration::from_secs(90)));
+    assert_eq!(
+        config.repos["/work/app"].min_backup_interval,
+        Some(Duration::from_secs(90 * 60))
+    );
+}
+
+#[test]
+fn durations_are_written_in_human_form() {
+    let mut config = Config::empty();
+    config.max_poll_interval = Some(Duration::from_secs(5 * 60));
+    config.stale_after = Some(Duration::from_secs(90));
+
+    let written = toml::to_string(&config).unwrap();
+
+    assert!(written.contains("max_poll_interval = \"5m\""), "{written}");
+    assert!(written.contains("stale_after = \"1m 30s\""), "{written}");
+    assert_eq!(toml::from_str::<Config>(&written).unwrap(), config);
+}
+
+#[test]
+fn seconds_keys_from_older_configs_still_load() {
+    let config: Config = toml::from_str(
+        "version = 1\nmax_poll_interval_secs = 120\nstatus_cache_ttl_secs = 60\n[repos]\n",
+    )
+    .unwrap();
+
+    assert_eq!(config.max_poll_interval, Some(Duration::from_secs(120)));
+    assert_eq!(config.status_cache_ttl, Duration::from_secs(60));
+}
+
+#[test]
+fn invalid_duration_is_a_clear_error() {
+    let e = toml::from_str::<Config>("version = 1\nstale_after = \"soon\"\n[repos]\n").unwrap_err();
+
+    let message = e.to_string();
+    assert!(message.contains("invalid duration 'soon'"), "{message}");
+    assert!(message.contains("\"5m\""), "{message}");
+}
+
+#[cfg(any(target_os = "macos", target_os = "windows"))]
+#[test]
+fn set_watch_skips_case_variants_of_a_watch() {
+    let tmp = tempfile::tempdir().unwrap();
+    fs::create_dir(tmp.path().join("Code")).unwrap();
+    let mut config = Config::empty();
+
+    config
+        .set_watch(tmp.path().join("Code"), WatchConfig::new())
+        .unwrap();
+    config
+        .set_watch(tmp.path().join("code"), WatchConfig::new())
+        .unwrap();
+
+    assert_eq!(config.repos.len(), 1);
+    let variant = tmp.path().join("code").canonicalize().unwrap();
+    assert!(config.case_variant_of(variant.to_str().unwrap()).is_some());
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[test]
+fn case_variants_are_separate_watches_where_case_matters() {
+    let tmp = tempfile::tempdir().unwrap();
+    fs::create_dir(tmp.path().join("Code")).unwrap();
+    fs::create_dir(tmp.path().join("code")).unwrap();
+    let mut config = Config::empty();
+
+    config
+        .set_watch(tmp.path().join("Code"), WatchConfig::new())
+        .unwrap();
+    config
+        .set_watch(tmp.path().join("code"), WatchConfig::new())
+        .unwrap();
+
+    assert_eq!(config.repos.len(), 2);
+    let variant = tmp.path().join("code").canonicalize().unwrap();
+    assert_eq!(config.case_variant_of(variant.to_str().unwrap()), None);
+}
+
+#[test]
+fn watch_keeps_an_existing_repo() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let head = git2::Repository::open(&repo.dir)
+        .unwrap()
+        .head()
+        .unwrap()
+        .target();
+    let mut config = Config::empty();
+
+    config.watch(&repo.dir, WatchConfig::new(), true).unwrap();
+
+    let key = repo.dir.canonicalize().unwrap();
+    assert!(config.repos.contains_key(key.to_str().unwrap()));
+    let reopened = git2::Repository::open(&repo.dir).unwrap();
+    assert_eq!(reopened.head().unwrap().target(), head);
+}
+
+#[test]
+fn watch_creates_a_repo_when_asked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = Config::empty();
+
+    config.watch(tmp.path(), WatchConfig::new(), true).unwrap();
+
+    assert!(snapshots::is_repo(tmp.path()));
+    let key = tmp.path().canonicalize().unwrap();
+    assert!(config.repos.contains_key(key.to_str().unwrap()));
+}
+
+#[test]
+fn watch_leaves_plain_directories_alone_by_default() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = Config::empty();
+
+    config.watch(tmp.path(), WatchConfig::new(), false).unwrap();
+
+    assert!(!snapshots::is_repo(tmp.path()));
+    let key = tmp.path().canonicalize().unwrap();
+    assert!(config.repos.contains_key(key.to_str().unwrap()));
+}
+
+#[test]
+fn load_file_tells_missing_files_from_malformed_ones() {
+    let tmp = tempfile::tempdir().unwrap();
+    let missing = tmp.path().join("missing.toml");
+    let malformed = tmp.path().join("malformed.toml");
+    fs::write(&malformed, "version = [").unwrap();
+
+    match Config::load_file(&missing) {
+        Err(ConfigError::Io(path, e)) => {
+            assert_eq!(path, missing);
+            assert_eq!(e.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("expected an IO error, got {other:?}"),
+    }
+    match Config::load_file(&malformed) {
+        Err(ConfigError::Parse(path, _)) => assert_eq!(path, malformed),
+        other => panic!("expected a parse error, got {other:?}"),
+    }
+}
+
+#[test]
+fn unwatching_an_unwatched_path_fails() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut config = Config::empty();
+
+    let result = config.set_unwatch(tmp.path());
+
+    let key = tmp.path().canonicalize().unwrap();
+    assert!(matches!(result, Err(ConfigError::NotWatched(path)) if path == key.to_str().unwrap()));
+}
+
+#[test]
+#[serial]
+fn set_watch_many_reports_each_path_and_saves_once() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_home = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", config_home.path());
+    let old = tmp.path().join("old");
+    let new = tmp.path().join("new");
+    let missing = tmp.path().join("missing");
+    fs::create_dir(&old).unwrap();
+    fs::create_dir(&new).unwrap();
+    let config_path = tmp.path().join("dura.toml");
+    let mut config = Config::empty();
+    watch(&mut config, &old.canonicalize().unwrap());
+
+    let outcomes = config.set_watch_many(
+        vec![
+            (new.clone(), WatchConfig::new()),
+            (old.clone(), WatchConfig::new()),
+            (missing.clone(), WatchConfig::new()),
+            (new.clone(), WatchConfig::new()),
+        ],
+        &config_path,
+    );
+
+    let paths: Vec<_> = outcomes.iter().map(|(path, _)| path.clone()).collect();
+    assert_eq!(paths, vec![new.clone(), old.clone(), missing, new.clone()]);
+    let new_key = new.canonicalize().unwrap().to_str().unwrap().to_string();
+    let old_key = old.canonicalize().unwrap().to_str().unwrap().to_string();
+    assert!(matches!(outcomes[0].1, WatchOutcome::Added));
+    assert!(matches!(&outcomes[1].1, WatchOutcome::AlreadyWatched(key) if *key == old_key));
+    assert!(matches!(
+        outcomes[2].1,
+        WatchOutcome::Error(WatchPathError::NotFound(_))
+    ));
+    assert!(matches!(&outcomes[3].1, WatchOutcome::AlreadyWatched(key) if *key == new_key));
+    let saved = Config::load_file(&config_path).unwrap();
+    assert_eq!(saved.repos.len(), 2);
+    assert!(saved.repos.contains_key(&new_key));
+    assert!(!Config::default_path().exists());
+
+    // nothing added, nothing saved
+    fs::remove_file(&config_path).unwrap();
+    config.set_watch_many(vec![(new, WatchConfig::new())], &config_path);
+    assert!(!config_path.exists());
+    env::remove_var("DURA_CONFIG_HOME");
+}
+
+#[test]
+fn find_watch_root_takes_the_longest_watched_prefix() {
+    let tmp = tempfile::tempdir().unwrap();
+    let work = tmp.path().canonicalize().unwrap().join("work");
+    let app = work.join("app");
+    fs::create_dir_all(app.join("src")).unwrap();
+    fs::create_dir_all(work.join("lib")).unwrap();
+    let mut config = Config::empty();
+    watch(&mut config, &work);
+    watch(&mut config, &app);
+
+    let root_of =
+        |path: &std::path::Path| config.find_watch_root(path).map(|(root, _)| root.clone());
+
+    // exact
+    assert_eq!(root_of(&app), Some(app.to_str().unwrap().to_string()));
+    // subdirectories, also through a path that isn't canonical
+    assert_eq!(
+        root_of(&app.join("src")),
+        Some(app.to_str().unwrap().to_string())
+    );
+    assert_eq!(
+        root_of(&app.join("src/../../lib")),
+        Some(work.to_str().unwrap().to_string())
+    );
+    // no match
+    assert_eq!(root_of(tmp.path()), None);
+    assert_eq!(root_of(&tmp.path().join("workshop")), None);
+    assert!(config.is_watched(&app.join("src")));
+    assert!(!config.is_watched(tmp.path()));
+}
+
+#[test]
+fn json_schema_describes_config_and_watch_config() {
+    let schema = Config::export_json_schema();
+
+    let properties = &schema["properties"];
+    assert_eq!(
+        properties["repos"]["additionalProperties"]["$ref"],
+        "#/definitions/WatchConfig"
+    );
+    assert!(properties["commit_author"]["description"]
+        .as_str()
+        .unwrap()
+        .contains("Author name"));
+    assert_eq!(properties["stale_after"]["examples"][0], "30s");
+
+    let max_depth = &schema["definitions"]["WatchConfig"]["properties"]["max_depth"];
+    assert_eq!(max_depth["type"], "integer");
+    assert_eq!(max_depth["minimum"].as_f64(), Some(0.0));
+    assert_eq!(max_depth["maximum"].as_f64(), Some(255.0));
+    assert_eq!(max_depth["default"], 255);
+}
+
+#[test]
+fn plans_report_the_change_without_making_it() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().canonicalize().unwrap();
+    let key = dir.to_str().unwrap().to_string();
+    let mut config = Config::empty();
+
+    assert_eq!(
+        config.plan_watch(&dir).unwrap(),
+        ConfigChange::Add(key.clone())
+    );
+    assert!(matches!(
+        config.plan_unwatch(&dir),
+        Err(ConfigError::NotWatched(_))
+    ));
+    assert!(config.repos.is_empty());
+
+    watch(&mut config, &dir);
+    assert_eq!(
+        config.plan_watch(&dir).unwrap(),
+        ConfigChange::Unchanged(key.clone())
+    );
+    assert_eq!(
+        config.plan_unwatch(&dir).unwrap(),
+        ConfigChange::Remove(key.clone())
+    );
+    assert!(config.repos.contains_key(&key));
+}
+
+#[test]
+fn labels_are_unique_among_watches() {
+    let notes = tempfile::tempdir().unwrap();
+    let other = tempfile::tempdir().unwrap();
+    let mut config = Config::empty();
+    let labeled = || WatchConfig::builder().label("notes").build();
+
+    config.set_watch(notes.path(), labeled()).unwrap();
+    let notes_key = notes.path().canonicalize().unwrap();
+    let notes_key = notes_key.to_str().unwrap();
+
+    match config.set_watch(other.path(), labeled()) {
+        Err(WatchPathError::DuplicateLabel(label, key)) => {
+            assert_eq!(label, "notes");
+            assert_eq!(key, notes_key);
+        }
+        result => panic!("expected a duplicate label, got {result:?}"),
+    }
+    assert_eq!(config.repos.len(), 1);
+    assert_eq!(
+        config.find_by_label("notes").map(|(key, _)| key.as_str()),
+        Some(notes_key)
+    );
+    assert_eq!(
+        config.display_name(notes_key),
+        format!("notes ({notes_key})")
+    );
+}
+
+#[test]
+fn watching_again_with_a_label_relabels() {
+    let notes = tempfile::tempdir().unwrap();
+    let other = tempfile::tempdir().unwrap();
+    let mut config = Config::empty();
+    config
+        .set_watch(notes.path(), WatchConfig::builder().max_depth(2).build())
+        .unwrap();
+    config
+        .set_watch(other.path(), WatchConfig::builder().label("other").build())
+        .unwrap();
+    let notes_key = notes.path().canonicalize().unwrap();
+    let notes_key = notes_key.to_str().unwrap();
+
+    config
+        .set_watch(notes.path(), WatchConfig::builder().label("notes").build())
+        .unwrap();
+    assert_eq!(config.repos[notes_key].label.as_deref(), Some("notes"));
+    assert_eq!(config.repos[notes_key].max_depth, 2);
+
+    assert!(matches!(
+        config.set_watch(notes.path(), WatchConfig::builder().label("other").build()),
+        Err(WatchPathError::DuplicateLabel(..))
+    ));
+    assert_eq!(config.repos[notes_key].label.as_deref(), Some("notes"));
+}
+
+#[test]
+fn failed_save_leaves_no_temp_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    // renaming the temp file over a directory fails after it's been written
+    let path = tmp.path().join("config.toml");
+    fs::create_dir(&path).unwrap();
+
+    let error = Config::empty().save_to_path(&path).unwrap_err();
+
+    assert!(matches!(error, ConfigError::Io(ref at, _) if *at == path));
+    assert!(!tmp.path().join("config.toml.tmp").exists());
+    assert!(path.is_dir());
+
+    let path = tmp.path().join("saved.toml");
+    Config::empty().save_to_path(&path).unwrap();
+    assert!(path.is_file());
+    assert!(!tmp.path().join("saved.toml.tmp").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn save_keeps_symlinks_and_permissions() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+    let tmp = tempfile::tempdir().unwrap();
+    let dotfiles = tmp.path().join("dotfiles.toml");
+    fs::write(&dotfiles, "").unwrap();
+    fs::set_permissions(&dotfiles, fs::Permissions::from_mode(0o600)).unwrap();
+    let path = tmp.path().join("config.toml");
+    symlink(&dotfiles, &path).unwrap();
+
+    Config::empty().save_to_path(&path).unwrap();
+
+    assert!(fs::symlink_metadata(&path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(Config::load_file(&dotfiles).unwrap(), Config::empty());
+    let mode = fs::metadata(&dotfiles).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn full_disk_and_permission_errors_say_what_to_do() {
+    let path = std::path::PathBuf::from("/home/me/.config/dura/config.toml");
+    let error = |kind| ConfigError::Io(path.clone(), std::io::Error::from(kind)).to_string();
+
+    assert_eq!(
+        error(std::io::ErrorKind::StorageFull),
+        "unable to write /home/me/.config/dura/config.toml: the disk is full, free up some \
+         space and try again"
+    );
+    assert!(error(std::io::ErrorKind::PermissionDenied).contains("DURA_CONFIG_HOME"));
+}
+
+#[cfg(unix)]
+#[test]
+fn save_to_read_only_dir_reports_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    fs::set_permissions(tmp.path(), fs::Permissions::from_mode(0o555)).unwrap();
+    let result = Config::empty().save_to_path(&tmp.path().join("config.toml"));
+    fs::set_permissions(tmp.path(), fs::Permissions::from_mode(0o755)).unwrap();
+    let Err(error) = result else {
+        println!("skipping, permissions aren't enforced");
+        return;
+    };
+
+    assert!(error.to_string().contains("permission denied"), "{error}");
+    assert!(!tmp.path().join("config.toml.tmp").exists());
+}
+
+#[test]
+fn global_uncommitted_summary_rolls_up_all_repos() {
+    let dirty_tmp = tempfile::tempdir().unwrap();
+    let mut dirty = repo_and_file!(dirty_tmp, "foo.txt");
+    dirty.change_file("foo.txt");
+    dirty.write_file("untracked.txt");
+    let an_hour_ago = SystemTime::now() - Duration::from_secs(60 * 60);
+    fs::File::options()
+        .write(true)
+        .open(dirty.dir.join("foo.txt"))
+        .unwrap()
+        .set_modified(an_hour_ago)
+        .unwrap();
+    let clean_tmp = tempfile::tempdir().unwrap();
+    let clean = repo_and_file!(clean_tmp, "foo.txt");
+
+    let mut config = Config::empty();
+    watch(&mut config, &dirty.dir);
+    watch(&mut config, &clean.dir);
+    watch(&mut config, &clean_tmp.path().join("missing"));
+    let summary = config.global_uncommitted_summary();
+
+    assert_eq!(summary.total_repos, 3);
+    assert_eq!(summary.dirty_repos, 1);
+    assert_eq!(summary.dirty_files, 2);
+    let age = summary.oldest_unbacked_change.unwrap();
+    assert!(age >= Duration::from_secs(60 * 60), "{age:?}");
+    assert!(age < Duration::from_secs(2 * 60 * 60), "{age:?}");
+
+    // both changes predate the backup
+    snapshots::capture(&dirty.dir).unwrap();
+    let summary = config.global_uncommitted_summary();
+
+    assert_eq!(summary.dirty_repos, 1);
+    assert_eq!(summary.oldest_unbacked_change, None);
+}
+
+#[test]
+#[serial]
+fn backup_now_ignores_the_interval() {
+    let cache = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", cache.path());
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let dir = repo.dir.canonicalize().unwrap();
+    let mut config = Config::empty();
+    watch_with_interval(&mut config, &dir, 60 * 60);
+    repo.change_file("foo.txt");
+    assert!(matches!(
+        config.backup_now(&dir.join("foo.txt")),
+        Ok(BackupOutcome::Committed(_))
+    ));
+    assert!(config.next_backup_eta(&dir).unwrap() > Duration::ZERO);
+
+    repo.write_file("bar.txt");
+    let outcome = config.backup_now(&dir).unwrap();
+
+    let BackupOutcome::Committed(status) = outcome else {
+        panic!("expected a backup, got {outcome:?}");
+    };
+    let git_repo = git2::Repository::open(&dir).unwrap();
+    let backup = git_repo
+        .find_commit(git2::Oid::from_str(&status.commit_hash).unwrap())
+        .unwrap();
+    assert!(backup.tree().unwrap().get_name("bar.txt").is_some());
+    assert_eq!(config.backup_now(&dir).unwrap(), BackupOutcome::NoChanges);
+}
+
+#[test]
+#[serial]
+fn backup_now_says_why_it_skipped() {
+    let cache = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", cache.path());
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let dir = repo.dir.canonicalize().unwrap();
+    let mut config = Config::empty();
+    watch_with_interval(&mut config, &dir, 0);
+    repo.change_file("foo.txt");
+    fs::write(dir.join(".git/index.lock"), "").unwrap();
+
+    assert_eq!(
+        config.backup_now(&dir).unwrap(),
+        BackupOutcome::Skipped(SkipReason::IndexLocked)
+    );
+}
+
+#[test]
+#[serial]
+fn backup_now_respects_the_watch() {
+    let cache = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", cache.path());
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    let mut repo = util::git_repo::GitRepo::new(root.join("app"));
+    repo.init();
+    repo.write_file("foo.txt");
+    repo.commit_all();
+    repo.change_file("foo.txt");
+    let mut config = Config::empty();
+
+    assert!(matches!(
+        config.backup_now(&repo.dir),
+        Err(BackupNowError::NotWatched(_))
+    ));
+
+    config.repos.insert(
+        root.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().exclude(["app"]).build()),
+    );
+    assert!(matches!(
+        config.backup_now(&repo.dir),
+        Err(BackupNowError::Excluded(path)) if path == repo.dir
+    ));
+    assert!(matches!(
+        config.backup_now(&root),
+        Err(BackupNowError::NotARepo(..))
+    ));
+}
+
+#[test]
+fn import_from_directory_list_adds_only_new_repos() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    let first = root.join("first");
+    let second = root.join("second");
+    let plain = root.join("plain");
+    for repo in [&first, &second] {
+        fs::create_dir(repo).unwrap();
+        git2::Repository::init(repo).unwrap();
+    }
+    fs::create_dir(&plain).unwrap();
+    let list = root.join("repos.txt");
+    fs::write(
+        &list,
+        format!(
+            "{}\n\n{}\n{}\n{}\n{}\n",
+            first.display(),
+            plain.display(),
+            root.join("missing").display(),
+            second.display(),
+            first.display(),
+        ),
+    )
+    .unwrap();
+    let mut config = Config::empty();
+
+    let summary = config.import_from_directory_list(&list).unwrap();
+    assert_eq!(
+        summary,
+        ImportSummary {
+            added: 2,
+            skipped: 3
+        }
+    );
+
+    let mut keys: Vec<&String> = config.repos.keys().collect();
+    keys.sort();
+    assert_eq!(
+        keys,
+        vec![first.to_str().unwrap(), second.to_str().unwrap()]
+    );
+    let summary = config.import_from_directory_list(&list).unwrap();
+    assert_eq!((summary.added, summary.skipped), (0, 5));
+    assert!(matches!(
+        config.import_from_directory_list(&root.join("nothing.txt")),
+        Err(ConfigError::Io(..))
+    ));
+}
+
+#[test]
+fn clean_candidates_are_missing_and_repoless_paths() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().canonicalize().unwrap();
+    let missing = root.join("missing");
+    let repo = root.join("repo");
+    let plain = root.join("plain");
+    let parent = root.join("parent");
+    for dir in [&repo, &plain, &parent.join("nested")] {
+        fs::create_dir_all(dir).unwrap();
+    }
+    git2::Repository::init(&repo).unwrap();
+    git2::Repository::init(parent.join("nested")).unwrap();
+    let mut config = Config::empty();
+    for path in [&missing, &repo, &plain, &parent] {
+        watch(&mut config, path);
+    }
+
+    assert_eq!(
+        config.clean_candidates(),
+        vec![
+            (missing.to_str().unwrap().to_string(), CleanReason::Missing),
+            (plain.to_str().unwrap().to_string(), CleanReason::NotARepo),
+        ]
+    );
+}
+
+#[test]
+#[serial]
+fn excludes_are_added_and_removed_on_an_existing_watch() {
+    let tmp = tempfile::tempdir().unwrap();
+    let config_home = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", config_home.path());
+    let dir = tmp.path().canonicalize().unwrap();
+    let key = dir.to_str().unwrap().to_string();
+    let mut config = Config::empty();
+    config.repos.insert(
+        key.clone(),
+        Rc::new(
+            WatchConfig::builder()
+                .exclude(["target"])
+                .max_depth(2)
+                .build(),
+        ),
+    );
+    let excludes = |config: &Config| config.repos[&key].exclude.clone();
+    let config_path = config_home.path().join("elsewhere.toml");
+
+    config
+        .add_exclude(&dir, "*.log".to_string(), &config_path)
+        .unwrap();
+    assert_eq!(excludes(&config), vec!["target", "*.log"]);
+    assert_eq!(config.repos[&key].max_depth, 2);
+    let saved = Config::load_file(&config_path).unwrap();
+    assert_eq!(excludes(&saved), vec!["target", "*.log"]);
+
+    assert!(matches!(
+        config.add_exclude(&dir, "*.log".to_string(), &config_path),
+        Err(ConfigError::DuplicateExclude(..))
+    ));
+    assert!(matches!(
+        config.add_exclude(&dir, "[unclosed".to_string(), &config_path),
+        Err(ConfigError::InvalidPattern(..))
+    ));
+    assert!(matches!(
+        config.add_exclude(&dir, "/tmp/build".to_string(), &config_path),
+        Err(ConfigError::AbsolutePattern(..))
+    ));
+    assert_eq!(excludes(&config), vec!["target", "*.log"]);
+
+    config.remove_exclude(&dir, "target", &config_path).unwrap();
+    assert_eq!(excludes(&config), vec!["*.log"]);
+    assert!(matches!(
+        config.remove_exclude(&dir, "target", &config_path),
+        Err(ConfigError::NoSuchExclude(..))
+    ));
+    let saved = Config::load_file(&config_path).unwrap();
+    assert_eq!(excludes(&saved), vec!["*.log"]);
+    assert!(!Config::default_path().exists());
+    env::remove_var("DURA_CONFIG_HOME");
+}
@@ -0,0 +1,237 @@
+use dura::config::{Config, WatchConfig};
+use dura::database::{
+    ErrorRing, RepoCache, RuntimeLock, HEARTBEAT_STALE_AFTER, OPEN_RETRY_BASE, OPEN_RETRY_MAX,
+    RECENT_ERRORS,
+};
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+fn running_since(start: SystemTime) -> RuntimeLock {
+    let mut lock = RuntimeLock::empty();
+    lock.pid = Some(42);
+    lock.start_time = Some(start);
+    lock
+}
+
+#[test]
+fn fresh_heartbeat_is_running() {
+    let now = SystemTime::now();
+    let mut lock = running_since(now - Duration::from_secs(2 * 60 * 60));
+    lock.last_heartbeat = Some(now - Duration::from_secs(5));
+
+    assert_eq!(lock.server_status(now), "Running (PID: 42, Uptime: 2h 0m)");
+}
+
+#[test]
+fn old_heartbeat_is_stale() {
+    let now = SystemTime::now();
+    let mut lock = running_since(now - Duration::from_secs(2 * 60 * 60));
+    lock.last_heartbeat = Some(now - HEARTBEAT_STALE_AFTER - Duration::from_secs(5 * 60));
+
+    assert_eq!(
+        lock.server_status(now),
+        "Running but stale (last heartbeat 6m ago, PID: 42)"
+    );
+}
+
+#[test]
+fn missing_heartbeat_is_not_stale() {
+    // e.g. written by a daemon from before heartbeats
+    let now = SystemTime::now();
+    let lock = running_since(now - Duration::from_secs(10 * 60));
+
+    assert_eq!(lock.server_status(now), "Running (PID: 42, Uptime: 10m)");
+}
+
+#[test]
+fn no_pid_is_not_running() {
+    assert_eq!(
+        RuntimeLock::empty().server_status(SystemTime::now()),
+        "Not running"
+    );
+}
+
+#[test]
+fn lock_without_heartbeat_still_loads() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("runtime.db");
+    std::fs::write(&path, r#"{"pid":42,"start_time":null}"#).unwrap();
+
+    let lock = RuntimeLock::load_file(&path).unwrap();
+
+    assert_eq!(lock.pid, Some(42));
+    assert_eq!(lock.last_heartbeat, None);
+}
+
+#[test]
+fn stale_only_with_old_heartbeat() {
+    let now = SystemTime::now();
+    let mut lock = running_since(now - Duration::from_secs(10 * 60));
+    assert!(!lock.is_stale(now));
+
+    lock.last_heartbeat = Some(now - HEARTBEAT_STALE_AFTER - Duration::from_secs(1));
+    assert!(lock.is_stale(now));
+
+    lock.pid = None;
+    assert!(!lock.is_stale(now));
+}
+
+#[test]
+fn purge_unwatched_drops_repos_no_watch_covers() {
+    let mut config = Config::empty();
+    config
+        .repos
+        .insert("/work".to_string(), Rc::new(WatchConfig::new()));
+    let mut lock = RuntimeLock::empty();
+    for path in ["/work", "/work/app", "/old/app"] {
+        lock.repos.insert(path.to_string(), RepoCache::default());
+    }
+
+    assert_eq!(lock.purge_unwatched(&config), 1);
+
+    let paths: Vec<&str> = lock.repos.keys().map(String::as_str).collect();
+    assert_eq!(paths, vec!["/work", "/work/app"]);
+}
+
+#[test]
+fn repeated_open_failures_back_off_up_to_the_cap() {
+    let path = Path::new("/mnt/flaky/app");
+    let now = SystemTime::now();
+    let mut lock = RuntimeLock::empty();
+
+    let intervals: Vec<Duration> = (0..3)
+        .map(|_| lock.record_open_failure(path, now))
+        .collect();
+
+    assert_eq!(
+        intervals,
+        vec![OPEN_RETRY_BASE, OPEN_RETRY_BASE * 2, OPEN_RETRY_BASE * 4]
+    );
+    assert_eq!(
+        lock.open_retry_at(path, now),
+        Some(now + OPEN_RETRY_BASE * 4)
+    );
+    assert_eq!(lock.open_retry_at(path, now + OPEN_RETRY_BASE * 4), None);
+    for _ in 0..40 {
+        lock.record_open_failure(path, now);
+    }
+    assert_eq!(lock.record_open_failure(path, now), OPEN_RETRY_MAX);
+}
+
+#[test]
+fn open_success_resets_the_backoff() {
+    let path = Path::new("/mnt/flaky/app");
+    let now = SystemTime::now();
+    let mut lock = RuntimeLock::empty();
+    lock.record_open_failure(path, now);
+    lock.record_open_failure(path, now);
+
+    assert!(lock.record_open_success(path));
+
+    assert_eq!(lock.open_retry_at(path, now), None);
+    assert_eq!(lock.record_open_failure(path, now), OPEN_RETRY_BASE);
+    assert!(lock.record_open_success(path));
+    assert!(!lock.record_open_success(path));
+}
+
+#[test]
+fn runtime_accumulates_over_sessions() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("runtime.db");
+    let start = SystemTime::now() - Duration::from_secs(10 * 3600);
+
+    let mut lock = running_since(start);
+    lock.end_session(start + Duration::from_secs(3600));
+    lock.save_to_path(&path);
+
+    let mut lock = RuntimeLock::load_file(&path).unwrap();
+    assert_eq!(lock.pid, None);
+    assert_eq!(lock.accumulated_runtime, Some(Duration::from_secs(3600)));
+    lock.pid = Some(43);
+    lock.start_time = Some(start + Duration::from_secs(2 * 3600));
+    lock.end_session(start + Duration::from_secs(4 * 3600));
+    lock.save_to_path(&path);
+
+    let lock = RuntimeLock::load_file(&path).unwrap();
+    assert_eq!(
+        lock.accumulated_runtime,
+        Some(Duration::from_secs(3 * 3600))
+    );
+    assert_eq!(
+        lock.total_runtime(SystemTime::now()),
+        Some(Duration::from_secs(3 * 3600))
+    );
+}
+
+#[test]
+fn crashed_session_ends_at_its_last_heartbeat() {
+    let start = SystemTime::now() - Duration::from_secs(10 * 3600);
+    let mut lock = running_since(start);
+    lock.last_heartbeat = Some(start + Duration::from_secs(3600));
+
+    lock.end_session(SystemTime::now());
+
+    assert_eq!(lock.accumulated_runtime, Some(Duration::from_secs(3600)));
+}
+
+#[test]
+fn total_runtime_includes_the_running_session() {
+    let now = SystemTime::now();
+    let mut lock = running_since(now - Duration::from_secs(600));
+    lock.accumulated_runtime = Some(Duration::from_secs(3600));
+
+    assert_eq!(lock.total_runtime(now), Some(Duration::from_secs(4200)));
+    assert_eq!(RuntimeLock::empty().total_runtime(now), None);
+}
+
+#[test]
+fn config_drift_needs_a_running_daemon_and_another_hash() {
+    let config = Config::empty();
+    let mut lock = running_since(SystemTime::now());
+    assert!(!lock.config_drifted(&config));
+
+    lock.config_hash = Some(config.content_hash());
+    assert!(!lock.config_drifted(&config));
+
+    let mut edited = Config::empty();
+    edited.commit_author = Some("someone".to_string());
+    assert!(lock.config_drifted(&edited));
+
+    lock.pid = None;
+    assert!(!lock.config_drifted(&edited));
+}
+
+#[test]
+fn dirty_is_reported_once_per_clean_to_dirty_edge() {
+    let path = Path::new("/work/app");
+    let mut lock = RuntimeLock::empty();
+
+    let edges: Vec<bool> = [true, false, true, true, false, false, true]
+        .into_iter()
+        .map(|dirty| lock.record_dirty(path, dirty))
+        .collect();
+
+    // dirty when first seen isn't an edge, it was never seen clean
+    assert_eq!(edges, [false, false, true, false, false, false, true]);
+}
+
+#[test]
+fn error_ring_keeps_only_the_newest_errors() {
+    let mut ring = ErrorRing::default();
+    let start = SystemTime::UNIX_EPOCH;
+
+    for i in 0..RECENT_ERRORS + 3 {
+        ring.push(start + Duration::from_secs(i as u64), format!("error {i}"));
+    }
+
+    let kept: Vec<&str> = ring.iter().map(|e| e.message.as_str()).collect();
+    let expected: Vec<String> = (3..RECENT_ERRORS + 3)
+        .map(|i| format!("error {i}"))
+        .collect();
+    assert_eq!(kept, expected);
+    assert_eq!(
+        ring.latest().map(|e| e.message.as_str()),
+        Some(format!("error {}", RECENT_ERRORS + 2).as_str())
+    );
+}
@@ -0,0 +1,110 @@
+use dura::config::{Config, LoadSource, WatchConfig};
+use dura::database::RuntimeLock;
+use dura::doctor::{ResolvedPaths, ResolvedRepoPaths};
+use std::env;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+mod util;
+
+#[macro_use]
+extern crate serial_test;
+
+#[test]
+#[serial]
+fn report_includes_lock_state_and_repos() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    env::set_var("DURA_CONFIG_HOME", tmp.path().join("config"));
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    let mut lock = RuntimeLock::empty();
+    lock.pid = Some(42);
+    lock.start_time = Some(SystemTime::now());
+    lock.save();
+
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+    config.repos.insert(
+        tmp.path().join("missing").to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+    let report = config.doctor();
+
+    assert_eq!(report.config, LoadSource::Missing(Config::default_path()));
+    assert_eq!(report.runtime_lock.path, RuntimeLock::default_path());
+    assert_eq!(report.runtime_lock.error, None);
+    assert_eq!(report.runtime_lock.pid, Some(42));
+    assert!(!report.runtime_lock.stale);
+    assert!(
+        report.runtime_lock.server_status.starts_with("Running"),
+        "{}",
+        report.runtime_lock.server_status
+    );
+    assert_eq!(report.repos.len(), 2);
+    let repo_check = report.repos.iter().find(|r| r.path == repo.dir).unwrap();
+    assert_eq!(repo_check.error, None);
+    assert!(report.repos.iter().any(|r| r.error.is_some()));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["runtime_lock"]["pid"], 42);
+    assert!(report.to_string().contains("Server: Running"));
+}
+
+#[test]
+#[serial]
+fn missing_lock_is_not_running() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path().join("config"));
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+
+    let report = Config::empty().doctor();
+
+    assert_eq!(report.runtime_lock.error, None);
+    assert_eq!(report.runtime_lock.pid, None);
+    assert_eq!(report.runtime_lock.server_status, "Not running");
+    assert!(report.repos.is_empty());
+}
+
+#[test]
+#[serial]
+fn debug_paths_follow_the_env_overrides() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    env::set_var("DURA_CONFIG_HOME", tmp.path().join("config"));
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    let missing = tmp.path().join("missing");
+    let mut config = Config::empty();
+    for path in [&repo.dir, &missing] {
+        config.repos.insert(
+            path.to_str().unwrap().to_string(),
+            Rc::new(WatchConfig::new()),
+        );
+    }
+
+    let paths = config.debug_paths();
+
+    assert_eq!(
+        paths,
+        ResolvedPaths {
+            config_home: tmp.path().join("config"),
+            config_file: tmp.path().join("config").join("config.toml"),
+            cache_home: tmp.path().join("cache"),
+            runtime_lock: tmp.path().join("cache").join("runtime.db"),
+            repos: vec![
+                ResolvedRepoPaths {
+                    key: repo.dir.to_str().unwrap().to_string(),
+                    canonical: Some(repo.dir.canonicalize().unwrap()),
+                    git_dir: Some(repo.dir.canonicalize().unwrap().join(".git/")),
+                },
+                ResolvedRepoPaths {
+                    key: missing.to_str().unwrap().to_string(),
+                    canonical: None,
+                    git_dir: None,
+                },
+            ],
+        }
+    );
+}
@@ -0,0 +1,163 @@
+use dura::config::WatchConfig;
+use dura::git_repo_iter::GitRepoIter;
+use git2::Repository;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn init(path: &Path) -> PathBuf {
+    fs::create_dir_all(path).unwrap();
+    Repository::init(path).unwrap();
+    path.to_path_buf()
+}
+
+fn repos_under(root: &Path, watch_config: &WatchConfig) -> HashSet<PathBuf> {
+    GitRepoIter::under(root, watch_config).collect()
+}
+
+#[test]
+fn under_finds_nested_repos() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let top = init(&root.join("top"));
+    let nested = init(&root.join("group/nested"));
+    fs::create_dir_all(root.join("empty")).unwrap();
+
+    let found = repos_under(&root, &WatchConfig::new());
+
+    assert_eq!(found, HashSet::from([top, nested]));
+}
+
+#[test]
+fn broken_repos_are_yielded_not_scanned() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let broken = root.join("broken");
+    fs::create_dir_all(broken.join(".git")).unwrap();
+    init(&broken.join("inside"));
+
+    let found = repos_under(&root, &WatchConfig::new());
+
+    assert_eq!(found, HashSet::from([broken]));
+}
+
+#[test]
+fn under_honors_exclude_and_include() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let kept = init(&root.join("kept"));
+    init(&root.join("vendor/skipped"));
+    let rescued = init(&root.join("vendor/rescued"));
+
+    let watch_config = WatchConfig::builder()
+        .exclude(["vendor"])
+        .include(["vendor/rescued"])
+        .build();
+    let found = repos_under(&root, &watch_config);
+
+    assert_eq!(found, HashSet::from([kept, rescued]));
+}
+
+#[test]
+fn under_honors_max_depth() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let shallow = init(&root.join("shallow"));
+    init(&root.join("a/b/deep"));
+
+    let watch_config = WatchConfig::builder().max_depth(1).build();
+    let found = repos_under(&root, &watch_config);
+
+    assert_eq!(found, HashSet::from([shallow]));
+}
+
+#[test]
+fn under_yields_root_when_it_is_a_repo() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = init(&tmp.path().join("root"));
+
+    let found = repos_under(&root, &WatchConfig::new());
+
+    assert_eq!(found, HashSet::from([root]));
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loops_are_scanned_once() {
+    use std::os::unix::fs::symlink;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let first = init(&root.join("a/first"));
+    let second = init(&root.join("b/second"));
+    symlink(root.join("b"), root.join("a/to_b")).unwrap();
+    symlink(root.join("a"), root.join("b/to_a")).unwrap();
+
+    let found: Vec<PathBuf> = GitRepoIter::under(&root, &WatchConfig::new())
+        .map(|path| path.canonicalize().unwrap())
+        .collect();
+
+    assert_eq!(found.len(), 2, "{found:?}");
+    assert!(found.contains(&first.canonicalize().unwrap()), "{found:?}");
+    assert!(found.contains(&second.canonicalize().unwrap()), "{found:?}");
+}
+
+#[test]
+fn under_honors_glob_excludes() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let kept = init(&root.join("app"));
+    init(&root.join("app.cache/nested"));
+    init(&root.join("libs/old-parser"));
+    let rescued = init(&root.join("libs/old-keep"));
+
+    let watch_config = WatchConfig::builder()
+        .exclude(["*.cache", "libs/old-*"])
+        .include(["libs/*-keep"])
+        .build();
+    let found = repos_under(&root, &watch_config);
+
+    assert_eq!(found, HashSet::from([kept, rescued]));
+}
+
+/// A repo right below `root`, and one below a plain directory below it.
+fn nested_tree(root: &Path) -> (PathBuf, PathBuf) {
+    (
+        init(&root.join("child")),
+        init(&root.join("group/grandchild")),
+    )
+}
+
+#[test]
+fn max_depth_0_only_scans_the_root() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    nested_tree(&root);
+    let watch_config = WatchConfig::builder().max_depth(0).build();
+
+    assert_eq!(repos_under(&root, &watch_config), HashSet::new());
+    init(&root);
+    assert_eq!(repos_under(&root, &watch_config), HashSet::from([root]));
+}
+
+#[test]
+fn max_depth_1_scans_the_roots_children() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let (child, _) = nested_tree(&root);
+
+    let found = repos_under(&root, &WatchConfig::builder().max_depth(1).build());
+
+    assert_eq!(found, HashSet::from([child]));
+}
+
+#[test]
+fn default_max_depth_has_no_limit() {
+    let tmp = tempfile::tempdir().unwrap();
+    let root = tmp.path().join("root");
+    let deep = init(&(0..300).fold(root.clone(), |dir, _| dir.join("d")));
+
+    let found = repos_under(&root, &WatchConfig::new());
+
+    assert_eq!(found, HashSet::from([deep]));
+}
@@ -0,0 +1,48 @@
+#![cfg(unix)]
+
+use dura::{hooks, snapshots};
+use std::fs;
+
+mod util;
+
+#[test]
+fn post_backup_hook_runs_with_env() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    repo.write_file("bar.txt");
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let out = tempfile::tempdir().unwrap();
+    let marker = out.path().join("marker");
+    let hook = format!(
+        "printf '%s\\n%s\\n%s' \"$DURA_REPO\" \"$DURA_COMMIT\" \"$DURA_FILES\" > '{}'",
+        marker.display()
+    );
+    let exit_status = hooks::run_post_backup_hook(&hook, repo.dir.as_path(), &status).unwrap();
+
+    assert!(exit_status.success());
+    let contents = fs::read_to_string(marker).unwrap();
+    let lines: Vec<&str> = contents.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            repo.dir.to_str().unwrap(),
+            status.commit_hash.as_str(),
+            "bar.txt",
+            "foo.txt"
+        ]
+    );
+}
+
+#[test]
+fn failing_hook_reports_exit_status() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let exit_status = hooks::run_post_backup_hook("exit 3", repo.dir.as_path(), &status).unwrap();
+
+    assert_eq!(exit_status.code(), Some(3));
+}
@@ -0,0 +1,520 @@
+mod util;
+
+use crate::util::dura::Dura;
+
+#[test]
+fn detailed_info_reports_git_config_identity() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    repo.set_config("user.name", "repo-author");
+    repo.set_config("user.email", "repo@someemail.com");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let output = dura.run_output(&["info", "--detail", "--all"]);
+
+    assert!(output.contains("Commit identity: repo-author <repo@someemail.com>"));
+}
+
+#[test]
+fn detailed_info_for_many_keeps_order_and_reports_unwatched() {
+    let first_tmp = tempfile::tempdir().unwrap();
+    let first = repo_and_file!(first_tmp, "foo.txt");
+    let second_tmp = tempfile::tempdir().unwrap();
+    let second = repo_and_file!(second_tmp, "bar.txt");
+    let unwatched = tempfile::tempdir().unwrap();
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], first_tmp.path());
+    dura.run_in_dir(&["watch"], second_tmp.path());
+    let first_path = first.dir.canonicalize().unwrap();
+    let second_path = second.dir.canonicalize().unwrap();
+    let unwatched_path = unwatched.path().canonicalize().unwrap();
+    let output = dura.run_output(&[
+        "info",
+        "--repo",
+        second_path.to_str().unwrap(),
+        "--repo",
+        unwatched_path.to_str().unwrap(),
+        "--repo",
+        first_path.join("foo.txt").to_str().unwrap(),
+    ]);
+
+    let position = |needle: String| {
+        output
+            .find(&needle)
+            .unwrap_or_else(|| panic!("{needle:?} not in {output}"))
+    };
+    let second_at = position(format!("{}\n", second_path.display()));
+    let unwatched_at = position(format!("{}: Not watched", unwatched_path.display()));
+    let first_at = position(format!("{}\n", first_path.display()));
+    assert!(second_at < unwatched_at);
+    assert!(unwatched_at < first_at);
+}
+
+#[test]
+fn summary_flags_repos_watched_twice() {
+    let first_tmp = tempfile::tempdir().unwrap();
+    let first = repo_and_file!(first_tmp, "foo.txt");
+    let second_tmp = tempfile::tempdir().unwrap();
+    let second = repo_and_file!(second_tmp, "foo.txt");
+    first.set_config("remote.origin.url", "https://example.com/project.git");
+    second.set_config("remote.origin.url", "https://example.com/project.git");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], first_tmp.path());
+    dura.run_in_dir(&["watch"], second_tmp.path());
+    let output = dura.run_output(&["info"]);
+
+    let second_path = second.dir.canonicalize().unwrap();
+    assert!(
+        output.contains(&format!("(also watched at {})", second_path.display())),
+        "{output}"
+    );
+}
+
+#[test]
+fn summary_marks_repos_over_the_size_limit() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    std::fs::write(repo.dir.join(".git/padding"), vec![0u8; 2 * 1024 * 1024]).unwrap();
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let mut config = dura.get_config().unwrap();
+    config.max_repo_size_mb = Some(1);
+    dura.save_config(&config);
+    let output = dura.run_output(&["info"]);
+
+    assert!(output.contains("(skipped: exceeds size limit)"), "{output}");
+}
+
+#[test]
+fn summary_marks_repos_whose_index_is_locked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    std::fs::write(repo.dir.join(".git/index.lock"), "").unwrap();
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let output = dura.run_output(&["info"]);
+
+    assert!(output.contains("(git busy)"), "{output}");
+}
+
+#[cfg(unix)]
+#[test]
+fn summary_marks_read_only_repos() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let git_dir = repo.dir.join(".git");
+    if !util::make_read_only(&git_dir) {
+        return;
+    }
+
+    let output = dura.run_output(&["info"]);
+
+    std::fs::set_permissions(&git_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(output.contains("(read-only, backups disabled)"), "{output}");
+}
+
+#[test]
+fn summary_marks_repos_on_branches_not_backed_up() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let mut config = dura.get_config().unwrap();
+    for watch_config in config.repos.values_mut() {
+        std::rc::Rc::make_mut(watch_config).only_branches = vec!["feature/*".to_string()];
+    }
+    dura.save_config(&config);
+    let output = dura.run_output(&["info"]);
+    assert!(output.contains("(inactive on this branch)"), "{output}");
+
+    repo.git(&["checkout", "-q", "-b", "feature/login"])
+        .unwrap();
+    let output = dura.run_output(&["info"]);
+    assert!(!output.contains("(inactive on this branch)"), "{output}");
+}
+
+#[test]
+fn summary_marks_repos_without_commits() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = util::git_repo::GitRepo::new(tmp.path().to_path_buf());
+    repo.init();
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let output = dura.run_output(&["info", "--with-backups"]);
+
+    assert!(output.contains("0 backups"), "{output}");
+    assert!(output.contains("(no commits yet)"), "{output}");
+}
+
+#[cfg(unix)]
+#[test]
+fn unresolvable_repo_is_reported_unavailable() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mount = tmp.path().join("mount");
+    let repo = util::git_repo::GitRepo::new(mount.join("repo"));
+    fs::create_dir_all(&repo.dir).unwrap();
+    repo.init();
+    repo.write_file("foo.txt");
+    repo.commit_all();
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], &repo.dir);
+    let key = repo.dir.canonicalize().unwrap();
+
+    // like a mount going away, the path resolved when it was watched but doesn't anymore
+    fs::set_permissions(&mount, fs::Permissions::from_mode(0o000)).unwrap();
+    if key.canonicalize().is_ok() {
+        fs::set_permissions(&mount, fs::Permissions::from_mode(0o755)).unwrap();
+        println!("skipping, permissions aren't enforced");
+        return;
+    }
+    let summary = dura.run_output(&["info"]);
+    let detail = dura.run_output(&["info", "--detail"]);
+    fs::set_permissions(&mount, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let unavailable = dura::repo_status::UNAVAILABLE;
+    assert!(
+        summary.contains(&format!("{} ({})", key.display(), unavailable)),
+        "{summary}"
+    );
+    assert!(
+        detail.contains(&format!("Path is {unavailable}")),
+        "{detail}"
+    );
+    let config = dura.get_config().unwrap();
+    assert!(config.repos.contains_key(key.to_str().unwrap()));
+}
+
+#[test]
+fn json_summary_is_only_json() {
+    let tmp = tempfile::tempdir().unwrap();
+    let _repo = repo_and_file!(tmp, "foo.txt");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let output = dura.run_output(&["info", "--format", "json"]);
+
+    let json: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(json["stats"]["total_repos"], 1);
+    assert_eq!(json["repos"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn detailed_info_shows_when_a_throttled_repo_is_backed_up_next() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let key = repo.dir.canonicalize().unwrap();
+    let dura = Dura::new();
+    let mut config = dura::config::Config::empty();
+    config.repos.insert(
+        key.to_str().unwrap().to_string(),
+        std::rc::Rc::new(
+            dura::config::WatchConfig::builder()
+                .min_backup_interval(std::time::Duration::from_secs(600))
+                .build(),
+        ),
+    );
+    dura.save_config(&config);
+    repo.change_file("foo.txt");
+    dura.run(&["capture", key.to_str().unwrap()]);
+    repo.change_file("foo.txt");
+
+    let output = dura.run_output(&["info", "--detail"]);
+
+    assert!(
+        output.contains("Has uncommitted changes (next backup in 10m)"),
+        "{output}"
+    );
+}
+
+#[test]
+fn summary_reports_status_errors() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let other_tmp = tempfile::tempdir().unwrap();
+    let other = repo_and_file!(other_tmp, "foo.txt");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    dura.run_in_dir(&["watch"], other_tmp.path());
+    std::fs::write(repo.dir.join(".git").join("index"), "not an index").unwrap();
+    let output = dura.run_output(&["info"]);
+
+    let key = repo.dir.canonicalize().unwrap();
+    let line = output
+        .lines()
+        .find(|line| line.contains(key.to_str().unwrap()))
+        .unwrap();
+    assert!(line.contains("(status error: "), "{output}");
+    let other_key = other.dir.canonicalize().unwrap();
+    assert!(output.contains(other_key.to_str().unwrap()), "{output}");
+}
+
+#[test]
+fn summary_flags_dirty_repos_with_old_backups_as_stale() {
+    let old_tmp = tempfile::tempdir().unwrap();
+    let mut old = repo_and_file!(old_tmp, "foo.txt");
+    let recent_tmp = tempfile::tempdir().unwrap();
+    let mut recent = repo_and_file!(recent_tmp, "foo.txt");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], old_tmp.path());
+    dura.run_in_dir(&["watch"], recent_tmp.path());
+    let mut config = dura.get_config().unwrap();
+    config.stale_after = Some(std::time::Duration::from_secs(3600));
+    dura.save_config(&config);
+
+    // a backup from two hours ago
+    let git_repo = git2::Repository::open(&old.dir).unwrap();
+    let head = git_repo.head().unwrap().peel_to_commit().unwrap();
+    let two_hours_ago = head.time().seconds() - 2 * 3600;
+    let sig =
+        git2::Signature::new("dura", "dura@github.io", &git2::Time::new(two_hours_ago, 0)).unwrap();
+    git_repo
+        .commit(
+            Some(&format!("refs/heads/dura/{}", head.id())),
+            &sig,
+            &sig,
+            dura::snapshots::BACKUP_MESSAGE,
+            &head.tree().unwrap(),
+            &[&head],
+        )
+        .unwrap();
+    old.change_file("foo.txt");
+    recent.change_file("foo.txt");
+    dura.run(&["capture", recent.dir.to_str().unwrap()]);
+    recent.change_file("foo.txt");
+    let output = dura.run_output(&["info", "--with-backups"]);
+
+    let line_for = |repo: &util::git_repo::GitRepo| {
+        let key = repo.dir.canonicalize().unwrap();
+        output
+            .lines()
+            .find(|line| line.contains(key.to_str().unwrap()))
+            .unwrap()
+            .to_string()
+    };
+    assert!(line_for(&old).contains("(stale)"), "{output}");
+    assert!(!line_for(&recent).contains("(stale)"), "{output}");
+}
+
+#[test]
+fn summary_counts_backups_only_when_asked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    repo.change_file("foo.txt");
+    let capture = repo.dir.to_str().unwrap();
+    dura.run(&["capture", capture]);
+    let key = repo.dir.canonicalize().unwrap();
+    let key = key.to_str().unwrap();
+    // as if dura hadn't seen the backup yet, so only a history walk would find it
+    let mut lock = dura.get_runtime_lock().unwrap();
+    lock.repos.remove(key);
+    dura.save_runtime_lock(&lock);
+    let cached_backup = || {
+        dura.get_runtime_lock()
+            .and_then(|lock| lock.repos.get(key).cloned())
+            .and_then(|cache| cache.last_backup)
+    };
+
+    let fast = dura.run_output(&["info"]);
+    assert!(!fast.contains("backups, today"), "{fast}");
+    let repo_line = fast.lines().find(|line| line.contains(key)).unwrap();
+    assert!(!repo_line.ends_with(':'), "{fast}");
+    // an uncounted repo is counted for the total, but not kept in the cache
+    assert!(fast.contains("Total backups: 1 (cached)"), "{fast}");
+    assert_eq!(cached_backup(), None);
+
+    let counted = dura.run_output(&["info", "--with-backups"]);
+    assert!(
+        counted.contains(&format!("{key}: 1 backups, today: 1")),
+        "{counted}"
+    );
+    assert!(counted.contains("Total backups: 1"), "{counted}");
+    // info only reads, the cache is the daemon's to keep
+    assert_eq!(cached_backup(), None);
+
+    // the newest backup is shown from the cache once the daemon recorded it
+    let id = repo
+        .git(&["for-each-ref", "--format=%(objectname)", "refs/heads/dura/"])
+        .unwrap()
+        .trim()
+        .to_string();
+    let mut lock = dura.get_runtime_lock().unwrap();
+    lock.repos.entry(key.to_string()).or_default().last_backup = Some(id.clone());
+    dura.save_runtime_lock(&lock);
+    let fast = dura.run_output(&["info"]);
+    assert!(fast.contains(&format!("[{}]", &id[..7])), "{fast}");
+}
+
+#[test]
+fn summary_warns_when_config_changed_under_the_daemon() {
+    let tmp = tempfile::tempdir().unwrap();
+    repo_and_file!(tmp, "foo.txt");
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let mut config = dura.get_config().unwrap();
+    let mut lock = dura::database::RuntimeLock::empty();
+    lock.pid = Some(std::process::id());
+    lock.config_hash = Some(config.content_hash());
+    dura.save_runtime_lock(&lock);
+
+    let output = dura.run_output(&["info"]);
+    assert!(!output.contains("Config on disk differs"), "{output}");
+
+    config.stale_after = Some(std::time::Duration::from_secs(60));
+    dura.save_config(&config);
+    let output = dura.run_output(&["info"]);
+    assert!(
+        output.contains("Config on disk differs from the running daemon's"),
+        "{output}"
+    );
+}
+
+#[test]
+fn detailed_info_leaves_out_clean_repos_unless_all() {
+    let clean_tmp = tempfile::tempdir().unwrap();
+    let mut clean = repo_and_file!(clean_tmp, "foo.txt");
+    let dirty_tmp = tempfile::tempdir().unwrap();
+    let mut dirty = repo_and_file!(dirty_tmp, "foo.txt");
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], clean_tmp.path());
+    dura.run_in_dir(&["watch"], dirty_tmp.path());
+    // backed up just now, then committed
+    clean.change_file("foo.txt");
+    dura.run(&["capture", clean.dir.to_str().unwrap()]);
+    clean.commit_all();
+    dirty.change_file("foo.txt");
+    let clean_key = format!("{}\n", clean.dir.canonicalize().unwrap().display());
+    let dirty_key = format!("{}\n", dirty.dir.canonicalize().unwrap().display());
+
+    let output = dura.run_output(&["info", "--detail"]);
+    assert!(!output.contains(&clean_key), "{output}");
+    assert!(output.contains(&dirty_key), "{output}");
+    assert!(
+        output.contains("1 clean repositories not shown"),
+        "{output}"
+    );
+
+    let output = dura.run_output(&["info", "--detail", "--all"]);
+    assert!(output.contains(&clean_key), "{output}");
+    assert!(output.contains(&dirty_key), "{output}");
+}
+
+#[test]
+fn labeled_repos_are_shown_and_found_by_label() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let key = repo.dir.canonicalize().unwrap();
+    let dura = Dura::new();
+    dura.run(&["watch", "--label", "notes", key.to_str().unwrap()]);
+
+    let summary = dura.run_output(&["info"]);
+    assert!(
+        summary.contains(&format!("notes ({})", key.display())),
+        "{summary}"
+    );
+    let human = dura.run_output(&["info", "--format", "human"]);
+    assert!(
+        human.contains(&format!("notes ({})", key.display())),
+        "{human}"
+    );
+    let detail = dura.run_output(&["info", "--repo", "notes"]);
+    assert!(
+        detail.contains(&format!("notes ({})\n", key.display())),
+        "{detail}"
+    );
+    assert!(!detail.contains("Not watched"), "{detail}");
+}
+
+#[test]
+fn detailed_info_shows_the_upstream() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let mut config = dura.get_config().unwrap();
+    config.force_plain_symbols = Some(true);
+    dura.save_config(&config);
+    let output = dura.run_output(&["info", "--detail", "--all"]);
+    assert!(output.contains("No upstream branch"), "{output}");
+
+    let branch = repo.git(&["branch", "--show-current"]).unwrap();
+    let branch = branch.trim();
+    repo.git(&["remote", "add", "origin", "/nonexistent"])
+        .unwrap();
+    repo.git(&[
+        "update-ref",
+        &format!("refs/remotes/origin/{branch}"),
+        "HEAD",
+    ])
+    .unwrap();
+    repo.git(&["branch", &format!("--set-upstream-to=origin/{branch}")])
+        .unwrap();
+    repo.change_file("foo.txt");
+    repo.commit_all();
+    let output = dura.run_output(&["info", "--detail", "--all"]);
+
+    assert!(
+        output.contains(&format!("Upstream: origin/{branch} (ahead 1, behind 0)")),
+        "{output}"
+    );
+}
+
+#[test]
+fn detail_shows_the_last_error() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let mut lock = dura::database::RuntimeLock::empty();
+    let failed_at = std::time::SystemTime::now() - std::time::Duration::from_secs(26 * 60 * 60);
+    lock.record_error(
+        &repo.dir.canonicalize().unwrap(),
+        failed_at,
+        "index is corrupt",
+    );
+    dura.save_runtime_lock(&lock);
+
+    let output = dura.run_output(&["info", "--detail", "--all"]);
+
+    assert!(
+        output.contains("Last error: index is corrupt (1d 2h ago)"),
+        "{output}"
+    );
+}
+
+#[test]
+fn summary_reports_a_head_on_a_deleted_branch() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    std::fs::write(repo.dir.join(".git/HEAD"), "ref: refs/heads/deleted\n").unwrap();
+    repo.change_file("foo.txt");
+
+    let dura = Dura::new();
+    dura.run_in_dir(&["watch"], tmp.path());
+    let output = dura.run_output(&["info"]);
+
+    assert!(
+        output.contains("(broken HEAD: refs/heads/deleted)"),
+        "{output}"
+    );
+    assert!(!output.contains("(no commits yet)"), "{output}");
+    assert!(output.contains("Overall Status:"), "{output}");
+}
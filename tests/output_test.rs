@@ -0,0 +1,210 @@
+use dura::config::{Config, SummaryStats, WatchConfig};
+use dura::output::{exit_code_for, render_json, render_porcelain, OutputFormat};
+use dura::repo_status::{RepoState, RepoStatus};
+use std::env;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+
+#[macro_use]
+extern crate serial_test;
+
+fn fixture() -> (SummaryStats, Vec<RepoStatus>) {
+    let stats = SummaryStats {
+        total_repos: 2,
+        dirty_repos: 1,
+        dirty_files: 3,
+        total_backups: 7,
+        inaccessible_repos: 1,
+    };
+    let repos = vec![
+        RepoStatus {
+            path: PathBuf::from("/work/app"),
+            label: None,
+            exists: true,
+            unavailable: false,
+            is_git_repo: true,
+            last_backup: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            backups: 7,
+            uncommitted_changes: true,
+            dirty_files: 3,
+            status_error: None,
+            open_retry_at: None,
+            state: RepoState::Merge,
+            upstream: None,
+        },
+        RepoStatus {
+            path: PathBuf::from("/work/gone"),
+            label: None,
+            exists: false,
+            unavailable: false,
+            is_git_repo: false,
+            last_backup: None,
+            backups: 0,
+            uncommitted_changes: false,
+            dirty_files: 0,
+            status_error: None,
+            open_retry_at: None,
+            state: RepoState::Normal,
+            upstream: None,
+        },
+    ];
+    (stats, repos)
+}
+
+#[test]
+fn formats_are_parsed_case_insensitively() {
+    assert_eq!("JSON".parse(), Ok(OutputFormat::Json));
+    assert_eq!("porcelain".parse(), Ok(OutputFormat::Porcelain));
+    assert!("yaml".parse::<OutputFormat>().is_err());
+}
+
+#[test]
+fn json_has_the_collected_data() {
+    let (stats, repos) = fixture();
+
+    let json: serde_json::Value = serde_json::from_str(&render_json(&stats, &repos)).unwrap();
+
+    assert_eq!(json["stats"]["total_repos"], 2);
+    assert_eq!(json["stats"]["dirty_files"], 3);
+    assert_eq!(json["stats"]["total_backups"], 7);
+    let paths: Vec<&str> = json["repos"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|repo| repo["path"].as_str().unwrap())
+        .collect();
+    assert_eq!(paths, vec!["/work/app", "/work/gone"]);
+    assert_eq!(json["repos"][0]["state"], "merge");
+}
+
+#[test]
+fn porcelain_has_the_collected_data() {
+    let (stats, repos) = fixture();
+
+    let porcelain = render_porcelain(&stats, &repos);
+
+    assert_eq!(
+        porcelain,
+        "total_repos 2\n\
+         dirty_repos 1\n\
+         dirty_files 3\n\
+         total_backups 7\n\
+         inaccessible_repos 1\n\
+         repo\t/work/app\tdirty\t1700000000\tmerge\n\
+         repo\t/work/gone\tmissing\t-\tnone\n"
+    );
+}
+
+#[test]
+fn stats_are_the_totals_of_the_statuses() {
+    let (stats, repos) = fixture();
+
+    assert_eq!(SummaryStats::of(&repos), stats);
+}
+
+#[test]
+#[serial]
+fn human_format_is_the_plain_summary() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    let repo_dir = tmp.path().join("repo");
+    git2::Repository::init(&repo_dir).unwrap();
+    std::fs::write(repo_dir.join("foo.txt"), "dirty").unwrap();
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo_dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+
+    let mut plain = Vec::new();
+    config.write_summary(&mut plain, false).unwrap();
+    let mut human = Vec::new();
+    config
+        .write_summary_as(&mut human, OutputFormat::Human)
+        .unwrap();
+
+    env::remove_var("DURA_CACHE_HOME");
+    assert_eq!(
+        String::from_utf8(human).unwrap(),
+        String::from_utf8(plain).unwrap()
+    );
+}
+
+/// A writer for a pipe whose reader went away.
+struct ClosedPipe;
+
+impl Write for ClosedPipe {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+#[serial]
+fn closed_pipe_is_reported_instead_of_panicking() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    let mut config = Config::empty();
+    config.repos.insert(
+        tmp.path().to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+
+    let results = [
+        config.write_summary(&mut ClosedPipe, true),
+        config.write_summary_as(&mut ClosedPipe, OutputFormat::Porcelain),
+        config.write_grouped_summary(&mut ClosedPipe, true),
+        config.write_detailed_info(&mut ClosedPipe, true),
+        config.write_detailed_info_for_many(&mut ClosedPipe, &[tmp.path().to_path_buf()]),
+    ];
+
+    for result in results {
+        let e = result.unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+        assert_eq!(exit_code_for(&e), 0);
+    }
+    env::remove_var("DURA_CACHE_HOME");
+}
+
+#[test]
+fn other_write_errors_fail() {
+    let e = io::Error::from(io::ErrorKind::PermissionDenied);
+    assert_eq!(exit_code_for(&e), 1);
+}
+
+#[test]
+#[serial]
+fn machine_formats_stay_ascii_when_fancy_symbols_are_forced() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    env::set_var("DURA_FANCY", "1");
+    let repo_dir = tmp.path().join("repo");
+    git2::Repository::init(&repo_dir).unwrap();
+    std::fs::write(repo_dir.join("foo.txt"), "dirty").unwrap();
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo_dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+
+    let mut human = Vec::new();
+    config.write_summary(&mut human, false).unwrap();
+    let mut machine = Vec::new();
+    for format in [OutputFormat::Json, OutputFormat::Porcelain] {
+        config.write_summary_as(&mut machine, format).unwrap();
+    }
+
+    env::remove_var("DURA_FANCY");
+    env::remove_var("DURA_CACHE_HOME");
+    assert!(
+        !human.is_ascii(),
+        "symbols aren't fancy, the test proves nothing"
+    );
+    assert!(machine.is_ascii(), "{}", String::from_utf8_lossy(&machine));
+}
@@ -57,3 +57,17 @@ fn file_changed_after_snapshot() {
     repo.change_file("foo.txt");
     assert!(pg.dir_changed(repo.dir.as_path()));
 }
+
+#[test]
+fn last_change_leaves_out_the_git_dir() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut pg = PollGuard::new();
+
+    sleep(Duration::from_secs_f64(1.5));
+    std::fs::write(repo.dir.join(".git/description"), "touched").unwrap();
+    assert_eq!(pg.last_change(repo.dir.as_path()), None);
+
+    repo.change_file("foo.txt");
+    assert!(pg.last_change(repo.dir.as_path()).is_some());
+}
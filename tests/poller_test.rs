@@ -0,0 +1,247 @@
+use dura::backup_queue::{backup_queue, BackupReceiver};
+use dura::config::{Config, ConfigError, WatchConfig};
+use dura::database::RuntimeLock;
+use dura::poller::{
+    self, ConfigReload, Debouncer, IdleBackoff, PollState, IDLE_CYCLES_BEFORE_BACKOFF,
+};
+use serial_test::serial;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
+use std::{env, fs, process, thread};
+
+mod util;
+
+const BASE: Duration = Duration::from_secs(5);
+
+#[test]
+fn interval_grows_while_idle_then_resets() {
+    let mut backoff = IdleBackoff::new(BASE);
+    backoff.set_max(Duration::from_secs(60));
+
+    for _ in 0..IDLE_CYCLES_BEFORE_BACKOFF {
+        assert_eq!(backoff.record_cycle(false), BASE);
+    }
+    let grown: Vec<u64> = (0..5)
+        .map(|_| backoff.record_cycle(false).as_secs())
+        .collect();
+    assert_eq!(grown, vec![10, 20, 40, 60, 60]);
+
+    assert_eq!(backoff.record_cycle(true), BASE);
+    assert_eq!(backoff.interval(), BASE);
+    for _ in 0..IDLE_CYCLES_BEFORE_BACKOFF {
+        assert_eq!(backoff.record_cycle(false), BASE);
+    }
+    assert_eq!(backoff.record_cycle(false), BASE * 2);
+}
+
+#[test]
+fn no_backoff_without_a_max() {
+    let mut backoff = IdleBackoff::new(BASE);
+
+    for _ in 0..IDLE_CYCLES_BEFORE_BACKOFF * 2 {
+        assert_eq!(backoff.record_cycle(false), BASE);
+    }
+}
+
+#[test]
+fn lowering_the_max_caps_the_current_interval() {
+    let mut backoff = IdleBackoff::new(BASE);
+    backoff.set_max(Duration::from_secs(60));
+    for _ in 0..IDLE_CYCLES_BEFORE_BACKOFF + 10 {
+        backoff.record_cycle(false);
+    }
+    assert_eq!(backoff.interval(), Duration::from_secs(60));
+
+    backoff.set_max(Duration::from_secs(15));
+    assert_eq!(backoff.interval(), Duration::from_secs(15));
+}
+
+#[test]
+#[serial]
+fn reload_applies_watches_from_disk() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    env::set_var("DURA_CACHE_HOME", tmp.path());
+    let watch = |config: &mut Config, key: &str| {
+        config
+            .repos
+            .insert(key.to_string(), Rc::new(WatchConfig::new()));
+    };
+    let mut on_disk = Config::empty();
+    watch(&mut on_disk, "/work/kept");
+    watch(&mut on_disk, "/work/added");
+    on_disk.save();
+    let mut running = Config::empty();
+    watch(&mut running, "/work/kept");
+    watch(&mut running, "/work/removed");
+
+    let changes = poller::reload(&mut running).unwrap();
+
+    assert_eq!(
+        changes,
+        ConfigReload {
+            added: vec!["/work/added".to_string()],
+            removed: vec!["/work/removed".to_string()],
+        }
+    );
+    assert_eq!(running, on_disk);
+    let runtime_lock = RuntimeLock::load();
+    assert_eq!(runtime_lock.config_hash, Some(on_disk.content_hash()));
+    assert!(!runtime_lock.config_drifted(&on_disk));
+}
+
+#[test]
+#[serial]
+fn broken_config_keeps_the_running_one() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    env::set_var("DURA_CACHE_HOME", tmp.path());
+    fs::write(Config::default_path(), "[repos\n").unwrap();
+    let mut running = Config::empty();
+    running
+        .repos
+        .insert("/work/kept".to_string(), Rc::new(WatchConfig::new()));
+
+    let result = poller::reload(&mut running);
+
+    assert!(matches!(result, Err(ConfigError::Parse(..))));
+    assert_eq!(running.repos.keys().collect::<Vec<_>>(), vec!["/work/kept"]);
+}
+
+#[test]
+fn debouncer_yields_each_repo_once_per_quiet_window() {
+    let ms = Duration::from_millis;
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let repo = PathBuf::from("/work/app");
+    let other = PathBuf::from("/work/lib");
+    let mut debouncer = Debouncer::new(ms(500));
+
+    // a burst of saves
+    for offset in [0, 40, 120, 300] {
+        debouncer.record(repo.clone(), start + ms(offset));
+    }
+    debouncer.record(other.clone(), start + ms(400));
+    assert!(debouncer.due(start + ms(700)).is_empty());
+    assert_eq!(debouncer.due(start + ms(800)), vec![repo.clone()]);
+    assert!(debouncer.due(start + ms(800)).is_empty());
+    assert_eq!(debouncer.due(start + ms(2000)), vec![other]);
+
+    // the next burst is another backup
+    debouncer.record(repo.clone(), start + ms(5000));
+    debouncer.record(repo.clone(), start + ms(5100));
+    assert!(debouncer.due(start + ms(5500)).is_empty());
+    assert_eq!(debouncer.due(start + ms(5600)), vec![repo]);
+}
+
+#[test]
+fn debouncer_without_a_window_is_due_at_once() {
+    let now = SystemTime::now();
+    let mut debouncer = Debouncer::new(Duration::ZERO);
+    debouncer.record(PathBuf::from("/work/app"), now);
+
+    assert_eq!(debouncer.due(now), vec![PathBuf::from("/work/app")]);
+}
+
+/// A config watching each of `repos`, as the daemon running this test has it, without a
+/// debounce window so that changed repos are queued on the poll that sees them.
+fn polled_config(tmp: &tempfile::TempDir, repos: &[&Path]) -> Config {
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    env::set_var("DURA_CACHE_HOME", tmp.path());
+    let mut runtime_lock = RuntimeLock::empty();
+    runtime_lock.pid = Some(process::id());
+    runtime_lock.save();
+    let mut config = Config::empty();
+    config.debounce_ms = None;
+    for repo in repos {
+        let key = repo.canonicalize().unwrap();
+        config.repos.insert(
+            key.to_str().unwrap().to_string(),
+            Rc::new(WatchConfig::new()),
+        );
+    }
+    config
+}
+
+fn queued(receiver: &BackupReceiver) -> Vec<PathBuf> {
+    std::iter::from_fn(|| receiver.try_recv()).collect()
+}
+
+#[test]
+#[serial]
+fn repos_over_the_size_limit_are_not_queued() {
+    let home = tempfile::tempdir().unwrap();
+    let (small_dir, big_dir) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    let mut small = repo_and_file!(small_dir, "foo.txt");
+    let mut big = repo_and_file!(big_dir, "foo.txt");
+    fs::write(big.dir.join(".git/big.pack"), vec![0u8; 2 * 1024 * 1024]).unwrap();
+    let mut config = polled_config(&home, &[&small.dir, &big.dir]);
+    config.max_repo_size_mb = Some(1);
+    // the poll guard only sees changes more than a second after the last commit
+    thread::sleep(Duration::from_millis(2100));
+    small.change_file("foo.txt");
+    big.change_file("foo.txt");
+
+    let (queue, receiver) = backup_queue(8);
+    poller::do_task(&config, &queue, &mut PollState::new());
+
+    assert_eq!(queued(&receiver), vec![small.dir.canonicalize().unwrap()]);
+}
+
+#[test]
+#[serial]
+fn first_change_from_clean_to_dirty_is_seen() {
+    let home = tempfile::tempdir().unwrap();
+    let repo_dir = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(repo_dir, "foo.txt");
+    let mut config = polled_config(&home, &[&repo.dir]);
+    config.notify_on_dirty = true;
+    let path = repo.dir.canonicalize().unwrap();
+    let (queue, _receiver) = backup_queue(8);
+    let mut state = PollState::new();
+
+    poller::do_task(&config, &queue, &mut state);
+    assert_eq!(RuntimeLock::load().dirty_state(&path), Some(false));
+
+    thread::sleep(Duration::from_millis(2100));
+    repo.change_file("foo.txt");
+    poller::do_task(&config, &queue, &mut state);
+
+    assert_eq!(RuntimeLock::load().dirty_state(&path), Some(true));
+}
+
+#[test]
+#[serial]
+fn repos_that_fail_to_open_are_backed_off_from() {
+    let home = tempfile::tempdir().unwrap();
+    let (healthy_dir, corrupt) = (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+    let mut healthy = repo_and_file!(healthy_dir, "foo.txt");
+    fs::create_dir(corrupt.path().join(".git")).unwrap();
+    let config = polled_config(&home, &[&healthy.dir, corrupt.path()]);
+    thread::sleep(Duration::from_millis(2100));
+    healthy.change_file("foo.txt");
+    let corrupt_path = corrupt.path().canonicalize().unwrap();
+    let open_failures = || RuntimeLock::load().repos[corrupt_path.to_str().unwrap()].open_failures;
+
+    let (queue, receiver) = backup_queue(8);
+    let mut state = PollState::new();
+    poller::do_task(&config, &queue, &mut state);
+
+    assert_eq!(queued(&receiver), vec![healthy.dir.canonicalize().unwrap()]);
+    assert_eq!(open_failures(), 1);
+    assert!(RuntimeLock::load()
+        .open_retry_at(&corrupt_path, SystemTime::now())
+        .is_some());
+
+    // skipped until the retry is due, so it doesn't fail again
+    poller::do_task(&config, &queue, &mut state);
+    assert_eq!(open_failures(), 1);
+
+    let mut summary = Vec::new();
+    config.write_summary(&mut summary, false).unwrap();
+    let summary = String::from_utf8(summary).unwrap();
+    assert!(
+        summary.contains("Not a git repository (retrying in"),
+        "{summary}"
+    );
+}
@@ -0,0 +1,80 @@
+use dura::config::{Config, WatchConfig};
+use dura::preflight::PreflightTarget;
+use std::env;
+use std::fs;
+use std::rc::Rc;
+
+mod util;
+
+#[macro_use]
+extern crate serial_test;
+
+#[test]
+#[serial]
+fn writable_dirs_pass() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    env::set_var("DURA_CONFIG_HOME", tmp.path().join("config"));
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+    let results = config.preflight();
+
+    let targets: Vec<_> = results.iter().map(|r| r.target).collect();
+    assert_eq!(
+        targets,
+        vec![
+            PreflightTarget::ConfigHome,
+            PreflightTarget::CacheHome,
+            PreflightTarget::GitDir
+        ]
+    );
+    assert!(results.iter().all(|r| r.passed()), "{results:?}");
+}
+
+#[cfg(unix)]
+#[test]
+#[serial]
+fn read_only_homes_fail() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let read_only = tmp.path().join("read-only");
+    fs::create_dir(&read_only).unwrap();
+    fs::set_permissions(&read_only, fs::Permissions::from_mode(0o555)).unwrap();
+    if fs::write(read_only.join("probe"), "").is_ok() {
+        // e.g. running as root, permissions aren't enforced
+        println!("skipping, can't make a read-only directory");
+        return;
+    }
+    env::set_var("DURA_CONFIG_HOME", &read_only);
+    env::set_var("DURA_CACHE_HOME", read_only.join("cache"));
+
+    let results = Config::empty().preflight();
+
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|r| !r.passed()), "{results:?}");
+    assert_eq!(results[0].path, read_only);
+}
+
+#[test]
+#[serial]
+fn non_repo_watch_fails() {
+    let tmp = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", tmp.path().join("config"));
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+
+    let mut config = Config::empty();
+    config.repos.insert(
+        tmp.path().to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+    let results = config.preflight();
+
+    assert_eq!(results[2].target, PreflightTarget::GitDir);
+    assert!(!results[2].passed());
+}
@@ -0,0 +1,50 @@
+use dura::config::WatchConfig;
+use dura::presets::{detect_project_kind, ProjectKind};
+use std::fs;
+
+#[test]
+fn preset_excludes() {
+    assert_eq!(
+        WatchConfig::preset(ProjectKind::Rust).exclude,
+        vec!["target/**"]
+    );
+    assert_eq!(
+        WatchConfig::preset(ProjectKind::Node).exclude,
+        vec!["node_modules/**"]
+    );
+    assert_eq!(
+        WatchConfig::preset(ProjectKind::Python).exclude,
+        vec!["__pycache__/**", ".venv/**", "venv/**", ".tox/**"]
+    );
+    assert_eq!(
+        WatchConfig::preset(ProjectKind::Generic),
+        WatchConfig::new()
+    );
+}
+
+#[test]
+fn detects_cargo_toml_as_rust() {
+    let tmp = tempfile::tempdir().unwrap();
+    fs::write(tmp.path().join("Cargo.toml"), "[package]").unwrap();
+
+    assert_eq!(detect_project_kind(tmp.path()), ProjectKind::Rust);
+}
+
+#[test]
+fn detects_other_kinds() {
+    let node = tempfile::tempdir().unwrap();
+    fs::write(node.path().join("package.json"), "{}").unwrap();
+    let python = tempfile::tempdir().unwrap();
+    fs::write(python.path().join("pyproject.toml"), "").unwrap();
+    let generic = tempfile::tempdir().unwrap();
+
+    assert_eq!(detect_project_kind(node.path()), ProjectKind::Node);
+    assert_eq!(detect_project_kind(python.path()), ProjectKind::Python);
+    assert_eq!(detect_project_kind(generic.path()), ProjectKind::Generic);
+}
+
+#[test]
+fn parses_kind_names() {
+    assert_eq!("Rust".parse::<ProjectKind>(), Ok(ProjectKind::Rust));
+    assert!("cobol".parse::<ProjectKind>().is_err());
+}
@@ -0,0 +1,64 @@
+use dura::config::Config;
+use dura::database::RuntimeLock;
+use dura::preflight::{self, PreflightTarget};
+use dura::{read_only, snapshots};
+use std::fs;
+
+mod util;
+
+// Read-only mode is process wide, so every test in this binary runs with it enabled
+
+#[test]
+fn config_save_leaves_file_unchanged() {
+    read_only::enable();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("config.toml");
+    fs::write(&path, "original").unwrap();
+
+    let mut config = Config::empty();
+    config.commit_author = Some("someone".to_string());
+    config.save_to_path(path.as_path()).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+}
+
+#[test]
+fn runtime_lock_save_leaves_file_unchanged() {
+    read_only::enable();
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("runtime.db");
+    fs::write(&path, "original").unwrap();
+
+    let mut runtime_lock = RuntimeLock::empty();
+    runtime_lock.pid = Some(12345);
+    runtime_lock.save_to_path(path.as_path());
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+}
+
+#[test]
+fn capture_makes_no_commit() {
+    read_only::enable();
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+
+    assert_eq!(snapshots::capture(repo.dir.as_path()).unwrap(), None);
+    assert_eq!(
+        repo.git(&["branch", "--list", "dura/*"]),
+        Some(String::new())
+    );
+}
+
+#[test]
+fn preflight_writes_nothing() {
+    read_only::enable();
+    let tmp = tempfile::tempdir().unwrap();
+    let missing = tmp.path().join("config");
+
+    let result = preflight::check_writable(PreflightTarget::ConfigHome, &missing);
+
+    assert!(result.passed(), "{result:?}");
+    assert!(!missing.exists());
+    assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+}
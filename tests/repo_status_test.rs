@@ -0,0 +1,245 @@
+use dura::config::{Config, WatchConfig};
+use dura::repo_status::{self, RepoState, RepoStatus, Upstream};
+use std::env;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+mod util;
+
+#[macro_use]
+extern crate serial_test;
+
+#[test]
+fn clean_repo_has_no_operation_in_progress() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+
+    let status = RepoStatus::collect(&Config::empty(), &repo.dir);
+
+    assert!(status.exists);
+    assert!(status.is_git_repo);
+    assert!(!status.uncommitted_changes);
+    assert_eq!(status.state, RepoState::Normal);
+}
+
+#[test]
+fn conflicted_merge_is_reported() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.git(&["checkout", "-b", "branch1"]).unwrap();
+    repo.change_file("foo.txt");
+    repo.commit_all();
+    repo.git(&["checkout", "master"]).unwrap();
+    repo.change_file("foo.txt");
+    repo.commit_all();
+    assert_eq!(repo.git(&["merge", "branch1"]), None);
+
+    let status = RepoStatus::collect(&Config::empty(), &repo.dir);
+
+    assert_eq!(status.state, RepoState::Merge);
+    assert!(status.uncommitted_changes);
+}
+
+#[test]
+fn missing_repo() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    let status = RepoStatus::collect(&Config::empty(), &tmp.path().join("missing"));
+
+    assert!(!status.exists);
+    assert!(!status.is_git_repo);
+    assert_eq!(status.last_backup, None);
+}
+
+#[test]
+#[serial]
+fn status_is_cached_until_a_repo_is_touched() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    let mut config = Config::empty();
+    config.status_cache_ttl = Duration::from_secs(60);
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+    assert!(!config.collect_status()[0].uncommitted_changes);
+
+    // rewriting a file in place leaves the directory's mtime alone, so the cache still holds
+    repo.change_file("foo.txt");
+    assert!(!config.collect_status()[0].uncommitted_changes);
+
+    // give the (coarse) file system clock time to move past the cache time
+    thread::sleep(Duration::from_millis(50));
+    repo.write_file("bar.txt");
+    assert!(config.collect_status()[0].uncommitted_changes);
+}
+
+#[test]
+#[serial]
+fn status_is_not_cached_by_default() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    env::set_var("DURA_CACHE_HOME", tmp.path().join("cache"));
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::new()),
+    );
+    assert!(!config.collect_status()[0].uncommitted_changes);
+
+    repo.change_file("foo.txt");
+    assert!(config.collect_status()[0].uncommitted_changes);
+}
+
+#[test]
+fn unborn_repo_is_clean_without_backups() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = util::git_repo::GitRepo::new(tmp.path().to_path_buf());
+    repo.init();
+
+    let config = Config::empty();
+    let status = RepoStatus::collect(&config, &repo.dir);
+
+    assert!(status.is_git_repo);
+    assert!(!status.uncommitted_changes);
+    assert_eq!(status.last_backup, None);
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+    assert!(dura::snapshots::is_unborn(&git_repo));
+    assert_eq!(config.count_backups(&git_repo).count, 0);
+}
+
+#[test]
+fn missing_repo_is_not_unavailable() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    let status = RepoStatus::collect(&Config::empty(), &tmp.path().join("missing"));
+
+    assert!(!status.exists);
+    assert!(!status.unavailable);
+}
+
+#[test]
+fn corrupt_index_is_a_status_error() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    std::fs::write(repo.dir.join(".git").join("index"), "not an index").unwrap();
+
+    let status = RepoStatus::collect(&Config::empty(), &repo.dir);
+
+    assert!(status.is_git_repo);
+    assert!(!status.uncommitted_changes);
+    assert!(status.status_error.is_some(), "{status:?}");
+}
+
+#[test]
+fn upstream_counts_commits_on_each_side() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let branch = repo.git(&["branch", "--show-current"]).unwrap();
+    let branch = branch.trim();
+    assert_eq!(
+        RepoStatus::collect(&Config::empty(), &repo.dir).upstream,
+        None
+    );
+
+    repo.git(&["remote", "add", "origin", "/nonexistent"])
+        .unwrap();
+    let remote_ref = format!("refs/remotes/origin/{branch}");
+    repo.git(&["update-ref", &remote_ref, "HEAD"]).unwrap();
+    repo.git(&["branch", &format!("--set-upstream-to=origin/{branch}")])
+        .unwrap();
+    // one commit only the remote has, two only the local branch has
+    let remote_commit = repo
+        .git(&["commit-tree", "HEAD^{tree}", "-p", "HEAD", "-m", "remote"])
+        .unwrap();
+    repo.git(&["update-ref", &remote_ref, remote_commit.trim()])
+        .unwrap();
+    for _ in 0..2 {
+        repo.change_file("foo.txt");
+        repo.commit_all();
+    }
+
+    let upstream = RepoStatus::collect(&Config::empty(), &repo.dir)
+        .upstream
+        .unwrap();
+
+    assert_eq!(upstream.name, format!("origin/{branch}"));
+    assert_eq!((upstream.ahead, upstream.behind), (2, 1));
+}
+
+fn representative_status() -> RepoStatus {
+    RepoStatus {
+        path: PathBuf::from("/work/app"),
+        label: Some("app".to_string()),
+        exists: true,
+        unavailable: false,
+        is_git_repo: true,
+        last_backup: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+        backups: 4,
+        uncommitted_changes: true,
+        dirty_files: 3,
+        status_error: None,
+        open_retry_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_600)),
+        state: RepoState::CherryPick,
+        upstream: Some(Upstream {
+            name: "origin/main".to_string(),
+            ahead: 2,
+            behind: 1,
+        }),
+    }
+}
+
+#[test]
+fn json_shape_matches_the_golden_file() {
+    let json = serde_json::to_string_pretty(&representative_status()).unwrap();
+
+    assert_eq!(
+        format!("{json}\n"),
+        include_str!("fixtures/repo_status.json")
+    );
+}
+
+#[test]
+fn json_round_trips() {
+    let status: RepoStatus =
+        serde_json::from_str(include_str!("fixtures/repo_status.json")).unwrap();
+
+    assert_eq!(status, representative_status());
+}
+
+#[test]
+fn times_in_the_old_format_are_still_read() {
+    let mut json = serde_json::to_value(representative_status()).unwrap();
+    json["last_backup"] = serde_json::json!({
+        "secs_since_epoch": 1_700_000_000,
+        "nanos_since_epoch": 0,
+    });
+
+    let status: RepoStatus = serde_json::from_value(json).unwrap();
+
+    assert_eq!(status, representative_status());
+}
+
+#[test]
+fn only_denied_writes_to_the_git_dir_are_write_errors() {
+    let git_dir = PathBuf::from("/work/app/.git/");
+    let os_error = |message: &str| {
+        git2::Error::new(git2::ErrorCode::GenericError, git2::ErrorClass::Os, message)
+    };
+
+    assert!(repo_status::is_write_error(
+        &os_error("failed to make directory '/work/app/.git/objects/ab': Permission denied"),
+        &git_dir
+    ));
+    assert!(repo_status::is_write_error(
+        &os_error("failed to lock file '/work/app/.git/refs/heads/x.lock': Read-only file system"),
+        &git_dir
+    ));
+    assert!(!repo_status::is_write_error(
+        &os_error("could not open '/work/app/secret.txt' for reading: Permission denied"),
+        &git_dir
+    ));
+}
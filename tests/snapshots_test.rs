@@ -1,6 +1,15 @@
-use dura::{config::Config, snapshots};
+use chrono::Local;
+use dura::clock::FixedClock;
+use dura::config::{BackupStrategy, Config, SubmoduleMode, WatchConfig};
+use dura::database::RuntimeLock;
+use dura::snapshots::{self, HeadState};
 
 use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::rc::Rc;
+use std::time::{Duration, SystemTime};
 
 mod util;
 
@@ -146,3 +155,908 @@ fn test_commit_signature_exclude_git_config() {
         .unwrap();
     assert_eq!(commit_email, "dura@github.io");
 }
+
+/// A repo with a submodule at `sub`, whose checkout was then moved back one commit. Returns the
+/// superproject and the commit the submodule now points at.
+fn repo_with_moved_submodule(tmp: &tempfile::TempDir) -> (util::git_repo::GitRepo, String) {
+    let mut sub = util::git_repo::GitRepo::new(tmp.path().join("sub"));
+    sub.init();
+    sub.write_file("a.txt");
+    sub.commit_all();
+    sub.change_file("a.txt");
+    sub.commit_all();
+
+    let superproject = util::git_repo::GitRepo::new(tmp.path().join("super"));
+    superproject.init();
+    superproject.write_file("foo.txt");
+    // `git submodule` refuses the explicit --work-tree that GitRepo::git passes
+    git_in(
+        &superproject.dir,
+        &[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "add",
+            sub.dir.to_str().unwrap(),
+            "sub",
+        ],
+    );
+    superproject.commit_all();
+
+    let checkout = superproject.dir.join("sub");
+    git_in(&checkout, &["checkout", "-q", "HEAD~1"]);
+    let moved_to = git_in(&checkout, &["rev-parse", "HEAD"]);
+    (superproject, moved_to)
+}
+
+fn git_in(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {args:?} failed");
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+fn watch_with_submodule_mode(tmp: &tempfile::TempDir, repo: &Path, mode: SubmoduleMode) {
+    watch_with(
+        tmp,
+        repo,
+        WatchConfig::builder().submodule_mode(mode).build(),
+    );
+}
+
+fn watch_with(tmp: &tempfile::TempDir, repo: &Path, watch_config: WatchConfig) {
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    let mut dura_config = Config::empty();
+    dura_config
+        .repos
+        .insert(repo.to_str().unwrap().to_string(), Rc::new(watch_config));
+    dura_config.save();
+}
+
+#[test]
+#[serial]
+fn moved_submodule_is_ignored_by_default() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (repo, _) = repo_with_moved_submodule(&tmp);
+    watch_with_submodule_mode(&tmp, &repo.dir, SubmoduleMode::default());
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap();
+
+    assert_eq!(status, None);
+}
+
+#[test]
+#[serial]
+fn ignored_submodule_keeps_its_pointer_in_backups() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (mut repo, _) = repo_with_moved_submodule(&tmp);
+    watch_with_submodule_mode(&tmp, &repo.dir, SubmoduleMode::Ignore);
+    let committed = repo.git(&["rev-parse", "HEAD:sub"]).unwrap();
+
+    repo.change_file("foo.txt");
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let recorded = repo
+        .git(&["rev-parse", &format!("{}:sub", status.commit_hash)])
+        .unwrap();
+    assert_eq!(recorded, committed);
+}
+
+#[test]
+#[serial]
+fn recorded_submodule_pointer_is_backed_up() {
+    let tmp = tempfile::tempdir().unwrap();
+    let (repo, moved_to) = repo_with_moved_submodule(&tmp);
+    watch_with_submodule_mode(&tmp, &repo.dir, SubmoduleMode::RecordPointer);
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let recorded = repo
+        .git(&["rev-parse", &format!("{}:sub", status.commit_hash)])
+        .unwrap();
+    assert_eq!(recorded.trim(), moved_to);
+}
+
+#[test]
+#[serial]
+fn unborn_repo_is_skipped_by_default() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = util::git_repo::GitRepo::new(tmp.path().join("repo"));
+    repo.init();
+    repo.write_file("foo.txt");
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    Config::empty().save();
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap();
+
+    assert_eq!(status, None);
+}
+
+#[test]
+#[serial]
+fn unborn_repo_is_backed_up_when_configured() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = util::git_repo::GitRepo::new(tmp.path().join("repo"));
+    repo.init();
+    repo.write_file("foo.txt");
+    env::set_var("DURA_CONFIG_HOME", tmp.path());
+    let mut dura_config = Config::empty();
+    dura_config.backup_unborn_repos = true;
+    dura_config.save();
+
+    let first = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    assert_eq!(first.dura_branch, snapshots::UNBORN_BRANCH);
+    assert_eq!(first.base_hash, git2::Oid::zero().to_string());
+
+    repo.change_file("foo.txt");
+    let second = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+    assert_eq!(second.dura_branch, snapshots::UNBORN_BRANCH);
+    let parent = repo
+        .git(&["rev-parse", &format!("{}^", second.commit_hash)])
+        .unwrap();
+    assert_eq!(parent.trim(), first.commit_hash);
+}
+
+#[test]
+#[serial]
+fn large_files_are_left_out_of_backups() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(
+        &tmp,
+        &repo.dir,
+        WatchConfig::builder().max_file_size_mb(1).build(),
+    );
+    fs::write(repo.dir.join("big.bin"), vec![0u8; 2 * 1024 * 1024]).unwrap();
+    repo.write_file("small.txt");
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let files = repo
+        .git(&["ls-tree", "--name-only", &status.commit_hash])
+        .unwrap();
+    let files: Vec<&str> = files.lines().collect();
+    assert!(files.contains(&"small.txt"), "{files:?}");
+    assert!(!files.contains(&"big.bin"), "{files:?}");
+}
+
+#[test]
+#[serial]
+fn huge_file_size_limit_leaves_nothing_out() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(
+        &tmp,
+        &repo.dir,
+        WatchConfig::builder().max_file_size_mb(u64::MAX).build(),
+    );
+    fs::write(repo.dir.join("big.bin"), vec![0u8; 2 * 1024 * 1024]).unwrap();
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let files = repo
+        .git(&["ls-tree", "--name-only", &status.commit_hash])
+        .unwrap();
+    assert!(files.lines().any(|file| file == "big.bin"), "{files}");
+}
+
+#[test]
+#[serial]
+fn excluded_files_are_left_out_of_backups() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(
+        &tmp,
+        &repo.dir,
+        WatchConfig::builder().exclude(["target/**"]).build(),
+    );
+    fs::create_dir(repo.dir.join("target")).unwrap();
+    repo.write_file("target/out.o");
+    repo.write_file("src.rs");
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let files = repo
+        .git(&["ls-tree", "-r", "--name-only", &status.commit_hash])
+        .unwrap();
+    let files: Vec<&str> = files.lines().collect();
+    assert!(files.contains(&"src.rs"), "{files:?}");
+    assert!(!files.contains(&"target/out.o"), "{files:?}");
+}
+
+#[test]
+#[serial]
+fn untracked_only_repo_is_backed_up_by_default() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(&tmp, &repo.dir, WatchConfig::new());
+    repo.write_file("untracked.txt");
+
+    assert!(snapshots::capture(repo.dir.as_path()).unwrap().is_some());
+}
+
+#[test]
+#[serial]
+fn untracked_only_repo_is_skipped_without_backup_untracked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(
+        &tmp,
+        &repo.dir,
+        WatchConfig::builder().backup_untracked(false).build(),
+    );
+    repo.write_file("untracked.txt");
+
+    assert_eq!(snapshots::capture(repo.dir.as_path()).unwrap(), None);
+}
+
+#[test]
+#[serial]
+fn tracked_changes_leave_untracked_files_out_without_backup_untracked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(
+        &tmp,
+        &repo.dir,
+        WatchConfig::builder().backup_untracked(false).build(),
+    );
+    repo.write_file("untracked.txt");
+    repo.change_file("foo.txt");
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let files = repo
+        .git(&["ls-tree", "--name-only", &status.commit_hash])
+        .unwrap();
+    let files: Vec<&str> = files.lines().collect();
+    assert_eq!(files, vec!["foo.txt"]);
+}
+
+#[test]
+#[serial]
+fn backup_leaves_staging_area_and_worktree_alone() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    watch_with(&tmp, &repo.dir, WatchConfig::new());
+    repo.write_file("staged.txt");
+    repo.git(&["add", "staged.txt"]).unwrap();
+    repo.change_file("foo.txt");
+    repo.write_file("untracked.txt");
+    let status_before = repo.git(&["status", "--porcelain"]).unwrap();
+    let index_before = fs::read(repo.dir.join(".git").join("index")).unwrap();
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    let files = repo
+        .git(&["ls-tree", "--name-only", &status.commit_hash])
+        .unwrap();
+    let files: Vec<&str> = files.lines().collect();
+    for file in ["foo.txt", "staged.txt", "untracked.txt"] {
+        assert!(files.contains(&file), "{files:?}");
+    }
+    assert_eq!(
+        repo.git(&["diff", "--cached", "--name-only"]).unwrap(),
+        "staged.txt\n"
+    );
+    assert_eq!(repo.git(&["status", "--porcelain"]).unwrap(), status_before);
+    assert_eq!(
+        fs::read(repo.dir.join(".git").join("index")).unwrap(),
+        index_before
+    );
+}
+
+#[test]
+#[serial]
+fn capture_with_uses_the_injected_config_only() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let config_home = tempfile::tempdir().unwrap();
+    env::set_var("DURA_CONFIG_HOME", config_home.path());
+    let mut on_disk = Config::empty();
+    on_disk.commit_author = Some("from-disk".to_string());
+    on_disk.save();
+
+    let mut injected = Config::empty();
+    injected.commit_author = Some("injected".to_string());
+    injected.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().backup_untracked(false).build()),
+    );
+    repo.write_file("untracked.txt");
+    assert_eq!(
+        snapshots::capture_with(&injected, repo.dir.as_path()).unwrap(),
+        None
+    );
+
+    repo.change_file("foo.txt");
+    let status = snapshots::capture_with(&injected, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+
+    let author = repo.git(&["show", "-s", "--format=format:%an", &status.commit_hash]);
+    assert_eq!(author.as_deref(), Some("injected"));
+}
+
+#[test]
+fn mirrored_backups_only_land_in_the_mirror() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let vault = tempfile::tempdir().unwrap();
+    let mirror_path = vault.path().join("vault.git");
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().mirror_path(&mirror_path).build()),
+    );
+
+    repo.change_file("foo.txt");
+    let first = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    repo.change_file("foo.txt");
+    let second = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(repo.git(&["branch", "--list", "dura/*"]).unwrap(), "");
+    let mirror = git2::Repository::open_bare(&mirror_path).unwrap();
+    let tip = mirror
+        .find_branch(&second.dura_branch, git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(tip.id().to_string(), second.commit_hash);
+    assert_eq!(tip.parent_id(0).unwrap().to_string(), first.commit_hash);
+    let backed_up = tip.tree().unwrap().get_name("foo.txt").unwrap().id();
+    let blob = mirror.find_blob(backed_up).unwrap();
+    assert_eq!(
+        blob.content(),
+        fs::read(repo.dir.join("foo.txt")).unwrap().as_slice()
+    );
+}
+
+fn backs_up_ignored_env(include_overrides_gitignore: bool) -> bool {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    fs::write(repo.dir.join(".gitignore"), ".env\n").unwrap();
+    repo.commit_all();
+    fs::write(repo.dir.join(".env"), "SECRET=1\n").unwrap();
+    repo.change_file("foo.txt");
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(
+            WatchConfig::builder()
+                .include([".env"])
+                .include_overrides_gitignore(include_overrides_gitignore)
+                .build(),
+        ),
+    );
+
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+
+    let files = repo
+        .git(&["ls-tree", "--name-only", &status.commit_hash])
+        .unwrap();
+    files.lines().any(|file| file == ".env")
+}
+
+#[test]
+fn included_ignored_files_are_backed_up_only_when_overriding_gitignore() {
+    assert!(backs_up_ignored_env(true));
+    assert!(!backs_up_ignored_env(false));
+}
+
+fn identity_config(author: Option<&str>, email: Option<&str>, exclude_git_config: bool) -> Config {
+    let mut config = Config::empty();
+    config.commit_author = author.map(str::to_string);
+    config.commit_email = email.map(str::to_string);
+    config.commit_exclude_git_config = exclude_git_config;
+    config
+}
+
+#[test]
+fn identity_completes_a_partial_config_from_git() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let repo = git2::Repository::open(&repo.dir).unwrap();
+    let resolve = |author, email| {
+        snapshots::resolve_identity(&identity_config(author, email, false), &repo).unwrap()
+    };
+
+    assert_eq!(
+        resolve(Some("me"), Some("me@example.com")),
+        ("me".to_string(), "me@example.com".to_string())
+    );
+    assert_eq!(
+        resolve(Some("me"), None),
+        ("me".to_string(), "duratest@dura.io".to_string())
+    );
+    assert_eq!(
+        resolve(None, Some("me@example.com")),
+        ("duratest".to_string(), "me@example.com".to_string())
+    );
+    assert_eq!(
+        resolve(None, None),
+        ("duratest".to_string(), "duratest@dura.io".to_string())
+    );
+}
+
+#[test]
+fn identity_without_git_config() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = repo_and_file!(tmp, "foo.txt");
+    let repo = git2::Repository::open(&repo.dir).unwrap();
+    let resolve =
+        |author, email| snapshots::resolve_identity(&identity_config(author, email, true), &repo);
+
+    assert_eq!(
+        resolve(Some("me"), Some("me@example.com")).unwrap(),
+        ("me".to_string(), "me@example.com".to_string())
+    );
+    let author_only = resolve(Some("me"), None).unwrap_err();
+    assert!(author_only.message().contains("commit_email"));
+    let email_only = resolve(None, Some("me@example.com")).unwrap_err();
+    assert!(email_only.message().contains("commit_author"));
+    assert_eq!(
+        resolve(None, None).unwrap(),
+        ("dura".to_string(), "dura@github.io".to_string())
+    );
+}
+
+#[test]
+fn partial_identity_fails_the_backup_without_writing_a_branch() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let config = identity_config(Some("me"), None, true);
+
+    assert!(snapshots::capture_with(&config, &repo.dir).is_err());
+    let git = git2::Repository::open(&repo.dir).unwrap();
+    assert!(git.branches(None).unwrap().all(|branch| !branch
+        .unwrap()
+        .0
+        .name()
+        .unwrap()
+        .unwrap()
+        .starts_with("dura/")));
+}
+
+#[test]
+fn max_backups_caps_the_dura_branches() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut config = Config::empty();
+    config.write_backup_notes = true;
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().max_backups(3).build()),
+    );
+    let git = git2::Repository::open(&repo.dir).unwrap();
+    let dura_backups = || -> Vec<dura::backups::Backup> {
+        dura::backups::find_backups(&git)
+            .unwrap()
+            .into_iter()
+            .filter(|backup| backup.ref_name.starts_with("dura/"))
+            .collect()
+    };
+
+    let mut status = None;
+    for round in 0..7 {
+        if round == 5 {
+            // the next backups go to a new branch, the old one is trimmed
+            repo.commit_all();
+        }
+        repo.change_file("foo.txt");
+        status = snapshots::capture_with(&config, &repo.dir).unwrap();
+        assert!(dura_backups().len() <= 3, "round {round}");
+    }
+
+    let status = status.unwrap();
+    let backups = dura_backups();
+    assert_eq!(backups.len(), 3);
+    let tip = git
+        .find_branch(&status.dura_branch, git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .target()
+        .unwrap();
+    assert_eq!(tip.to_string(), status.commit_hash);
+    assert!(backups.iter().any(|backup| backup.oid == tip));
+    for backup in &backups {
+        assert!(dura::backups::backup_note(&git, backup.oid).is_some());
+    }
+}
+
+#[test]
+fn zero_max_backups_keeps_them_all() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().max_backups(0).build()),
+    );
+    let git = git2::Repository::open(&repo.dir).unwrap();
+
+    for _ in 0..3 {
+        repo.change_file("foo.txt");
+        let status = snapshots::capture_with(&config, &repo.dir)
+            .unwrap()
+            .unwrap();
+        assert!(git
+            .find_branch(&status.dura_branch, git2::BranchType::Local)
+            .is_ok());
+    }
+
+    let backups = dura::backups::find_backups(&git).unwrap();
+    assert_eq!(backups.len(), 3);
+}
+
+#[test]
+fn older_backups_are_thinned_to_one_per_day() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut thinning = Config::empty();
+    thinning.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().thin_to_daily_after_days(1).build()),
+    );
+    let at = |days_ago: i64, hour: i64| {
+        let midnight =
+            dura::backups::local_midnight(Local::now() - chrono::Duration::days(days_ago));
+        SystemTime::UNIX_EPOCH + Duration::from_secs((midnight + hour * 3600) as u64)
+    };
+    let mut backup_at = |time| {
+        repo.change_file("foo.txt");
+        snapshots::capture_with_clock(&Config::empty(), &repo.dir, &FixedClock(time))
+            .unwrap()
+            .unwrap()
+    };
+    backup_at(at(3, 9));
+    backup_at(at(3, 10));
+    backup_at(at(2, 9));
+    backup_at(at(2, 10));
+
+    repo.change_file("foo.txt");
+    let newest = snapshots::capture_with(&thinning, &repo.dir)
+        .unwrap()
+        .unwrap();
+
+    let git = git2::Repository::open(&repo.dir).unwrap();
+    let mut times: Vec<i64> = dura::backups::find_backups(&git)
+        .unwrap()
+        .into_iter()
+        .map(|backup| backup.time)
+        .collect();
+    times.sort();
+    let secs = |time: SystemTime| {
+        time.duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64
+    };
+    let newest_time = git
+        .find_commit(git2::Oid::from_str(&newest.commit_hash).unwrap())
+        .unwrap()
+        .time()
+        .seconds();
+    assert_eq!(times, vec![secs(at(3, 9)), secs(at(2, 9)), newest_time]);
+}
+
+#[test]
+fn only_branches_limits_where_backups_are_made() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(WatchConfig::builder().only_branches(["feature/*"]).build()),
+    );
+    repo.change_file("foo.txt");
+
+    assert_eq!(
+        snapshots::capture_with(&config, repo.dir.as_path()).unwrap(),
+        None
+    );
+    assert!(!repo
+        .git(&["branch", "--list", "dura/*"])
+        .unwrap_or_default()
+        .contains("dura/"));
+
+    repo.git(&["checkout", "-q", "-b", "feature/login"])
+        .unwrap();
+    let git_repo = git2::Repository::open(repo.dir.as_path()).unwrap();
+    assert_eq!(
+        snapshots::current_branch(&git_repo).as_deref(),
+        Some("feature/login")
+    );
+    assert!(config.backs_up_current_branch(&repo.dir, &git_repo));
+
+    assert!(snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .is_some());
+}
+
+#[test]
+fn only_branches_matches_globs() {
+    let watch_config = WatchConfig::builder()
+        .only_branches(["feature/*", "wip"])
+        .build();
+
+    assert!(watch_config.backs_up_branch(Some("feature/login")));
+    assert!(watch_config.backs_up_branch(Some("wip")));
+    assert!(!watch_config.backs_up_branch(Some("main")));
+    assert!(!watch_config.backs_up_branch(Some("feature/a/b")));
+    // detached HEAD
+    assert!(!watch_config.backs_up_branch(None));
+    assert!(WatchConfig::new().backs_up_branch(None));
+}
+
+#[test]
+fn stash_strategy_adds_backups_to_the_stash() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let mut config = Config::empty();
+    config.backup_strategy = BackupStrategy::Stash;
+    repo.change_file("foo.txt");
+    let changed = fs::read_to_string(repo.dir.join("foo.txt")).unwrap();
+
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(status.dura_branch, "stash@{0}");
+    let git_repo = git2::Repository::open(repo.dir.as_path()).unwrap();
+    let branch = snapshots::current_branch(&git_repo).unwrap();
+    assert_eq!(
+        repo.git(&["stash", "list"]).unwrap().trim_end(),
+        format!("stash@{{0}}: On {branch}: dura auto-backup")
+    );
+    // the working tree keeps the change, and git can restore it from the stash
+    assert_eq!(
+        fs::read_to_string(repo.dir.join("foo.txt")).unwrap(),
+        changed
+    );
+    repo.git(&["checkout", "--", "foo.txt"]).unwrap();
+    repo.git(&["stash", "apply", "-q"]).unwrap();
+    assert_eq!(
+        fs::read_to_string(repo.dir.join("foo.txt")).unwrap(),
+        changed
+    );
+    assert!(repo
+        .git(&["branch", "--list", "dura/*"])
+        .unwrap_or_default()
+        .is_empty());
+    // nothing new to stash
+    assert_eq!(
+        snapshots::capture_with(&config, repo.dir.as_path()).unwrap(),
+        None
+    );
+
+    repo.change_file("foo.txt");
+    snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    assert_eq!(config.count_backups(&git_repo).count, 2);
+    assert_eq!(
+        config
+            .count_backups_by_branch(&git_repo)
+            .keys()
+            .collect::<Vec<_>>(),
+        ["stash@{0}", "stash@{1}"]
+    );
+    assert_eq!(Config::empty().count_backups(&git_repo).count, 0);
+}
+
+#[test]
+fn backups_are_pushed_to_every_remote() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    let remotes = tempfile::tempdir().unwrap();
+    let github = remotes.path().join("github.git");
+    let mirror = remotes.path().join("mirror.git");
+    git2::Repository::init_bare(&github).unwrap();
+    git2::Repository::init_bare(&mirror).unwrap();
+    repo.git(&["remote", "add", "github", github.to_str().unwrap()])
+        .unwrap();
+    let missing = remotes.path().join("missing.git");
+    let watch_config = WatchConfig::builder()
+        .push_remote(missing.to_str().unwrap())
+        .push_remote("github")
+        .push_remote(mirror.to_str().unwrap())
+        .build();
+    let mut config = Config::empty();
+    config.repos.insert(
+        repo.dir.to_str().unwrap().to_string(),
+        Rc::new(watch_config),
+    );
+
+    repo.change_file("foo.txt");
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+    assert!(status.pushes.is_empty());
+    let pushes = snapshots::push_backup(&config, repo.dir.as_path(), &status);
+
+    let failed: Vec<bool> = pushes.iter().map(|p| p.error.is_some()).collect();
+    assert_eq!(failed, vec![true, false, false]);
+    for remote in [&github, &mirror] {
+        let remote = git2::Repository::open_bare(remote).unwrap();
+        let tip = remote
+            .find_branch(&status.dura_branch, git2::BranchType::Local)
+            .unwrap()
+            .get()
+            .target()
+            .unwrap();
+        assert_eq!(tip.to_string(), status.commit_hash);
+    }
+}
+
+#[test]
+fn locked_index_skips_the_backup_until_released() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let lock = repo.dir.join(".git/index.lock");
+    fs::write(&lock, "").unwrap();
+
+    assert_eq!(
+        snapshots::capture_with(&Config::empty(), repo.dir.as_path()).unwrap(),
+        None
+    );
+
+    fs::remove_file(&lock).unwrap();
+    assert!(
+        snapshots::capture_with(&Config::empty(), repo.dir.as_path())
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[test]
+fn backups_are_dated_by_the_injected_clock() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+
+    let status =
+        snapshots::capture_with_clock(&Config::empty(), repo.dir.as_path(), &FixedClock(time))
+            .unwrap()
+            .unwrap();
+
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+    let commit = git_repo
+        .find_commit(git2::Oid::from_str(&status.commit_hash).unwrap())
+        .unwrap();
+    assert_eq!(commit.time().seconds(), 1_600_000_000);
+    assert_eq!(commit.committer().when().seconds(), 1_600_000_000);
+}
+
+#[test]
+#[serial]
+fn dura_saving_its_own_config_is_not_backed_up() {
+    let tmp = tempfile::tempdir().unwrap();
+    let cache = tempfile::tempdir().unwrap();
+    let repo = util::git_repo::GitRepo::new(tmp.path().to_path_buf());
+    repo.init();
+    env::set_var("DURA_CONFIG_HOME", tmp.path().join("dura"));
+    env::set_var("DURA_CACHE_HOME", cache.path());
+    let mut config = Config::empty();
+    config.save();
+    repo.write_file("foo.txt");
+    repo.commit_all();
+
+    config.write_backup_notes = true;
+    config.save();
+    let after_own_save = snapshots::capture_with(&config, repo.dir.as_path()).unwrap();
+
+    let config_file = Config::default_path();
+    let mut edited = fs::read_to_string(&config_file).unwrap();
+    edited.push_str("# edited by hand\n");
+    fs::write(&config_file, edited).unwrap();
+    let after_user_edit = snapshots::capture_with(&config, repo.dir.as_path()).unwrap();
+
+    env::remove_var("DURA_CONFIG_HOME");
+    env::remove_var("DURA_CACHE_HOME");
+    assert_eq!(after_own_save, None);
+    assert!(after_user_edit.is_some());
+}
+
+#[test]
+#[serial]
+fn dura_cache_is_left_out_of_backups() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    env::set_var("DURA_CACHE_HOME", repo.dir.join("cache"));
+    RuntimeLock::empty().save();
+    repo.change_file("foo.txt");
+
+    let status = snapshots::capture(repo.dir.as_path()).unwrap().unwrap();
+
+    env::remove_var("DURA_CACHE_HOME");
+    let git = git2::Repository::open(&repo.dir).unwrap();
+    let tree = git
+        .find_commit(git2::Oid::from_str(&status.commit_hash).unwrap())
+        .unwrap()
+        .tree()
+        .unwrap();
+    assert!(tree.get_name("foo.txt").is_some());
+    assert!(tree.get_name("cache").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn repos_with_a_read_only_git_dir_are_not_backed_up() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    repo.change_file("foo.txt");
+    let git_dir = repo.dir.join(".git");
+    if !util::make_read_only(&git_dir) {
+        return;
+    }
+
+    let status = snapshots::capture_with(&Config::empty(), repo.dir.as_path());
+
+    fs::set_permissions(&git_dir, fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(status.unwrap(), None);
+    assert!(!repo
+        .git(&["branch", "--list", "dura/*"])
+        .unwrap_or_default()
+        .contains("dura/"));
+}
+
+#[test]
+fn broken_head_is_not_unborn_and_not_backed_up() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    fs::write(repo.dir.join(".git/HEAD"), "ref: refs/heads/deleted\n").unwrap();
+    repo.change_file("foo.txt");
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+
+    assert_eq!(
+        snapshots::broken_head(&git_repo).as_deref(),
+        Some("refs/heads/deleted")
+    );
+    assert!(!snapshots::is_unborn(&git_repo));
+    let mut config = Config::empty();
+    config.backup_unborn_repos = true;
+    assert_eq!(
+        snapshots::capture_with(&config, repo.dir.as_path()).unwrap(),
+        None
+    );
+
+    let fresh_dir = tempfile::tempdir().unwrap();
+    let fresh = git2::Repository::init(fresh_dir.path()).unwrap();
+    assert_eq!(snapshots::broken_head(&fresh), None);
+    assert!(snapshots::is_unborn(&fresh));
+}
+
+#[test]
+fn broken_head_is_backed_up_when_configured() {
+    let tmp = tempfile::tempdir().unwrap();
+    let mut repo = repo_and_file!(tmp, "foo.txt");
+    fs::write(repo.dir.join(".git/HEAD"), "ref: refs/heads/deleted\n").unwrap();
+    repo.change_file("foo.txt");
+    let mut config = Config::empty();
+    config.backup_broken_head_repos = true;
+
+    let status = snapshots::capture_with(&config, repo.dir.as_path())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(status.dura_branch, snapshots::UNBORN_BRANCH);
+    let git_repo = git2::Repository::open(&repo.dir).unwrap();
+    assert_eq!(
+        snapshots::head_state(&git_repo),
+        HeadState::Broken("refs/heads/deleted".to_string())
+    );
+}
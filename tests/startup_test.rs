@@ -1,6 +1,5 @@
 mod util;
 
-use dura::config::Config;
 use dura::database::RuntimeLock;
 use std::fs;
 
@@ -72,7 +71,7 @@ fn start_serve_with_other_pid_in_config() {
 fn start_serve_with_invalid_json() {
     let mut dura = util::dura::Dura::new();
     let runtime_lock_path = dura.runtime_lock_path();
-    Config::create_dir(runtime_lock_path.as_path());
+    RuntimeLock::create_dir(runtime_lock_path.as_path());
     fs::write(runtime_lock_path, "{\"pid\":34725").unwrap();
 
     assert_eq!(None, dura.pid(true));
@@ -0,0 +1,113 @@
+use dura::term::{self, Color, TermEnv};
+
+fn tty() -> TermEnv {
+    TermEnv {
+        is_tty: true,
+        term: Some("xterm-256color".to_string()),
+        ..TermEnv::default()
+    }
+}
+
+#[test]
+fn tty_gets_color_and_fancy_symbols() {
+    let env = tty();
+    assert!(env.use_color());
+    assert!(env.fancy_symbols());
+}
+
+#[test]
+fn no_color_keeps_fancy_symbols() {
+    let env = TermEnv {
+        no_color: true,
+        ..tty()
+    };
+    assert!(!env.use_color());
+    assert!(env.fancy_symbols());
+}
+
+#[test]
+fn plain_text_keeps_color() {
+    let env = TermEnv {
+        plain_text: true,
+        ..tty()
+    };
+    assert!(env.use_color());
+    assert!(!env.fancy_symbols());
+}
+
+#[test]
+fn clicolor_zero_disables_color_only() {
+    let env = TermEnv {
+        clicolor: Some("0".to_string()),
+        ..tty()
+    };
+    assert!(!env.use_color());
+    assert!(env.fancy_symbols());
+}
+
+#[test]
+fn pipe_gets_neither_unless_forced() {
+    let pipe = TermEnv::default();
+    assert!(!pipe.use_color());
+    assert!(!pipe.fancy_symbols());
+
+    let forced = TermEnv {
+        clicolor_force: true,
+        fancy: true,
+        ..TermEnv::default()
+    };
+    assert!(forced.use_color());
+    assert!(forced.fancy_symbols());
+}
+
+#[test]
+fn clicolor_force_beats_no_color() {
+    let env = TermEnv {
+        no_color: true,
+        clicolor_force: true,
+        ..TermEnv::default()
+    };
+    assert!(env.use_color());
+    assert!(!env.fancy_symbols());
+}
+
+#[test]
+fn linux_console_gets_color_and_plain_symbols() {
+    let env = TermEnv {
+        term: Some("linux".to_string()),
+        ..tty()
+    };
+    assert!(env.use_color());
+    assert!(!env.fancy_symbols());
+}
+
+#[test]
+fn paint_only_when_enabled() {
+    assert_eq!(term::paint("ok", Color::Green, false), "ok");
+    assert_eq!(term::paint("ok", Color::Green, true), "\x1b[32mok\x1b[0m");
+}
+
+#[test]
+fn config_can_force_symbols_either_way() {
+    let mut config = dura::config::Config::empty();
+    let plain_env = TermEnv {
+        plain_text: true,
+        ..tty()
+    };
+    let fancy_env = TermEnv {
+        fancy: true,
+        ..TermEnv::default()
+    };
+
+    assert!(config.fancy_symbols(&tty()));
+    assert!(!config.fancy_symbols(&plain_env));
+    assert!(config.fancy_symbols(&fancy_env));
+
+    config.force_plain_symbols = Some(true);
+    assert!(!config.fancy_symbols(&tty()));
+    assert!(!config.fancy_symbols(&fancy_env));
+
+    config.force_plain_symbols = Some(false);
+    assert!(config.fancy_symbols(&TermEnv::default()));
+    assert!(config.fancy_symbols(&plain_env));
+}
@@ -0,0 +1,22 @@
+use dura::timeout::run_with_timeout;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+#[test]
+fn fast_operation_returns_its_result() {
+    let result = run_with_timeout(Duration::from_secs(5), || 42);
+
+    assert_eq!(result, Some(42));
+}
+
+#[test]
+fn slow_operation_times_out() {
+    let start = Instant::now();
+    let result = run_with_timeout(Duration::from_millis(100), || {
+        sleep(Duration::from_secs(5));
+        42
+    });
+
+    assert_eq!(result, None);
+    assert!(Instant::now() - start < Duration::from_secs(2));
+}
@@ -98,6 +98,22 @@ impl Dura {
         }
     }
 
+    /// Like `run`, but returns stdout instead of printing it so tests can assert on it.
+    pub fn run_output(&self, args: &[&str]) -> String {
+        println!("$ dura {}", args.join(" "));
+        let exe = env!("CARGO_BIN_EXE_dura").to_string();
+        let output = Command::new(exe)
+            .args(args)
+            .env("DURA_CONFIG_HOME", self.config_dir.path())
+            .env("DURA_CACHE_HOME", self.cache_dir.path())
+            .output()
+            .unwrap();
+
+        let text = String::from_utf8(output.stdout).unwrap();
+        println!("{text}");
+        text
+    }
+
     pub fn pid(&self, is_primary: bool) -> Option<u32> {
         if is_primary {
             self.primary.as_ref().map(|d| d.child.id())
@@ -118,7 +134,7 @@ impl Dura {
     }
 
     pub fn save_config(&self, cfg: &Config) {
-        cfg.save_to_path(self.config_path().as_path());
+        cfg.save_to_path(self.config_path().as_path()).unwrap();
     }
 
     pub fn runtime_lock_path(&self) -> path::PathBuf {
@@ -5,3 +5,20 @@ pub mod daemon;
 pub mod dura;
 pub mod git_repo;
 pub mod macros;
+
+/// Takes away the write permissions of `dir`. Returns false, with them given back, when that
+/// doesn't keep anyone from writing to it, e.g. when the tests run as root.
+#[cfg(unix)]
+pub fn make_read_only(dir: &std::path::Path) -> bool {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o555)).unwrap();
+    let probe = dir.join("write-probe");
+    if fs::write(&probe, "").is_ok() {
+        fs::remove_file(&probe).unwrap();
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o755)).unwrap();
+        return false;
+    }
+    true
+}
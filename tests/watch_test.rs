@@ -51,3 +51,62 @@ fn watch_dir_with_repo_nested_3_folders_deep() {
 
     assert_eq!(dura.git_repos(), tmp_set);
 }
+
+#[test]
+fn watch_with_explicit_config_path() {
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = GitRepo::new(tmp.path().join("repo"));
+    repo.init();
+    let custom = tempfile::tempdir().unwrap();
+    let custom_path = custom.path().join("custom.toml");
+    let custom_arg = custom_path.to_str().unwrap();
+
+    let dura = Dura::new();
+    dura.run(&["--config", custom_arg, "watch", repo.dir.to_str().unwrap()]);
+
+    assert!(!dura.config_path().exists());
+    let config = dura::config::Config::load_file(&custom_path).unwrap();
+    let repo_path = repo.dir.canonicalize().unwrap();
+    assert!(config.repos.contains_key(repo_path.to_str().unwrap()));
+
+    let output = dura.run_output(&["info", "--config", custom_arg]);
+    assert!(output.contains(&format!("Config: {}", custom_path.display())));
+    assert!(output.contains(&repo_path.display().to_string()));
+
+    dura.run(&[
+        "unwatch",
+        "--config",
+        custom_arg,
+        repo.dir.to_str().unwrap(),
+    ]);
+    let config = dura::config::Config::load_file(&custom_path).unwrap();
+    assert!(config.repos.is_empty());
+    assert!(!dura.config_path().exists());
+}
+
+#[test]
+fn dry_run_leaves_the_config_alone() {
+    let tmp = tempfile::tempdir().unwrap();
+    GitRepo::new(tmp.path().to_path_buf()).init();
+    let key = tmp.path().canonicalize().unwrap();
+    let key = key.to_str().unwrap();
+    let dura = Dura::new();
+
+    let output = dura.run_output(&["watch", "--dry-run", key]);
+    assert!(
+        output.contains(&format!("Would start watching {key}")),
+        "{output}"
+    );
+    assert!(dura
+        .get_config()
+        .is_none_or(|config| config.repos.is_empty()));
+
+    dura.run(&["watch", key]);
+    let before = dura.get_config().unwrap();
+    let output = dura.run_output(&["unwatch", "--dry-run", key]);
+    assert!(
+        output.contains(&format!("Would stop watching {key}")),
+        "{output}"
+    );
+    assert_eq!(dura.get_config().unwrap(), before);
+}